@@ -2,6 +2,11 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    // The bundled SDL2 libraries are only needed by the SDL frontend; builds
+    // without it (the core library, wasm, tui, winit) must not require them.
+    if env::var_os("CARGO_FEATURE_SDL").is_none() {
+        return;
+    }
     let target = env::var("TARGET").unwrap();
     if target.contains("pc-windows") {
         let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
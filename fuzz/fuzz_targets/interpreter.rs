@@ -0,0 +1,48 @@
+// Runs arbitrary ROMs for a bounded number of cycles. Faulting is fine, as
+// long as it is reported as a `Chip8Error` rather than a panic.
+//
+//     cargo +nightly fuzz run interpreter
+#![no_main]
+
+use chip8::{Quirks, CPU};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_ROM: usize = 0x1000 - 0x200;
+const MAX_CYCLES: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the quirks and the keypad pattern, so every
+    // interpreter variant gets explored.
+    let (flags, rom) = match data.split_first() {
+        Some((&flags, rom)) => (flags, &rom[..rom.len().min(MAX_ROM)]),
+        None => return,
+    };
+
+    let mut cpu = CPU::new();
+    cpu.set_seed(0);
+    cpu.quirks = Quirks {
+        shift_uses_vy: flags & 0x01 != 0,
+        load_store_increments_i: flags & 0x02 != 0,
+        load_store_increments_by_x: flags & 0x80 != 0,
+        jump_uses_vx: flags & 0x04 != 0,
+        clip_sprites: flags & 0x08 != 0,
+        vf_reset: flags & 0x10 != 0,
+        display_wait: flags & 0x40 != 0,
+        ..Quirks::default()
+    };
+    if cpu.load_bytes(rom).is_err() {
+        return;
+    }
+
+    for cycle in 0..MAX_CYCLES {
+        let keys = [flags & 0x20 != 0 && cycle % 3 == 0; 16];
+        if cpu.cycle(keys).is_err() {
+            break;
+        }
+        // Ending frames, not just ticking the timers, releases a CPU
+        // waiting for the display.
+        if (cycle + 1) % cpu.cycles_per_frame.max(1) == 0 && cpu.end_frame().is_err() {
+            break;
+        }
+    }
+});
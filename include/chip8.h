@@ -0,0 +1,56 @@
+/*
+ * C API for the chip8 interpreter; see src/ffi.rs for how to build the
+ * library. Functions returning int return 0 on success and -1 on failure.
+ * Functions filling a buffer return the number of bytes the data needs and
+ * only write it if `len` is large enough, so pass NULL first to get the
+ * size.
+ *
+ * This header is maintained by hand rather than generated, so that it can
+ * carry the C-side notes below. The handle is opaque, so there are no
+ * struct layouts to drift. A test in src/ffi.rs rebuilds each prototype
+ * from its Rust signature and fails if the header doesn't match.
+ */
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Chip8 Chip8;
+
+Chip8 *chip8_new(void);
+void chip8_free(Chip8 *chip8);
+
+int chip8_load_rom(Chip8 *chip8, const uint8_t *rom, size_t len);
+void chip8_reset(Chip8 *chip8);
+
+/* One instruction, or one 60 Hz frame of instructions plus the timers. */
+int chip8_step(Chip8 *chip8);
+int chip8_run_frame(Chip8 *chip8);
+void chip8_set_speed(Chip8 *chip8, size_t cycles_per_frame);
+
+/* Bit n is keypad key n. The second player's keypad is for CHIP-8X. */
+void chip8_set_keys(Chip8 *chip8, uint16_t keys);
+void chip8_set_keys2(Chip8 *chip8, uint16_t keys);
+
+/* One byte per pixel, row by row; 0 is off. On a MegaChip screen each
+   byte is a palette index. */
+size_t chip8_screen_width(const Chip8 *chip8);
+size_t chip8_screen_height(const Chip8 *chip8);
+size_t chip8_get_framebuffer(const Chip8 *chip8, uint8_t *out, size_t len);
+bool chip8_sound_active(const Chip8 *chip8);
+
+/* chip8_get_state returns 0 if the state can't be saved. */
+size_t chip8_get_state(const Chip8 *chip8, uint8_t *out, size_t len);
+int chip8_set_state(Chip8 *chip8, const uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CHIP8_H */
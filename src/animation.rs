@@ -0,0 +1,118 @@
+// Animated GIF capture. Frames are kept as they are while recording and
+// only rendered and encoded when the recording is saved, so capturing
+// costs little more than a copy per drawn frame.
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, ImageResult, Rgba, RgbaImage};
+
+use crate::frame::Frame;
+use crate::palette::Palette;
+
+struct Capture {
+    frame: Frame,
+    // How many emulator frames this image stays on screen.
+    frames: u32,
+}
+
+#[derive(Default)]
+pub struct GifRecorder {
+    captures: Vec<Capture>,
+}
+
+impl GifRecorder {
+    pub fn new() -> Self {
+        GifRecorder::default()
+    }
+
+    // Call once per emulator frame. Only frames that drew something are
+    // kept; the rest extend how long the previous image is shown.
+    pub fn observe(&mut self, frame: &Frame) {
+        if frame.draw || self.captures.is_empty() {
+            self.captures.push(Capture {
+                frame: frame.clone(),
+                frames: 1,
+            });
+        } else if let Some(last) = self.captures.last_mut() {
+            last.frames += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.captures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captures.is_empty()
+    }
+
+    // Writes a looping GIF sized like a window at `scale`: every image is
+    // 64x32 * `scale` pixels, with high resolution frames drawn at half the
+    // pixel size as the SDL frontend does.
+    pub fn save<P: AsRef<Path>>(&self, palette: &Palette, scale: u32, path: P) -> ImageResult<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for capture in &self.captures {
+            let delay = Delay::from_numer_denom_ms(capture.frames * 1000, 60);
+            let image = render(capture, palette, scale.max(1));
+            encoder.encode_frame(image::Frame::from_parts(image, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+}
+
+fn render(capture: &Capture, palette: &Palette, scale: u32) -> RgbaImage {
+    let (width, height) = capture.frame.screen_size();
+    let image_width = 64 * scale;
+    let image_height = 32 * scale;
+    RgbaImage::from_fn(image_width, image_height, |x, y| {
+        let row = (y * height as u32 / image_height) as usize;
+        let col = (x * width as u32 / image_width) as usize;
+        let [r, g, b] = capture.frame.color(palette, col, row);
+        Rgba([r, g, b, 255])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{Resolution, CPU};
+
+    #[test]
+    fn only_drawn_frames_are_captured() {
+        let mut cpu = CPU::new();
+        let mut recorder = GifRecorder::new();
+        let mut frame = cpu.frame();
+        for draw in [true, false, false, true, false].iter() {
+            frame.draw = *draw;
+            recorder.observe(&frame);
+        }
+        assert_eq!(recorder.len(), 2);
+        assert_eq!(recorder.captures[0].frames, 3);
+        assert_eq!(recorder.captures[1].frames, 2);
+
+        cpu.resolution = Resolution::High;
+        recorder.observe(&cpu.frame());
+        assert_eq!(recorder.len(), 2);
+    }
+
+    #[test]
+    fn writes_a_gif() {
+        let mut cpu = CPU::new();
+        let mut recorder = GifRecorder::new();
+        cpu.gfx.set(0, 0, 1);
+        cpu.draw_flag = true;
+        recorder.observe(&cpu.frame());
+        cpu.resolution = Resolution::High;
+        recorder.observe(&cpu.frame());
+
+        let path = std::env::temp_dir().join(format!("chip8-{}.gif", std::process::id()));
+        recorder.save(&Palette::default(), 2, &path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+    }
+}
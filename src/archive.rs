@@ -0,0 +1,106 @@
+// ROMs inside .zip archives, as many ROM collections are distributed. With
+// no entry named, the first ROM in the archive (a .ch8, .c8, .sc8 or .xo8
+// file) is loaded; a named
+// entry matches either its full path inside the archive or just its file
+// name, so `pong.ch8` finds `games/pong.ch8`.
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use zip::ZipArchive;
+
+use crate::error::Chip8Error;
+use crate::processor;
+
+const EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+fn error(e: zip::result::ZipError) -> Chip8Error {
+    Chip8Error::InvalidArchive(e.to_string())
+}
+
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+// Reads a ROM from `path`, looking inside it if it's a .zip. `entry` is
+// only used for archives.
+pub fn read_rom(path: &Path, entry: Option<&str>) -> Result<Vec<u8>, Chip8Error> {
+    if is_zip(path) {
+        extract(File::open(path)?, entry)
+    } else {
+        processor::read_rom(path)
+    }
+}
+
+fn wanted(name: &str, entry: Option<&str>) -> bool {
+    let path = Path::new(name);
+    match entry {
+        Some(entry) => name == entry || path.file_name().is_some_and(|f| f == entry),
+        None => path
+            .extension()
+            .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e))),
+    }
+}
+
+pub fn extract<R: Read + Seek>(reader: R, entry: Option<&str>) -> Result<Vec<u8>, Chip8Error> {
+    let mut archive = ZipArchive::new(reader).map_err(error)?;
+    for n in 0..archive.len() {
+        let mut file = archive.by_index(n).map_err(error)?;
+        if file.is_file() && wanted(file.name(), entry) {
+            let mut rom = Vec::new();
+            file.read_to_end(&mut rom)?;
+            return Ok(rom);
+        }
+    }
+    Err(Chip8Error::InvalidArchive(match entry {
+        Some(entry) => format!("no entry named {}", entry),
+        None => "no ROM in the archive".to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn archive(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        let mut cursor = zip.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn extracts_the_first_rom_or_a_named_one() {
+        let files: &[(&str, &[u8])] = &[
+            ("README.txt", b"hello"),
+            ("games/pong.ch8", &[0x12, 0x00]),
+            ("games/tetris.CH8", &[0x00, 0xE0]),
+        ];
+        assert_eq!(extract(archive(files), None).unwrap(), [0x12, 0x00]);
+        assert_eq!(
+            extract(archive(files), Some("tetris.CH8")).unwrap(),
+            [0x00, 0xE0]
+        );
+        assert_eq!(
+            extract(archive(files), Some("games/pong.ch8")).unwrap(),
+            [0x12, 0x00]
+        );
+
+        assert!(matches!(
+            extract(archive(files), Some("brix.ch8")),
+            Err(Chip8Error::InvalidArchive(_))
+        ));
+        assert!(matches!(
+            extract(archive(&[("README.txt", b"hello")]), None),
+            Err(Chip8Error::InvalidArchive(_))
+        ));
+        assert!(extract(Cursor::new(b"not a zip".to_vec()), None).is_err());
+    }
+}
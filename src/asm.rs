@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::disasm::Instruction;
+
+// Programs are assembled to run from the usual CHIP-8 load address.
+pub const ORIGIN: usize = 0x200;
+
+// The assembler accepts the same syntax the disassembler prints, so a
+// listing can be edited and reassembled. On top of that it understands:
+//
+//   name:  or  : name     define a label (Octo style for the latter)
+//   ; comment             anything after a semicolon is ignored
+//   DB 1, 2, 0b1010       data bytes
+//   DW #1234, label       data words, big-endian
+//   LD I, LONG label      XO-CHIP F000 NNNN
+//   LDHI I, label         MegaChip 01NN NNNN
+//
+// Numbers may be decimal, #hex, 0xhex or 0bbinary, and labels can appear
+// anywhere an address or value is expected.
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(u32),
+    Label(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Reg(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Pitch,
+    Long(Expr),
+    Value(Expr),
+}
+
+struct Statement<'a> {
+    line: usize,
+    text: &'a str,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+impl Statement<'_> {
+    fn size(&self) -> usize {
+        match (self.mnemonic.as_str(), &self.operands[..]) {
+            ("DB", operands) => operands.len(),
+            ("DW", operands) => operands.len() * 2,
+            ("LD", [Operand::I, Operand::Long(_)]) | ("LDHI", _) => 4,
+            ("LD", [Operand::I, Operand::Value(Expr::Number(n))]) if *n > 0xFFF => 4,
+            _ => 2,
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = ORIGIN;
+
+    for (n, raw) in source.lines().enumerate() {
+        let line = n + 1;
+        let mut text = raw.split(';').next().unwrap_or("").trim();
+
+        while let Some((name, rest)) = split_label(text) {
+            if labels.insert(name.to_string(), addr as u32).is_some() {
+                return Err(error(line, format!("label '{}' is defined twice", name)));
+            }
+            text = rest;
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands = rest
+            .split(',')
+            .map(str::trim)
+            .filter(|operand| !operand.is_empty())
+            .map(|operand| parse_operand(operand, line))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let statement = Statement {
+            line,
+            text,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands,
+        };
+        addr += statement.size();
+        statements.push(statement);
+    }
+
+    let mut rom = Vec::new();
+    for statement in &statements {
+        rom.extend(encode(statement, &labels)?);
+    }
+    Ok(rom)
+}
+
+// Strips one leading label definition, in either `name:` or `: name` form.
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = text.strip_prefix(':') {
+        let rest = rest.trim_start();
+        let (name, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        return Some((name, rest.trim()));
+    }
+    let (name, rest) = text.split_once(':')?;
+    if is_identifier(name) {
+        Some((name, rest.trim()))
+    } else {
+        None
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V')?;
+    if digit.len() == 1 {
+        u8::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn parse_operand(text: &str, line: usize) -> Result<Operand, AsmError> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "PITCH" => Operand::Pitch,
+        _ => {
+            if let Some(x) = register(&upper) {
+                Operand::Reg(x)
+            } else if let Some((x, y)) = upper
+                .split_once('-')
+                .and_then(|(x, y)| Some((register(x.trim())?, register(y.trim())?)))
+            {
+                Operand::Range(x, y)
+            } else if upper.starts_with("LONG ") {
+                Operand::Long(parse_expr(text[5..].trim(), line)?)
+            } else {
+                Operand::Value(parse_expr(text, line)?)
+            }
+        }
+    };
+    Ok(operand)
+}
+
+fn parse_expr(text: &str, line: usize) -> Result<Expr, AsmError> {
+    let number = if let Some(hex) = text.strip_prefix('#') {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        u32::from_str_radix(bin, 2)
+    } else if is_identifier(text) {
+        return Ok(Expr::Label(text.to_string()));
+    } else {
+        text.parse()
+    };
+    number
+        .map(Expr::Number)
+        .map_err(|_| error(line, format!("'{}' is not a number or label", text)))
+}
+
+fn encode(statement: &Statement, labels: &HashMap<String, u32>) -> Result<Vec<u8>, AsmError> {
+    let line = statement.line;
+    let value = |expr: &Expr, max: u32| -> Result<u32, AsmError> {
+        let n = match expr {
+            Expr::Number(n) => *n,
+            Expr::Label(name) => *labels
+                .get(name)
+                .ok_or_else(|| error(line, format!("undefined label '{}'", name)))?,
+        };
+        if n > max {
+            return Err(error(line, format!("{:#X} does not fit in {:#X}", n, max)));
+        }
+        Ok(n)
+    };
+    let nibble = |expr| value(expr, 0xF).map(|n| n as u8);
+    let byte = |expr| value(expr, 0xFF).map(|n| n as u8);
+    let addr = |expr| value(expr, 0xFFF).map(|n| n as u16);
+    let word = |expr| value(expr, 0xFFFF).map(|n| n as u16);
+
+    use Instruction::*;
+    use Operand::{Reg, Value};
+    let instruction = match (statement.mnemonic.as_str(), &statement.operands[..]) {
+        ("DB", operands) => {
+            return operands
+                .iter()
+                .map(|operand| match operand {
+                    Value(expr) => byte(expr),
+                    _ => Err(error(line, "DB expects numbers or labels".to_string())),
+                })
+                .collect();
+        }
+        ("DW", operands) => {
+            let mut bytes = Vec::new();
+            for operand in operands {
+                match operand {
+                    Value(expr) => bytes.extend_from_slice(&word(expr)?.to_be_bytes()),
+                    _ => return Err(error(line, "DW expects numbers or labels".to_string())),
+                }
+            }
+            return Ok(bytes);
+        }
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Lores,
+        ("HIGH", []) => Hires,
+        ("AUDIO", []) => Audio,
+        ("MEGAOFF", []) => MegaOff,
+        ("MEGAON", []) => MegaOn,
+        ("STOPSND", []) => StopSound,
+        ("SCD", [Value(n)]) => ScrollDown(nibble(n)?),
+        ("SCU", [Value(n)]) => ScrollUp(nibble(n)?),
+        ("JP", [Value(nnn)]) => Jump(addr(nnn)?),
+        ("JP", [Reg(0), Value(nnn)]) => JumpOffset(addr(nnn)?),
+        ("CALL", [Value(nnn)]) => Call(addr(nnn)?),
+        ("SE", [Reg(x), Value(nn)]) => SkipEqByte {
+            x: *x,
+            nn: byte(nn)?,
+        },
+        ("SE", [Reg(x), Reg(y)]) => SkipEqReg { x: *x, y: *y },
+        ("SNE", [Reg(x), Value(nn)]) => SkipNeByte {
+            x: *x,
+            nn: byte(nn)?,
+        },
+        ("SNE", [Reg(x), Reg(y)]) => SkipNeReg { x: *x, y: *y },
+        ("SAVE", [Operand::Range(x, y)]) => SaveRange { x: *x, y: *y },
+        ("LOAD", [Operand::Range(x, y)]) => LoadRange { x: *x, y: *y },
+        ("LD", [Reg(x), Value(nn)]) => LoadByte {
+            x: *x,
+            nn: byte(nn)?,
+        },
+        ("LD", [Reg(x), Reg(y)]) => Move { x: *x, y: *y },
+        ("LD", [Operand::I, Operand::Long(nnnn)]) => LoadILong(word(nnnn)?),
+        ("LD", [Operand::I, Value(nnn)]) if statement.size() == 4 => LoadILong(word(nnn)?),
+        ("LD", [Operand::I, Value(nnn)]) => LoadI(addr(nnn)?),
+        ("LD", [Reg(x), Operand::Dt]) => GetDelay(*x),
+        ("LD", [Reg(x), Operand::K]) => WaitKey(*x),
+        ("LD", [Operand::Dt, Reg(x)]) => SetDelay(*x),
+        ("LD", [Operand::St, Reg(x)]) => SetSound(*x),
+        ("LD", [Operand::F, Reg(x)]) => Font(*x),
+        ("LD", [Operand::Hf, Reg(x)]) => BigFont(*x),
+        ("LD", [Operand::B, Reg(x)]) => Bcd(*x),
+        ("LD", [Operand::Pitch, Reg(x)]) => Pitch(*x),
+        ("LD", [Operand::IndirectI, Reg(x)]) => Store(*x),
+        ("LD", [Reg(x), Operand::IndirectI]) => Restore(*x),
+        ("LD", [Operand::R, Reg(x)]) => SaveFlags(*x),
+        ("LD", [Reg(x), Operand::R]) => LoadFlags(*x),
+        ("ADD", [Reg(x), Value(nn)]) => AddByte {
+            x: *x,
+            nn: byte(nn)?,
+        },
+        ("ADD", [Reg(x), Reg(y)]) => Add { x: *x, y: *y },
+        ("ADD", [Operand::I, Reg(x)]) => AddI(*x),
+        ("OR", [Reg(x), Reg(y)]) => Or { x: *x, y: *y },
+        ("AND", [Reg(x), Reg(y)]) => And { x: *x, y: *y },
+        ("XOR", [Reg(x), Reg(y)]) => Xor { x: *x, y: *y },
+        ("SUB", [Reg(x), Reg(y)]) => Sub { x: *x, y: *y },
+        ("SUBN", [Reg(x), Reg(y)]) => SubN { x: *x, y: *y },
+        ("SHR", [Reg(x)]) => Shr { x: *x, y: *x },
+        ("SHR", [Reg(x), Reg(y)]) => Shr { x: *x, y: *y },
+        ("SHL", [Reg(x)]) => Shl { x: *x, y: *x },
+        ("SHL", [Reg(x), Reg(y)]) => Shl { x: *x, y: *y },
+        ("RND", [Reg(x), Value(nn)]) => Random {
+            x: *x,
+            nn: byte(nn)?,
+        },
+        ("DRW", [Reg(x), Reg(y), Value(n)]) => Draw {
+            x: *x,
+            y: *y,
+            n: nibble(n)?,
+        },
+        ("SKP", [Reg(x)]) => SkipKey(*x),
+        ("SKNP", [Reg(x)]) => SkipNotKey(*x),
+        ("SKP2", [Reg(x)]) => SkipKey2(*x),
+        ("SKNP2", [Reg(x)]) => SkipNotKey2(*x),
+        ("PLANE", [Value(n)]) => Plane(nibble(n)?),
+        ("LDHI", [Operand::I, Value(nnnnnn)]) => LoadIHigh(value(nnnnnn, 0xFF_FFFF)?),
+        ("LDPAL", [Value(nn)]) => LoadPalette(byte(nn)?),
+        ("SPRW", [Value(nn)]) => SpriteWidth(byte(nn)?),
+        ("SPRH", [Value(nn)]) => SpriteHeight(byte(nn)?),
+        ("ALPHA", [Value(nn)]) => Alpha(byte(nn)?),
+        ("DIGISND", [Value(n)]) => DigiSound(value(n, 1)? as u8),
+        ("BMODE", [Value(n)]) => BlendMode(nibble(n)?),
+        ("CCOL", [Value(nn)]) => CollisionColor(byte(nn)?),
+        ("SCRU", [Value(n)]) => MegaScrollUp(nibble(n)?),
+        _ => return Err(error(line, format!("cannot assemble '{}'", statement.text))),
+    };
+    Ok(instruction.encode())
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn resolves_forward_labels() {
+        let rom = assemble(
+            "start:  LD I, sprite   ; point at the data
+                     DRW V0, V1, 2
+                     JP start
+             : sprite
+                     DB 0b11110000, #90",
+        )
+        .unwrap();
+        assert_eq!(rom, vec![0xA2, 0x06, 0xD0, 0x12, 0x12, 0x00, 0xF0, 0x90]);
+    }
+
+    #[test]
+    fn long_loads_take_four_bytes() {
+        let rom = assemble("LD I, LONG end\nLD I, #1234\nend: DW #ABCD").unwrap();
+        assert_eq!(
+            rom,
+            vec![0xF0, 0x00, 0x02, 0x08, 0xF0, 0x00, 0x12, 0x34, 0xAB, 0xCD]
+        );
+    }
+
+    #[test]
+    fn reassembles_a_disassembly() {
+        let rom = [
+            0x00, 0xE0, 0x6A, 0x02, 0x8A, 0xB4, 0xF3, 0x29, 0xFA, 0x55, 0x52, 0x43, 0x00, 0xC4,
+            0xB2, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x80, 0x0F,
+        ];
+        let source: String = disassemble(&rom, ORIGIN)
+            .iter()
+            .map(|line| format!("{}\n", line.instruction))
+            .collect();
+        assert_eq!(assemble(&source).unwrap(), rom.to_vec());
+    }
+
+    #[test]
+    fn assembles_megachip_instructions() {
+        let rom = assemble("MEGAON\nLDHI I, #012345\nSPRW 0\nBMODE 4\nSCRU 2").unwrap();
+        assert_eq!(
+            rom,
+            vec![0x00, 0x11, 0x01, 0x01, 0x23, 0x45, 0x03, 0x00, 0x08, 0x04, 0x00, 0xB2]
+        );
+        let listing: Vec<String> = disassemble(&rom, ORIGIN)
+            .iter()
+            .map(|line| line.instruction.to_string())
+            .collect();
+        assert_eq!(listing[1], "LDHI I, #012345");
+    }
+
+    #[test]
+    fn reports_the_failing_line() {
+        let err = assemble("CLS\nJP nowhere").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(assemble("LD V0, 256").unwrap_err().line, 1);
+        assert_eq!(assemble("MOV V0, V1").unwrap_err().line, 1);
+    }
+}
@@ -0,0 +1,73 @@
+use chip8::frontend::Buzzer;
+use chip8::tone::{Tone, Voice};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+struct Output(Voice);
+
+impl AudioCallback for Output {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out, 1);
+    }
+}
+
+pub struct Audio {
+    device: AudioDevice<Output>,
+    pattern: ([u8; 16], u8),
+    playing: bool,
+    // The voice's count, kept from when the device was last locked.
+    underruns: u32,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &sdl2::Sdl, tone: Tone) -> Result<Self, String> {
+        let audio_subsys = sdl_context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: tone.buffer,
+        };
+
+        let device = audio_subsys.open_playback(None, &desired, |spec| {
+            Output(Voice::new(tone, spec.freq as u32))
+        })?;
+        // The device runs from here on, playing silence until a frame of
+        // sound is queued.
+        device.resume();
+
+        Ok(Audio {
+            device,
+            pattern: ([0; 16], 64),
+            playing: false,
+            underruns: 0,
+        })
+    }
+}
+
+impl Buzzer for Audio {
+    // Queues a frame of sound while `on` is set; the device is only locked
+    // when there is something to queue or a beep ends.
+    fn set_playing(&mut self, on: bool) {
+        if on {
+            let mut output = self.device.lock();
+            output.0.play_frame();
+            self.underruns = output.0.underruns();
+        } else if self.playing {
+            self.device.lock().0.stop();
+        }
+        self.playing = on;
+    }
+
+    fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    // Only locks the device when the program changed the pattern or pitch.
+    fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        if self.pattern != (pattern, pitch) {
+            self.pattern = (pattern, pitch);
+            self.device.lock().0.set_pattern(pattern, pitch);
+        }
+    }
+}
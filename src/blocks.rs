@@ -0,0 +1,284 @@
+// A cache of straight-line runs of instructions, fetched once and kept by
+// the address they start at, so hot loops don't fetch and bounds-check
+// every opcode again on every pass. A block runs up to and including the
+// first instruction that can jump, skip, wait, exit, draw or store to
+// memory.
+//
+// A block is dropped when an instruction stores into it. Tools polled every
+// frame that only sometimes write memory, like the gdb server, go through
+// `Chip8::with_cpu_mut`, which drops just the blocks whose code changed.
+// Anything else that changes memory (state loads, cheats being toggled)
+// goes through `Chip8::cpu_mut`, which clears the whole cache. Cheats and
+// scripts can write memory between any two instructions, so code they
+// touch is never cached.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::disasm::Instruction;
+use crate::error::Chip8Error;
+use crate::frame::Frame;
+use crate::headless::Halt;
+use crate::processor::CPU;
+use crate::watch;
+
+// Keeps a runaway straight line, e.g. through zeroed memory, in check.
+const MAX_BLOCK: usize = 256;
+
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: HashMap<usize, Vec<u16>>,
+}
+
+fn ends_block(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x0000 => matches!(opcode, 0x00EE | 0x00FD | 0x0100..=0x01FF),
+        0x1000 | 0x2000 | 0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xB000 | 0xD000 | 0xE000 => true,
+        0xF000 => opcode == 0xF000 || matches!(opcode & 0xFF, 0x0A | 0x33 | 0x55),
+        _ => false,
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        BlockCache::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Like `CPU::run_frame`.
+    pub fn run_frame(&mut self, cpu: &mut CPU, keypad: [bool; 16]) -> Result<Frame, Chip8Error> {
+        cpu.draw_flag = false;
+        let mut left = cpu.cycles_per_frame;
+        while left > 0 {
+            left -= self.step(cpu, left, keypad)?;
+        }
+        cpu.end_frame()?;
+        Ok(cpu.frame())
+    }
+
+    // Like `CPU::run_headless`, with the same results.
+    pub fn run_headless(
+        &mut self,
+        cpu: &mut CPU,
+        max_cycles: usize,
+        keypad: [bool; 16],
+    ) -> Result<Halt, Chip8Error> {
+        let per_frame = cpu.cycles_per_frame.max(1);
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            if cpu.halted {
+                return Ok(Halt::Exit { cycles });
+            }
+            if cpu.keypad_waiting && !keypad.contains(&true) {
+                return Ok(Halt::KeyWait { cycles });
+            }
+
+            let limit = (max_cycles - cycles).min(per_frame - cycles % per_frame);
+            let pc = cpu.pc;
+            let ran = self.step(cpu, limit, keypad)?;
+            let last = pc + (ran - 1) * 2;
+            cycles += ran;
+            if cpu.pc == last && !cpu.halted && !cpu.vblank_wait {
+                return Ok(Halt::Loop { pc: last, cycles });
+            }
+            if cycles % per_frame == 0 {
+                cpu.end_frame()?;
+            }
+        }
+        Ok(Halt::Limit { cycles: max_cycles })
+    }
+
+    // Runs up to `limit` instructions of the block at PC, or a single
+    // `CPU::cycle` where there is no block to run. Returns the number of
+    // instructions executed, at least one.
+    pub fn step(
+        &mut self,
+        cpu: &mut CPU,
+        limit: usize,
+        keypad: [bool; 16],
+    ) -> Result<usize, Chip8Error> {
+        #[cfg(feature = "lua")]
+        let scripted = cpu.script.is_some();
+        #[cfg(not(feature = "lua"))]
+        let scripted = false;
+        if scripted || cpu.halted || cpu.keypad_waiting || cpu.vblank_wait {
+            cpu.cycle(keypad)?;
+            return Ok(1);
+        }
+
+        let pc = cpu.pc;
+        if !self.blocks.contains_key(&pc) {
+            match self.fetch(cpu) {
+                Some(block) => self.blocks.insert(pc, block),
+                None => {
+                    cpu.cycle(keypad)?;
+                    return Ok(1);
+                }
+            };
+        }
+        let block = &self.blocks[&pc];
+        let count = block.len().min(limit);
+
+        cpu.keypad = keypad;
+        let mut stored = None;
+        for (n, &opcode) in block[..count].iter().enumerate() {
+            if n + 1 == block.len() {
+                stored = Instruction::decode(&opcode.to_be_bytes())
+                    .map(|instruction| watch::accesses(instruction, cpu).memory_writes)
+                    .filter(|writes| !writes.is_empty());
+            }
+            cpu.execute(opcode)?;
+        }
+        if let Some(writes) = stored {
+            self.invalidate(writes);
+        }
+        Ok(count)
+    }
+
+    // The opcodes of the block at PC, or None if it can't be cached.
+    fn fetch(&self, cpu: &CPU) -> Option<Vec<u16>> {
+        let code = cpu.addressable().get(cpu.pc..)?;
+        let mut block = Vec::new();
+        for op in code.chunks_exact(2).take(MAX_BLOCK) {
+            let opcode = (op[0] as u16) << 8 | op[1] as u16;
+            block.push(opcode);
+            if ends_block(opcode) {
+                break;
+            }
+        }
+        let range = cpu.pc..cpu.pc + block.len() * 2;
+        let poked = cpu
+            .cheats
+            .iter()
+            .flat_map(|cheat| &cheat.pokes)
+            .any(|(addr, _)| range.contains(addr));
+        // F000 NNNN and 01NN NNNN are fetched whole by the interpreter.
+        let long = matches!(block.last(), Some(0xF000 | 0x0100..=0x01FF));
+        if block.is_empty() || poked || long {
+            None
+        } else {
+            Some(block)
+        }
+    }
+
+    // Drops the blocks whose code no longer matches memory.
+    pub fn revalidate(&mut self, cpu: &CPU) {
+        let memory = cpu.addressable();
+        self.blocks.retain(|&start, block| {
+            let code = memory.get(start..start + block.len() * 2);
+            code.is_some_and(|code| {
+                code.chunks_exact(2)
+                    .zip(block.iter())
+                    .all(|(op, &opcode)| op == opcode.to_be_bytes())
+            })
+        });
+    }
+
+    // Drops every block overlapping memory that was just written.
+    fn invalidate(&mut self, written: Range<usize>) {
+        self.blocks.retain(|&start, block| {
+            start + block.len() * 2 <= written.start || written.end <= start
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu_with(program: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.set_seed(1);
+        cpu.load_bytes(program).unwrap();
+        cpu
+    }
+
+    // Counts V0 up, drawing it, and rewrites the 7001 at 204 into 7002
+    // once V0 reaches 10 using FX55, then loops.
+    const SELF_MODIFYING: &[u8] = &[
+        0x60, 0x00, // 200: V0 = 0
+        0x70, 0x01, // 202: V0 += 1
+        0xF0, 0x29, // 204: I = font(V0)
+        0xD1, 0x15, // 206: draw
+        0x30, 0x0A, // 208: skip if V0 == 10
+        0x12, 0x02, // 20A: jump 202
+        0x61, 0x70, // 20C: V1 = 70
+        0x62, 0x02, // 20E: V2 = 02
+        0xA2, 0x02, // 210: I = 202
+        0xF2, 0x55, // 212: store V0-V2 at 202: 0A 70 02
+        0x13, 0x00, // 214: jump 300
+    ];
+
+    #[test]
+    fn matches_the_interpreter() {
+        let mut rom = SELF_MODIFYING.to_vec();
+        rom.resize(0x100, 0);
+        // 300: V3 = random, V4 += V3, jump 202, which now reads 0A70 and
+        // faults as an unknown opcode.
+        rom.extend_from_slice(&[0xC3, 0xFF, 0x84, 0x34, 0x12, 0x02]);
+
+        let mut interpreted = cpu_with(&rom);
+        let interpreted_result = interpreted.run_headless(10_000, [false; 16]);
+        let mut cached = cpu_with(&rom);
+        let mut cache = BlockCache::new();
+        let cached_result = cache.run_headless(&mut cached, 10_000, [false; 16]);
+
+        assert_eq!(
+            format!("{:?}", cached_result),
+            format!("{:?}", interpreted_result)
+        );
+        assert!(matches!(
+            cached_result,
+            Err(Chip8Error::UnknownOpcode { .. })
+        ));
+        assert_eq!(cached.v, interpreted.v);
+        assert_eq!((cached.pc, cached.i), (interpreted.pc, interpreted.i));
+        assert_eq!(cached.frame().to_string(), interpreted.frame().to_string());
+    }
+
+    #[test]
+    fn halts_like_the_interpreter() {
+        let programs: [&[u8]; 3] = [
+            &[0x60, 0x05, 0x61, 0x06, 0x12, 0x04], // loop at 204
+            &[0x60, 0x05, 0x00, 0xFD],             // exit
+            &[0x60, 0x05, 0xF0, 0x0A],             // wait for a key
+        ];
+        for program in programs {
+            let mut cpu = cpu_with(program);
+            let expected = cpu.run_headless(100, [false; 16]).unwrap();
+            let mut cpu = cpu_with(program);
+            let halt = BlockCache::new().run_headless(&mut cpu, 100, [false; 16]);
+            assert_eq!(halt.unwrap(), expected);
+        }
+
+        // 7001 / 1200, as in the interpreter's limit test.
+        let mut cpu = cpu_with(&[0x70, 0x01, 0x12, 0x00]);
+        let halt = BlockCache::new().run_headless(&mut cpu, 10, [false; 16]);
+        assert_eq!(halt.unwrap(), Halt::Limit { cycles: 10 });
+        assert_eq!(cpu.v[0], 5);
+    }
+
+    #[test]
+    fn stores_drop_the_blocks_they_hit() {
+        let mut cpu = cpu_with(SELF_MODIFYING);
+        let mut cache = BlockCache::new();
+        cpu.cycles_per_frame = 6;
+        cache.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cache.len(), 3);
+
+        cache.invalidate(0x203..0x204);
+        assert_eq!(cache.len(), 2);
+        cache.invalidate(0x100..0x200);
+        assert_eq!(cache.len(), 2);
+    }
+}
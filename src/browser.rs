@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+use chip8::frame::Frame;
+use chip8::frontend::Screen;
+use chip8::palette::Palette;
+use chip8::processor::CPU;
+
+use crate::display::{Display, Overlay};
+#[cfg(feature = "image")]
+use crate::thumbnail;
+
+#[cfg(not(feature = "zip"))]
+const EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+#[cfg(feature = "zip")]
+const EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "zip"];
+const ROWS: usize = 20;
+
+// The ROMs in `dir`, sorted by file name. Unreadable directories have none.
+pub fn roms_in(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+    roms.sort_by_key(|path| path.file_name().map(|name| name.to_ascii_lowercase()));
+    roms
+}
+
+// A list of ROMs in the emulator window, picked with the arrow keys and
+// Enter, for starting the binary without arguments like a console. The
+// selected ROM's thumbnail, if it has one, shows behind the list. Returns
+// the chosen ROM, or None if the window was closed or Esc pressed.
+pub fn browse(roms: &[PathBuf], scale: u32, palette: Palette) -> Option<PathBuf> {
+    let sdl_context = sdl2::init().unwrap();
+    let mut display = Display::new(&sdl_context, scale, palette, false);
+    let mut events = sdl_context.event_pump().unwrap();
+    let blank = CPU::new().frame();
+    let mut selected = 0;
+    let mut top = 0;
+    // The thumbnail and which ROM it is for, loaded when the selection moves.
+    let mut shown: Option<(usize, Option<Frame>)> = None;
+
+    loop {
+        // Keep the selection on screen, scrolling a row at a time.
+        if selected < top {
+            top = selected;
+        } else if selected >= top + ROWS {
+            top = selected + 1 - ROWS;
+        }
+        let mut overlay = Overlay::default();
+        overlay.line(format!("{} ROMS  ENTER TO PLAY  ESC TO QUIT", roms.len()));
+        for (i, rom) in roms.iter().enumerate().skip(top).take(ROWS) {
+            let name = rom.file_stem().unwrap_or_default().to_string_lossy();
+            let marker = if i == selected { ">" } else { " " };
+            overlay.spans(vec![(format!("{} {}", marker, name), i == selected)]);
+        }
+        display.set_overlay(overlay);
+        if shown.as_ref().map(|(rom, _)| *rom) != Some(selected) {
+            shown = Some((selected, thumbnail(roms.get(selected))));
+        }
+        match &shown {
+            Some((_, Some(frame))) => display.draw(frame),
+            _ => display.draw(&blank),
+        }
+
+        let last = roms.len().saturating_sub(1);
+        match events.wait_event() {
+            Event::Quit { .. } => return None,
+            Event::KeyDown {
+                keycode: Some(key), ..
+            } => match key {
+                Keycode::Escape => return None,
+                Keycode::Return | Keycode::KpEnter => return roms.get(selected).cloned(),
+                Keycode::Up => selected = selected.saturating_sub(1),
+                Keycode::Down => selected = (selected + 1).min(last),
+                Keycode::PageUp => selected = selected.saturating_sub(ROWS),
+                Keycode::PageDown => selected = (selected + ROWS).min(last),
+                Keycode::Home => selected = 0,
+                Keycode::End => selected = last,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+// The thumbnail `rom` was given when it was first played; see
+// `thumbnail::ThumbnailCapture`.
+#[cfg(feature = "image")]
+fn thumbnail(rom: Option<&PathBuf>) -> Option<Frame> {
+    rom.and_then(|rom| thumbnail::load(rom))
+}
+
+#[cfg(not(feature = "image"))]
+fn thumbnail(_rom: Option<&PathBuf>) -> Option<Frame> {
+    None
+}
@@ -0,0 +1,166 @@
+// Sets up a CPU in one go instead of changing fields after `CPU::new`:
+//
+//   let cpu = CPU::builder().quirks(Quirks::vip()).speed(15).seed(1).build()?;
+//
+// Anything left unset keeps the `CPU::new` value. `build` checks that the
+// pieces fit together, which poking the fields directly does not.
+use alloc::format;
+
+use crate::error::Chip8Error;
+use crate::font;
+use crate::frame::CYCLES_PER_FRAME;
+use crate::processor::{BIG_FONT_ADDR, CPU, MEMORY_SIZE};
+use crate::quirks::Quirks;
+
+#[derive(Clone, Debug)]
+pub struct CpuBuilder {
+    quirks: Quirks,
+    speed: usize,
+    seed: Option<u64>,
+    start: usize,
+    memory_size: usize,
+    font: [u8; 80],
+}
+
+impl Default for CpuBuilder {
+    fn default() -> Self {
+        CpuBuilder {
+            quirks: Quirks::default(),
+            speed: CYCLES_PER_FRAME,
+            seed: None,
+            start: 0x200,
+            memory_size: MEMORY_SIZE,
+            font: font::FONT_SET,
+        }
+    }
+}
+
+impl CpuBuilder {
+    pub fn new() -> Self {
+        CpuBuilder::default()
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // Instructions executed per 60 Hz frame.
+    pub fn speed(mut self, cycles_per_frame: usize) -> Self {
+        self.speed = cycles_per_frame;
+        self
+    }
+
+    // Seeds CXNN; without one the seed is random where the platform allows.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Where ROMs load and execution begins.
+    pub fn start(mut self, addr: usize) -> Self {
+        self.start = addr;
+        self
+    }
+
+    // Bytes of addressable memory, at most 64K.
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    // The 4x5 hex digits FX29 points at, five bytes per digit from 0 to F.
+    pub fn font(mut self, font: [u8; 80]) -> Self {
+        self.font = font;
+        self
+    }
+
+    pub fn build(self) -> Result<CPU, Chip8Error> {
+        // The fonts live below the program, so it has to start past them.
+        let fonts_end = BIG_FONT_ADDR + font::BIG_FONT_SET.len();
+        if self.memory_size > MEMORY_SIZE {
+            return Err(Chip8Error::InvalidConfig(format!(
+                "memory size {:X} is over the {:X} maximum",
+                self.memory_size, MEMORY_SIZE
+            )));
+        }
+        if self.start < fonts_end || self.start >= self.memory_size {
+            return Err(Chip8Error::InvalidConfig(format!(
+                "start address {:X} is not between the fonts (up to {:X}) and the end of memory ({:X})",
+                self.start, fonts_end, self.memory_size
+            )));
+        }
+        if self.speed == 0 {
+            return Err(Chip8Error::InvalidConfig(
+                "speed must be at least 1 instruction per frame".into(),
+            ));
+        }
+
+        let mut cpu = CPU::new();
+        cpu.quirks = self.quirks;
+        cpu.cycles_per_frame = self.speed;
+        if let Some(seed) = self.seed {
+            cpu.set_seed(seed);
+        }
+        cpu.start = self.start;
+        cpu.pc = self.start;
+        cpu.memory_size = self.memory_size;
+        cpu.memory[..self.font.len()].copy_from_slice(&self.font);
+        Ok(cpu)
+    }
+}
+
+impl CPU {
+    pub fn builder() -> CpuBuilder {
+        CpuBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_a_configured_cpu() {
+        let mut font = font::FONT_SET;
+        font[0] = 0xFF;
+        let mut cpu = CPU::builder()
+            .quirks(Quirks::vip())
+            .speed(15)
+            .seed(7)
+            .start(0x600)
+            .memory_size(0x1000)
+            .font(font)
+            .build()
+            .unwrap();
+        assert_eq!(cpu.quirks, Quirks::vip());
+        assert_eq!(cpu.cycles_per_frame, 15);
+        assert_eq!((cpu.pc, cpu.start, cpu.memory_size), (0x600, 0x600, 0x1000));
+        assert_eq!(cpu.memory[0], 0xFF);
+
+        let mut seeded = CPU::new();
+        seeded.set_seed(7);
+        assert_eq!(cpu.rng, seeded.rng);
+
+        // Loading V0 and V1 from FFF reads past the 4K end.
+        cpu.load_bytes(&[0xAF, 0xFF, 0xF1, 0x65]).unwrap();
+        cpu.cycle([false; 16]).unwrap();
+        assert!(matches!(
+            cpu.cycle([false; 16]),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_settings_that_dont_fit() {
+        assert!(CPU::builder().memory_size(MEMORY_SIZE + 1).build().is_err());
+        assert!(CPU::builder().start(0x10).build().is_err());
+        assert!(CPU::builder()
+            .memory_size(0x1000)
+            .start(0x1000)
+            .build()
+            .is_err());
+        assert!(CPU::builder().speed(0).build().is_err());
+        assert_eq!(CPU::builder().build().unwrap().pc, 0x200);
+    }
+}
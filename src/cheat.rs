@@ -0,0 +1,136 @@
+// Cheats that freeze memory: each one is a set of bytes written back
+// after every instruction, so the game can never change them (infinite
+// lives, a maxed-out score). They are read from a cheat file, by default
+// <ROM>.cheats next to the ROM, one cheat per line:
+//
+//   <name> = <addr>:<value>[, <addr>:<value>...]
+//
+// e.g. `Infinite lives = 3A0:03` or `Max score = 3B0:99, 3B1:99`, with the
+// address and value in hex. Blank lines and lines starting with # are
+// skipped. Every cheat starts enabled.
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub pokes: Vec<(usize, u8)>,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, pokes) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected NAME = ADDR:VALUE", n + 1))?;
+            let pokes = pokes
+                .split(',')
+                .map(|poke| {
+                    let (addr, value) = poke.trim().split_once(':')?;
+                    let addr = addr.trim_start_matches("0x").trim_start_matches("0X");
+                    Some((
+                        usize::from_str_radix(addr, 16).ok()?,
+                        u8::from_str_radix(value, 16).ok()?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("line {}: expected hex ADDR:VALUE pairs", n + 1))?;
+            cheats.push(Cheat {
+                name: name.trim().to_string(),
+                pokes,
+                enabled: true,
+            });
+        }
+        Ok(Cheats { cheats })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Cheats::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Turns the nth cheat on or off, returning it, or None if there is no
+    // such cheat.
+    pub fn toggle(&mut self, n: usize) -> Option<&Cheat> {
+        let cheat = self.cheats.get_mut(n)?;
+        cheat.enabled = !cheat.enabled;
+        Some(cheat)
+    }
+
+    // Writes every enabled cheat's values into `memory`. Addresses past
+    // the end are ignored.
+    pub fn apply(&self, memory: &mut [u8]) {
+        let pokes = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| &cheat.pokes);
+        for &(addr, value) in pokes {
+            if let Some(byte) = memory.get_mut(addr) {
+                *byte = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CPU;
+
+    #[test]
+    fn parses_cheat_files() {
+        let cheats =
+            Cheats::parse("# Some game\n\nInfinite lives = 3A0:03\nMax score = 0x3B0:99, 3B1:ff\n")
+                .unwrap();
+        let names: Vec<&str> = cheats.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Infinite lives", "Max score"]);
+        assert_eq!(
+            cheats.iter().nth(1).unwrap().pokes,
+            [(0x3B0, 0x99), (0x3B1, 0xFF)]
+        );
+
+        assert!(Cheats::parse("lives 3A0:03").is_err());
+        assert!(Cheats::parse("lives = 3A0").is_err());
+        assert!(Cheats::parse("lives = 3A0:100").is_err());
+    }
+
+    #[test]
+    fn enabled_cheats_hold_memory_while_running() {
+        let mut cpu = CPU::new();
+        // Store 0 at 300 forever.
+        cpu.memory[0x200..0x208].copy_from_slice(&[0xA3, 0x00, 0x60, 0x00, 0xF0, 0x55, 0x12, 0x00]);
+        cpu.cheats = Cheats::parse("lives = 300:09").unwrap();
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!(cpu.memory[0x300], 9);
+
+        assert!(!cpu.cheats.toggle(0).unwrap().enabled);
+        assert!(cpu.cheats.toggle(1).is_none());
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!(cpu.memory[0x300], 0);
+    }
+}
@@ -0,0 +1,619 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sdl")]
+use chip8::config;
+use chip8::debugger::OpcodePattern;
+use chip8::frame::CYCLES_PER_FRAME;
+use chip8::megachip::MEGA_CYCLES_PER_FRAME;
+use chip8::palette::{self, Palette};
+use chip8::quirks::Quirks;
+use chip8::recent::RecentRoms;
+use chip8::settings::Settings;
+use chip8::watch::Watchpoint;
+
+#[cfg(feature = "sdl")]
+use crate::browser;
+use crate::picker;
+
+pub const DEFAULT_SCALE: u32 = 20;
+
+pub const USAGE: &str = "Usage: chip8 [OPTIONS] <ROM>
+       chip8 recent
+       chip8 disasm <ROM>
+       chip8 asm <SOURCE> [-o <OUT>]
+
+Options:
+    --scale <N>         Open the window with CHIP-8 pixels N points wide, e.g.
+                        4, 8 or 16 (default 20)
+    --speed <N>         Instructions executed per 60 Hz frame (default 8, or
+                        3000 for MegaChip)
+    --megachip          Run the ROM on a MegaChip machine, as .mc8 ROMs always
+                        are
+    --palette <P>       Colours: default, green, amber, lcd or white
+    --fg <RRGGBB>       Foreground colour, replacing the palette's
+    --bg <RRGGBB>       Background colour, replacing the palette's
+    --phosphor <N>      Fade cleared pixels out over N frames, like a CRT
+    --crt               Start with scanlines, bloom and vignette on
+    --volume <PERCENT>  Buzzer volume from 1 to 100, replacing the one in
+                        tone.txt or <ROM>.tone
+    --audio-buffer <SAMPLES>
+                        Audio device buffer from 16 to 8192 samples: smaller
+                        for less delay, larger if the sound crackles
+    --quirks <P>        Start from a quirk preset: default, vip, schip, xochip
+                        or hires
+    --shift-quirk       8XY6/8XYE shift VY into VX
+    --load-store-quirk  FX55/FX65 increment I
+    --load-store-x-quirk
+                        FX55/FX65 increment I by X only, as on CHIP-48
+    --jump-quirk        BNNN jumps to XNN + VX
+    --clip-quirk        Clip sprites at the screen edges instead of wrapping
+    --vf-reset-quirk    8XY1/8XY2/8XY3 reset VF
+    --display-wait-quirk
+                        DXYN waits for the next frame, as on the VIP
+    --hires-quirk       A first instruction of 1260 switches to HiRes
+                        CHIP-8's 64x64 screen
+    --entry <NAME>      Load NAME from a .zip ROM instead of the first ROM in
+                        it (zip builds)
+    --rom-db <FILE>     Also look ROMs up in FILE to pick their quirks
+    --no-rom-db         Don't pick quirks for known ROMs automatically, from
+                        the ROM database or a CHIP-8 Archive programs.json
+    --recent <N>        Run the Nth most recently loaded ROM, as listed by
+                        `chip8 recent`
+    --rom-dir <DIR>     List the ROMs in DIR to choose from when no ROM is
+                        given (default: the roms folder in the config
+                        directory, if there is one)
+    --seed <N>          Seed the CXNN random number generator
+    --record <FILE>     Record the keypad state of every frame to FILE
+    --replay <FILE>     Play back keypad input recorded with --record
+    --break <ADDR>      Pause in the debugger when PC reaches ADDR (hex)
+    --break-op <OP>     Pause in the debugger before any instruction matching
+                        OP, where letters other than A-F are wildcards, e.g.
+                        DXYN for every draw
+    --watch <LOC>       Pause in the debugger before any instruction reading
+                        or writing LOC, a memory address (hex) or register
+                        like V3; add :r or :w to watch only reads or writes
+    --gdb <PORT>        Accept a GDB remote debugging connection on PORT
+    --remote <PORT>     Serve the JSON debugging API over WebSocket on PORT
+    --trace <FILE>      Log every executed instruction to FILE (- for stderr)
+    --profile           Report time spent per opcode and address on exit
+    --script <FILE>     Run the Lua script FILE alongside the ROM (lua builds)
+    --jit               Compile hot code to native code (experimental; jit
+                        builds)
+    --run-in-background Keep running while the window is not focused, rather
+                        than pausing until it is
+    --vsync             Pace frames by the display's refresh instead of a
+                        timer, for smoother scrolling
+    --tui               Draw in the terminal instead of a window
+    --headless          Run without a window and print the final screen
+    --cycles <N>        Instruction limit for --headless (default 1000000)
+    --load-mem <ADDR>:<FILE>
+                        Copy FILE into memory at ADDR (hex) after loading
+                        the ROM
+    --dump <ADDR>:<LEN>:<FILE>
+                        Write LEN bytes of memory from ADDR (both hex) to
+                        FILE when the emulator exits
+    --cheats <FILE>     Read cheats from FILE instead of <ROM>.cheats
+    --keymap <FILE>     Read key bindings from FILE, one KEY=SCANCODE per line;
+                        <ROM>.keymap is read too if it exists
+    --key <K>=<NAME>    Bind CHIP-8 key K (0-F) to an SDL scancode, e.g. 5=Up,
+                        or to a controller button, e.g. 5=pad:b; prefix it
+                        with 2: for the second player's keypad, e.g.
+                        2:5=Keypad 5
+    -h, --help          Print this message
+
+Hotkeys:
+    P                   Pause / resume
+    M                   Mute / unmute, remembered in tone.txt
+    Backspace           Restart the ROM
+    Esc                 Quit
+    Shift+F1            Open / close the debug panel (egui builds)
+    F2                  Show / hide the memory viewer
+    PgUp / PgDn / Home  Scroll the memory viewer / follow PC again
+    F3                  Show / hide registers, timers and stack
+    Shift+F3            Show / hide frames and instructions per second and
+                        frame times
+    F4                  Slow motion (quarter speed) on / off
+    F5 / F9             Save / load state
+    F1                  Pause or resume in the debugger
+    F6                  Run one frame while paused
+    F7                  Turn the CRT effect on / off
+    F8                  Start / stop recording a GIF next to the ROM
+    F10                 Execute one instruction while paused
+    F11 / Alt+Enter     Enter / leave fullscreen
+    F12                 Save a screenshot next to the ROM
+    - / =               Lower / raise the speed
+    Tab (hold)          Rewind
+    ` (hold)            Fast-forward
+    Ctrl+1 .. Ctrl+9    Turn the first nine cheats on / off
+
+A second player, for CHIP-8X and two-player programs, has their own
+keypad: keys 1-9 on the numeric keypad by position, 0 on keypad 0, A on
+the keypad's point, B on Enter, C on / and D on *, and the second
+controller.
+
+A controller's left stick presses keys 2, 4, 6 and 8. Keymap files and
+--key change that with stick.up, stick.down, stick.left and stick.right
+set to a key, stick.deadzone from 0 to 32767 and stick.sensitivity as a
+multiplier, e.g. stick.deadzone=12000; in <ROM>.keymap they apply to that
+game only.
+
+Without a ROM, the emulator lists the ROM directory's games to pick from,
+or a file dialog asks for one if there is no ROM directory.
+
+Cheats freeze memory, one per line of the cheat file as
+NAME = ADDR:VALUE[, ADDR:VALUE...] with hex addresses and values. They all
+start on.
+
+ROMs from the CHIP-8 Archive get their title, quirks and speed from the
+archive's programs.json, found next to the ROM or in the folder above.
+
+The buzzer's frequency, volume and waveform are read from tone.txt in the
+config directory, then <ROM>.tone, one NAME = VALUE per line: frequency in
+Hz, volume in percent, waveform square, triangle or sine, muted yes or no,
+and buffer in samples.
+
+SCHIP RPL flags (FX75/FX85) are kept next to the ROM in <ROM>.rpl, except
+with --headless, --record or --replay.
+
+Breakpoints, watchpoints, the memory view position and comments are kept in
+<ROM>.debug and reloaded the next time the ROM is debugged. Lines like
+`comment 2A4 Draws the paddle` add notes shown when the debugger stops there.
+
+Quitting a game saves its state, and starting the same ROM again resumes
+from it, except with --tui and the exceptions above; Backspace then starts
+the game afresh.
+Set resume = no in settings.txt in the config directory to turn this off.
+
+settings.txt also keeps the speed, scale and CRT effect (crt yes or no) to
+start with. Changing them while playing, with the hotkeys or by resizing
+the window, writes them back when the emulator exits.";
+
+// Debugger and keymap settings only apply to the SDL frontend.
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+pub struct Options {
+    pub rom: String,
+    // The file to load when the ROM is a .zip.
+    pub entry: Option<String>,
+    pub scale: u32,
+    pub speed: usize,
+    // Set when --speed was given, which overrides a CHIP-8 Archive tickrate.
+    pub speed_chosen: bool,
+    pub megachip: bool,
+    pub palette: Palette,
+    pub phosphor: Option<u32>,
+    pub crt: bool,
+    pub volume: Option<f32>,
+    pub audio_buffer: Option<u16>,
+    pub quirks: Quirks,
+    // Set when any quirk option was given, which overrides the ROM database.
+    pub quirks_chosen: bool,
+    pub rom_db: Option<String>,
+    pub use_rom_db: bool,
+    pub seed: Option<u64>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub breakpoints: Vec<usize>,
+    pub opcode_breakpoints: Vec<OpcodePattern>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub gdb: Option<u16>,
+    pub remote: Option<u16>,
+    pub trace: Option<String>,
+    pub profile: bool,
+    pub script: Option<String>,
+    pub jit: bool,
+    pub cheats: Option<String>,
+    // (address, file) to copy into memory, and (address, length, file) to
+    // write out on exit.
+    pub memory_loads: Vec<(usize, String)>,
+    pub dumps: Vec<(usize, usize, String)>,
+    pub keymap: Option<String>,
+    pub bindings: Vec<String>,
+    pub pause_unfocused: bool,
+    pub vsync: bool,
+    pub tui: bool,
+    pub headless: bool,
+    pub cycles: usize,
+    // settings.txt, which the options above start from.
+    pub settings: Settings,
+}
+
+pub enum Command {
+    Run(Box<Options>),
+    Recent,
+    Disasm(String),
+    Asm {
+        source: String,
+        output: Option<String>,
+    },
+}
+
+pub enum Error {
+    Help,
+    Usage(String),
+    // No ROM was given and the user backed out of choosing one.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Help => write!(f, "{}", USAGE),
+            Error::Cancelled => Ok(()),
+            Error::Usage(msg) => write!(f, "{}\n\n{}", msg, USAGE),
+        }
+    }
+}
+
+impl Command {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, Error> {
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("recent") => {
+                args.next();
+                match args.next() {
+                    None => Ok(Command::Recent),
+                    Some(flag) if flag == "-h" || flag == "--help" => Err(Error::Help),
+                    Some(arg) => Err(Error::Usage(format!("Unexpected argument '{}'", arg))),
+                }
+            }
+            Some("disasm") => {
+                args.next();
+                match (args.next(), args.next()) {
+                    (Some(flag), _) if flag == "-h" || flag == "--help" => Err(Error::Help),
+                    (Some(rom), None) if !rom.starts_with('-') => Ok(Command::Disasm(rom)),
+                    _ => Err(Error::Usage(
+                        "disasm expects exactly one ROM file".to_string(),
+                    )),
+                }
+            }
+            Some("asm") => {
+                args.next();
+                let mut source = None;
+                let mut output = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-h" | "--help" => return Err(Error::Help),
+                        "-o" | "--output" => {
+                            output =
+                                Some(args.next().ok_or_else(|| {
+                                    Error::Usage(format!("{} needs a value", arg))
+                                })?)
+                        }
+                        flag if flag.starts_with('-') => {
+                            return Err(Error::Usage(format!("Unknown option '{}'", flag)));
+                        }
+                        _ if source.is_some() => {
+                            return Err(Error::Usage(format!("Unexpected argument '{}'", arg)));
+                        }
+                        _ => source = Some(arg),
+                    }
+                }
+                let source =
+                    source.ok_or_else(|| Error::Usage("No source file given".to_string()))?;
+                Ok(Command::Asm { source, output })
+            }
+            _ => Options::from_args(args).map(|options| Command::Run(Box::new(options))),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, Error> {
+        let mut rom = None;
+        let mut scale = None;
+        let mut speed = CYCLES_PER_FRAME;
+        let mut speed_chosen = false;
+        let mut megachip = false;
+        let mut palette = Palette::default();
+        let mut foreground = None;
+        let mut background = None;
+        let mut phosphor = None;
+        let mut crt = false;
+        let mut volume = None;
+        let mut audio_buffer = None;
+        let mut quirks = Quirks::default();
+        let mut quirks_chosen = false;
+        let mut entry = None;
+        let mut rom_db = None;
+        let mut use_rom_db = true;
+        let mut seed = None;
+        let mut record = None;
+        let mut replay = None;
+        let mut breakpoints = Vec::new();
+        let mut opcode_breakpoints = Vec::new();
+        let mut watchpoints = Vec::new();
+        let mut gdb = None;
+        let mut remote = None;
+        let mut trace = None;
+        let mut profile = false;
+        let mut script = None;
+        let mut jit = false;
+        let mut rom_dir = None;
+        let mut cheats = None;
+        let mut memory_loads = Vec::new();
+        let mut dumps = Vec::new();
+        let mut keymap = None;
+        let mut bindings = Vec::new();
+        let mut pause_unfocused = true;
+        let mut vsync = false;
+        let mut tui = false;
+        let mut headless = false;
+        let mut cycles = 1_000_000;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(Error::Help),
+                "--scale" => scale = Some(number(&arg, args.next())?),
+                "--speed" => {
+                    speed = number(&arg, args.next())?;
+                    speed_chosen = true;
+                }
+                "--megachip" => megachip = true,
+                "--palette" => {
+                    let name = args.next().unwrap_or_default();
+                    palette = Palette::preset(&name)
+                        .ok_or_else(|| Error::Usage(format!("Unknown palette '{}'", name)))?;
+                }
+                "--fg" => foreground = Some(color(&arg, args.next())?),
+                "--bg" => background = Some(color(&arg, args.next())?),
+                "--phosphor" => phosphor = Some(number(&arg, args.next())?),
+                "--crt" => crt = true,
+                "--volume" => {
+                    let percent: f32 = number(&arg, args.next())?;
+                    if percent > 100.0 {
+                        return Err(Error::Usage(format!(
+                            "--volume expects a percentage up to 100, got {}",
+                            percent
+                        )));
+                    }
+                    volume = Some(percent);
+                }
+                "--audio-buffer" => {
+                    let samples: u16 = number(&arg, args.next())?;
+                    if !(16..=8192).contains(&samples) {
+                        return Err(Error::Usage(format!(
+                            "--audio-buffer expects 16 to 8192 samples, got {}",
+                            samples
+                        )));
+                    }
+                    audio_buffer = Some(samples);
+                }
+                "--quirks" => {
+                    let name = args.next().unwrap_or_default();
+                    quirks = Quirks::preset(&name)
+                        .ok_or_else(|| Error::Usage(format!("Unknown quirk preset '{}'", name)))?;
+                    quirks_chosen = true;
+                }
+                "--shift-quirk"
+                | "--load-store-quirk"
+                | "--load-store-x-quirk"
+                | "--jump-quirk"
+                | "--clip-quirk"
+                | "--vf-reset-quirk"
+                | "--display-wait-quirk"
+                | "--hires-quirk" => {
+                    quirks.enable(&arg[2..arg.len() - "-quirk".len()]);
+                    quirks_chosen = true;
+                }
+                "--entry" => entry = Some(value(&arg, args.next())?),
+                "--rom-db" => rom_db = Some(value(&arg, args.next())?),
+                "--no-rom-db" => use_rom_db = false,
+                "--recent" if rom.is_some() => {
+                    return Err(Error::Usage(
+                        "--recent replaces the ROM argument".to_string(),
+                    ));
+                }
+                "--recent" => {
+                    let n = number(&arg, args.next())?;
+                    let recent = RecentRoms::load()
+                        .get(n)
+                        .map(|path| path.display().to_string());
+                    rom = Some(recent.ok_or_else(|| {
+                        Error::Usage(format!("There is no recent ROM number {}", n))
+                    })?);
+                }
+                "--seed" => {
+                    let value = value(&arg, args.next())?;
+                    seed = Some(value.parse().map_err(|_| {
+                        Error::Usage(format!("--seed expects a number, got '{}'", value))
+                    })?);
+                }
+                "--record" => record = Some(value(&arg, args.next())?),
+                "--replay" => replay = Some(value(&arg, args.next())?),
+                "--break" => breakpoints.push(address(&arg, args.next())?),
+                "--break-op" => {
+                    let value = value(&arg, args.next())?;
+                    opcode_breakpoints.push(OpcodePattern::parse(&value).ok_or_else(|| {
+                        Error::Usage(format!(
+                            "--break-op expects an opcode like DXYN, got '{}'",
+                            value
+                        ))
+                    })?);
+                }
+                "--watch" => {
+                    let value = value(&arg, args.next())?;
+                    watchpoints.push(Watchpoint::parse(&value).ok_or_else(|| {
+                        Error::Usage(format!(
+                            "--watch expects an address or register like 3A0 or VF:w, got '{}'",
+                            value
+                        ))
+                    })?);
+                }
+                "--gdb" => gdb = Some(number(&arg, args.next())?),
+                "--remote" => remote = Some(number(&arg, args.next())?),
+                "--trace" => trace = Some(value(&arg, args.next())?),
+                "--profile" => profile = true,
+                "--script" => script = Some(value(&arg, args.next())?),
+                "--jit" => jit = true,
+                "--rom-dir" => rom_dir = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--cheats" => cheats = Some(value(&arg, args.next())?),
+                "--load-mem" => {
+                    let value = value(&arg, args.next())?;
+                    let parsed = value.split_once(':').and_then(|(addr, file)| {
+                        Some((hex(addr)?, file.to_string())).filter(|_| !file.is_empty())
+                    });
+                    memory_loads.push(parsed.ok_or_else(|| {
+                        Error::Usage(format!("--load-mem expects ADDR:FILE, got '{}'", value))
+                    })?);
+                }
+                "--dump" => {
+                    let value = value(&arg, args.next())?;
+                    let mut parts = value.splitn(3, ':');
+                    let parsed = match (parts.next(), parts.next(), parts.next()) {
+                        (Some(addr), Some(len), Some(file)) if !file.is_empty() => hex(addr)
+                            .zip(hex(len))
+                            .map(|(addr, len)| (addr, len, file.to_string())),
+                        _ => None,
+                    };
+                    dumps.push(parsed.ok_or_else(|| {
+                        Error::Usage(format!("--dump expects ADDR:LEN:FILE, got '{}'", value))
+                    })?);
+                }
+                "--keymap" => keymap = Some(value(&arg, args.next())?),
+                "--key" => bindings.push(value(&arg, args.next())?),
+                "--run-in-background" => pause_unfocused = false,
+                "--vsync" => vsync = true,
+                "--tui" => tui = true,
+                "--headless" => headless = true,
+                "--cycles" => cycles = number(&arg, args.next())?,
+                flag if flag.starts_with('-') => {
+                    return Err(Error::Usage(format!("Unknown option '{}'", flag)));
+                }
+                _ if rom.is_some() => {
+                    return Err(Error::Usage(format!("Unexpected argument '{}'", arg)));
+                }
+                _ => rom = Some(arg),
+            }
+        }
+
+        // Read only once the arguments are, so --help works whatever is in
+        // it, and a broken file doesn't stop the emulator from starting.
+        let settings = Settings::load().unwrap_or_else(|e| {
+            eprintln!("{}; using the default settings", e);
+            Settings::default()
+        });
+        let scale = scale.or(settings.scale).unwrap_or(DEFAULT_SCALE);
+        if !speed_chosen {
+            speed = settings.speed.unwrap_or(CYCLES_PER_FRAME);
+        }
+        let crt = crt || settings.crt;
+
+        let rom = match rom {
+            Some(rom) => rom,
+            None => choose_rom(rom_dir, scale, palette)?,
+        };
+        let megachip = megachip
+            || Path::new(&rom)
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("mc8"));
+        if megachip && !speed_chosen {
+            speed = MEGA_CYCLES_PER_FRAME;
+        }
+        // Colours override the preset whichever order they were given in.
+        if let Some(color) = foreground {
+            palette.set_foreground(color);
+        }
+        if let Some(color) = background {
+            palette.set_background(color);
+        }
+        Ok(Options {
+            rom,
+            entry,
+            scale,
+            speed,
+            speed_chosen,
+            megachip,
+            palette,
+            phosphor,
+            crt,
+            volume,
+            audio_buffer,
+            quirks,
+            quirks_chosen,
+            rom_db,
+            use_rom_db,
+            seed,
+            record,
+            replay,
+            breakpoints,
+            opcode_breakpoints,
+            watchpoints,
+            gdb,
+            remote,
+            trace,
+            profile,
+            script,
+            jit,
+            cheats,
+            memory_loads,
+            dumps,
+            keymap,
+            bindings,
+            pause_unfocused,
+            vsync,
+            tui,
+            headless,
+            cycles,
+            settings,
+        })
+    }
+}
+
+// Asks for a ROM when none was given: from the ROM directory's list in
+// the window if there is one, otherwise with a file dialog.
+#[cfg_attr(not(feature = "sdl"), allow(unused_variables))]
+fn choose_rom(rom_dir: Option<PathBuf>, scale: u32, palette: Palette) -> Result<String, Error> {
+    #[cfg(feature = "sdl")]
+    {
+        let rom_dir = rom_dir.or_else(|| config::config_dir().map(|dir| dir.join("roms")));
+        let roms = rom_dir.as_deref().map(browser::roms_in).unwrap_or_default();
+        if !roms.is_empty() {
+            return browser::browse(&roms, scale, palette)
+                .map(|rom| rom.display().to_string())
+                .ok_or(Error::Cancelled);
+        }
+    }
+    picker::pick_rom().ok_or_else(|| Error::Usage("No ROM file given".to_string()))
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, Error> {
+    value.ok_or_else(|| Error::Usage(format!("{} needs a value", flag)))
+}
+
+fn number<T: std::str::FromStr + PartialOrd + From<u8>>(
+    flag: &str,
+    value: Option<String>,
+) -> Result<T, Error> {
+    let value = value.ok_or_else(|| Error::Usage(format!("{} needs a value", flag)))?;
+    match value.parse::<T>() {
+        Ok(n) if n > T::from(0) => Ok(n),
+        _ => Err(Error::Usage(format!(
+            "{} expects a positive number, got '{}'",
+            flag, value
+        ))),
+    }
+}
+
+fn color(flag: &str, value: Option<String>) -> Result<[u8; 3], Error> {
+    let value = value.ok_or_else(|| Error::Usage(format!("{} needs a value", flag)))?;
+    palette::parse_color(&value).ok_or_else(|| {
+        Error::Usage(format!(
+            "{} expects a colour like FFB000, got '{}'",
+            flag, value
+        ))
+    })
+}
+
+fn address(flag: &str, value: Option<String>) -> Result<usize, Error> {
+    let value = value.ok_or_else(|| Error::Usage(format!("{} needs a value", flag)))?;
+    hex(&value)
+        .ok_or_else(|| Error::Usage(format!("{} expects a hex address, got '{}'", flag, value)))
+}
+
+fn hex(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,30 @@
+// Where per-user files live: the platform's configuration directory plus
+// `chip8`, e.g. ~/.config/chip8 on Linux. The CHIP8_CONFIG_DIR environment
+// variable overrides it, which tests and portable installs rely on.
+use std::env;
+use std::path::PathBuf;
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("CHIP8_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    platform_config_dir().map(|dir| dir.join("chip8"))
+}
+
+#[cfg(target_os = "windows")]
+fn platform_config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_config_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
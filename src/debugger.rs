@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::disasm::Instruction;
+use crate::error::Chip8Error;
+use crate::frame::Frame;
+use crate::processor::CPU;
+use crate::watch::{self, Watchpoint};
+
+// Matches opcodes written the way references list them: hex digits must
+// match and any other letter is a wildcard nibble, so "DXYN" is any draw,
+// "FX29" any font lookup and "00E0" exactly CLS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpcodePattern {
+    text: String,
+    mask: u16,
+    value: u16,
+}
+
+impl OpcodePattern {
+    pub fn parse(text: &str) -> Option<Self> {
+        if text.chars().count() != 4 || !text.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let (mut mask, mut value) = (0, 0);
+        for c in text.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xF;
+                value |= digit as u16;
+            }
+        }
+        Some(OpcodePattern {
+            text: text.to_ascii_uppercase(),
+            mask,
+            value,
+        })
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Why the debugger stopped: a breakpoint, or a watchpoint the instruction
+// at `pc` is about to read or write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub pc: usize,
+    pub watch: Option<(Watchpoint, &'static str)>,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.watch {
+            Some((watch, access)) => {
+                write!(f, "Watchpoint {} ({}) at {:03X}", watch, access, self.pc)
+            }
+            None => write!(f, "Breakpoint at {:03X}", self.pc),
+        }
+    }
+}
+
+// Wraps frame execution with pause, single-step, breakpoints on PC
+// addresses or opcodes, and watchpoints. The debugger holds no CPU state of its own, so it
+// can be attached to or detached from a running program at any point.
+#[derive(Default)]
+pub struct Debugger {
+    paused: bool,
+    pending_steps: usize,
+    pending_frame: bool,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: Vec<OpcodePattern>,
+    watchpoints: Vec<Watchpoint>,
+    // Notes on addresses, shown when the debugger stops there.
+    comments: BTreeMap<usize, String>,
+    hit: Option<Hit>,
+    // The subroutine each CPU stack entry called, as seen by the debugger;
+    // None for calls made before it was attached or across a state load.
+    calls: Vec<Option<usize>>,
+}
+
+// A subroutine on the call stack: where it starts, if known, and where it
+// will return to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    pub entry: Option<usize>,
+    pub return_to: usize,
+}
+
+// Programs that leave subroutines with a jump instead of 00EE pile up
+// frames until the 16-entry stack overflows; warn well before that.
+const DEEP_STACK: usize = 12;
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+        self.pending_frame = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    // Executes one instruction on the next frame. Only meaningful while
+    // paused; a running program is not affected.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    // Whether a frame can run without the debugger looking at every
+    // instruction: it isn't paused and has nothing to stop at.
+    pub fn is_idle(&self) -> bool {
+        !self.paused
+            && self.breakpoints.is_empty()
+            && self.opcode_breakpoints.is_empty()
+            && self.watchpoints.is_empty()
+    }
+
+    // Whether a requested step or frame has yet to run.
+    pub fn has_pending(&self) -> bool {
+        self.pending_steps > 0 || self.pending_frame
+    }
+
+    // Runs one whole frame, timers included, on the next frame. Like `step`
+    // it does nothing unless paused, and a breakpoint still cuts it short.
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.pending_frame = true;
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    // Returns true if the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, addr: usize) -> bool {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Pauses before any instruction matching `pattern`.
+    pub fn add_opcode_breakpoint(&mut self, pattern: OpcodePattern) {
+        if !self.opcode_breakpoints.contains(&pattern) {
+            self.opcode_breakpoints.push(pattern);
+        }
+    }
+
+    pub fn opcode_breakpoints(&self) -> impl Iterator<Item = &OpcodePattern> {
+        self.opcode_breakpoints.iter()
+    }
+
+    // Pauses before any instruction that reads or writes what `watch`
+    // watches.
+    pub fn add_watchpoint(&mut self, watch: Watchpoint) {
+        if !self.watchpoints.contains(&watch) {
+            self.watchpoints.push(watch);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watch: &Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watch);
+        self.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter()
+    }
+
+    // An empty comment removes the address's comment.
+    pub fn set_comment(&mut self, addr: usize, text: &str) {
+        match text.trim() {
+            "" => self.comments.remove(&addr),
+            text => self.comments.insert(addr, text.to_string()),
+        };
+    }
+
+    pub fn comment(&self, addr: usize) -> Option<&str> {
+        self.comments.get(&addr).map(String::as_str)
+    }
+
+    pub fn comments(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.comments
+            .iter()
+            .map(|(&addr, text)| (addr, text.as_str()))
+    }
+
+    fn check(&self, cpu: &CPU) -> Option<Hit> {
+        let hit = |watch| Some(Hit { pc: cpu.pc, watch });
+        if self.breakpoints.contains(&cpu.pc) {
+            return hit(None);
+        }
+        let next = cpu.memory.get(cpu.pc..).unwrap_or_default();
+        if let [hi, lo, ..] = *next {
+            let opcode = (hi as u16) << 8 | lo as u16;
+            if self.opcode_breakpoints.iter().any(|p| p.matches(opcode)) {
+                return hit(None);
+            }
+        }
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        let accesses = watch::accesses(Instruction::decode(next)?, cpu);
+        self.watchpoints
+            .iter()
+            .find_map(|&watch| accesses.hits(&watch).map(|access| (watch, access)))
+            .and_then(|watch| hit(Some(watch)))
+    }
+
+    // The breakpoint or watchpoint that paused execution during the last
+    // frame, if any.
+    pub fn take_hit(&mut self) -> Option<Hit> {
+        self.hit.take()
+    }
+
+    // Like `CPU::run_frame`, but stops before any instruction with a
+    // breakpoint on its address or opcode and runs only the requested steps or frame while paused.
+    // The timers are frozen while paused, so the buzzer is silenced too.
+    pub fn run_frame(&mut self, cpu: &mut CPU, keypad: [bool; 16]) -> Result<Frame, Chip8Error> {
+        cpu.draw_flag = false;
+        let running = !self.paused || std::mem::take(&mut self.pending_frame);
+        let cycles = if running {
+            cpu.cycles_per_frame
+        } else {
+            std::mem::take(&mut self.pending_steps)
+        };
+
+        for _ in 0..cycles {
+            let sp = cpu.sp;
+            cpu.cycle(keypad)?;
+            self.track_call(sp, cpu);
+            if let Some(hit) = self.check(cpu).filter(|_| running) {
+                self.paused = true;
+                self.hit = Some(hit);
+                break;
+            }
+        }
+        if running {
+            cpu.end_frame()?;
+        }
+
+        let mut frame = cpu.frame();
+        frame.sound &= !self.paused;
+        Ok(frame)
+    }
+
+    // Follows 2NNN and 00EE through the stack pointer, so any way of
+    // changing it is covered.
+    fn track_call(&mut self, sp: usize, cpu: &CPU) {
+        if cpu.sp > sp {
+            self.calls.resize(sp, None);
+            self.calls.push(Some(cpu.pc));
+        } else {
+            self.calls.truncate(cpu.sp);
+        }
+    }
+
+    // The CPU's stack, innermost call first.
+    pub fn call_stack(&self, cpu: &CPU) -> Vec<CallFrame> {
+        cpu.stack[..cpu.sp]
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, &return_to)| CallFrame {
+                entry: self.calls.get(depth).copied().flatten(),
+                return_to,
+            })
+            .collect()
+    }
+
+    pub fn view<'a>(&'a self, cpu: &'a CPU) -> View<'a> {
+        View {
+            debugger: self,
+            cpu,
+        }
+    }
+}
+
+// The inspection view printed when the debugger stops: registers, timers,
+// stack, the instruction about to run and the active breakpoints.
+pub struct View<'a> {
+    debugger: &'a Debugger,
+    cpu: &'a CPU,
+}
+
+impl fmt::Display for View<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.cpu)?;
+        let next = self.cpu.memory.get(self.cpu.pc..).unwrap_or_default();
+        match Instruction::decode(next) {
+            Some(instruction) => write!(f, "Next: {}", instruction)?,
+            None => write!(f, "Next: <out of memory>")?,
+        }
+        match self.debugger.comment(self.cpu.pc) {
+            Some(comment) => writeln!(f, "  ; {}", comment)?,
+            None => writeln!(f)?,
+        }
+        let calls = self.debugger.call_stack(self.cpu);
+        if !calls.is_empty() {
+            writeln!(f, "Call stack ({} deep):", calls.len())?;
+            for (n, call) in calls.iter().enumerate() {
+                match call.entry {
+                    Some(entry) => write!(f, "  #{} {:03X}", calls.len() - n, entry)?,
+                    None => write!(f, "  #{} ???", calls.len() - n)?,
+                }
+                writeln!(
+                    f,
+                    "  called from {:03X}, returns to {:03X}",
+                    call.return_to.wrapping_sub(2),
+                    call.return_to
+                )?;
+            }
+            if calls.len() >= DEEP_STACK {
+                writeln!(
+                    f,
+                    "  Warning: the stack is nearly full; a subroutine may be \
+                     leaving with a jump instead of 00EE"
+                )?;
+            }
+        }
+        write!(f, "Breakpoints:")?;
+        for addr in self.debugger.breakpoints() {
+            write!(f, " {:03X}", addr)?;
+        }
+        for pattern in self.debugger.opcode_breakpoints() {
+            write!(f, " {}", pattern)?;
+        }
+        if self.debugger.watchpoints.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\nWatchpoints:")?;
+        for watch in self.debugger.watchpoints() {
+            write!(f, " {}", watch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tight loop of 6001 / 7001 / 1200.
+    fn looping_cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x00]);
+        cpu
+    }
+
+    #[test]
+    fn breakpoint_pauses_before_the_instruction() {
+        let mut cpu = looping_cpu();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(debugger.take_hit().map(|hit| hit.pc), Some(0x204));
+        assert_eq!(cpu.pc, 0x204);
+        assert_eq!(cpu.v[0], 2);
+    }
+
+    #[test]
+    fn opcode_patterns_use_wildcard_letters() {
+        let draw = OpcodePattern::parse("dxyn").unwrap();
+        assert!(draw.matches(0xD125));
+        assert!(!draw.matches(0x6125));
+        assert_eq!(draw.to_string(), "DXYN");
+        let shift = OpcodePattern::parse("8XY6").unwrap();
+        assert!(shift.matches(0x8AB6));
+        assert!(!shift.matches(0x8AB4));
+        assert!(OpcodePattern::parse("DXY").is_none());
+        assert!(OpcodePattern::parse("D-YN").is_none());
+    }
+
+    #[test]
+    fn opcode_breakpoint_pauses_before_a_match() {
+        let mut cpu = looping_cpu();
+        let mut debugger = Debugger::new();
+        debugger.add_opcode_breakpoint(OpcodePattern::parse("1NNN").unwrap());
+
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(debugger.take_hit().map(|hit| hit.pc), Some(0x204));
+        assert_eq!(cpu.v[0], 2);
+    }
+
+    #[test]
+    fn watchpoint_pauses_before_the_access() {
+        let mut cpu = looping_cpu();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::parse("V0:w").unwrap());
+
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        let hit = debugger.take_hit().unwrap();
+        assert_eq!(hit.pc, 0x202);
+        assert_eq!(hit.to_string(), "Watchpoint V0:w (write) at 202");
+        assert_eq!(cpu.v[0], 1);
+    }
+
+    #[test]
+    fn call_stack_follows_calls_and_returns() {
+        // 2206 / 1202 / 00E0 / 220A / 00EE / 00EE: two calls deep, then out.
+        let mut cpu = CPU::new();
+        cpu.memory[0x200..0x20C].copy_from_slice(&[
+            0x22, 0x06, 0x12, 0x02, 0x00, 0xE0, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE,
+        ]);
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        debugger.step();
+        debugger.step();
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(
+            debugger.call_stack(&cpu),
+            [
+                CallFrame {
+                    entry: Some(0x20A),
+                    return_to: 0x208,
+                },
+                CallFrame {
+                    entry: Some(0x206),
+                    return_to: 0x202,
+                },
+            ]
+        );
+        let view = debugger.view(&cpu).to_string();
+        assert!(view.contains("#2 20A  called from 206, returns to 208"));
+
+        debugger.step();
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(debugger.call_stack(&cpu).len(), 1);
+        assert_eq!(debugger.call_stack(&cpu)[0].entry, Some(0x206));
+    }
+
+    #[test]
+    fn calls_made_before_attaching_are_unknown() {
+        let mut cpu = looping_cpu();
+        cpu.stack[0] = 0x2F0;
+        cpu.sp = 1;
+        let debugger = Debugger::new();
+        assert_eq!(
+            debugger.call_stack(&cpu),
+            [CallFrame {
+                entry: None,
+                return_to: 0x2F0,
+            }]
+        );
+        assert!(debugger.view(&cpu).to_string().contains("#1 ???"));
+    }
+
+    #[test]
+    fn paused_frames_only_run_requested_steps() {
+        let mut cpu = looping_cpu();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+
+        debugger.step();
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn frame_advance_runs_one_frame() {
+        let mut cpu = looping_cpu();
+        cpu.delay_timer = 5;
+        let mut debugger = Debugger::new();
+        debugger.pause();
+
+        debugger.advance_frame();
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(cpu.delay_timer, 4);
+        let pc = cpu.pc;
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, pc);
+        assert_eq!(cpu.delay_timer, 4);
+    }
+
+    #[test]
+    fn resuming_runs_past_the_breakpoint() {
+        let mut cpu = looping_cpu();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+
+        debugger.resume();
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, 0x204);
+        assert_eq!(cpu.v[0], 2);
+        assert_eq!(debugger.take_hit().map(|hit| hit.pc), Some(0x204));
+    }
+}
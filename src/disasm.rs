@@ -0,0 +1,445 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// One decoded instruction, covering CHIP-8, SCHIP, XO-CHIP and MegaChip. Mnemonics
+// follow Cowgod's reference, with `#` marking immediate values; anything
+// that does not decode is kept as data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Instruction {
+    ScrollDown(u8),
+    ScrollUp(u8),
+    Cls,
+    Ret,
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    Lores,
+    Hires,
+    // MegaChip; see `megachip`.
+    MegaOff,
+    MegaOn,
+    LoadIHigh(u32),
+    LoadPalette(u8),
+    SpriteWidth(u8),
+    SpriteHeight(u8),
+    Alpha(u8),
+    DigiSound(u8),
+    StopSound,
+    BlendMode(u8),
+    CollisionColor(u8),
+    MegaScrollUp(u8),
+    Jump(u16),
+    Call(u16),
+    SkipEqByte { x: u8, nn: u8 },
+    SkipNeByte { x: u8, nn: u8 },
+    SkipEqReg { x: u8, y: u8 },
+    SaveRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    LoadByte { x: u8, nn: u8 },
+    AddByte { x: u8, nn: u8 },
+    Move { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    Add { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    Shr { x: u8, y: u8 },
+    SubN { x: u8, y: u8 },
+    Shl { x: u8, y: u8 },
+    SkipNeReg { x: u8, y: u8 },
+    LoadI(u16),
+    JumpOffset(u16),
+    Random { x: u8, nn: u8 },
+    Draw { x: u8, y: u8, n: u8 },
+    SkipKey(u8),
+    SkipNotKey(u8),
+    // CHIP-8X's second keypad.
+    SkipKey2(u8),
+    SkipNotKey2(u8),
+    LoadILong(u16),
+    Plane(u8),
+    Audio,
+    GetDelay(u8),
+    WaitKey(u8),
+    SetDelay(u8),
+    SetSound(u8),
+    AddI(u8),
+    Font(u8),
+    BigFont(u8),
+    Bcd(u8),
+    Pitch(u8),
+    Store(u8),
+    Restore(u8),
+    SaveFlags(u8),
+    LoadFlags(u8),
+    Data(u16),
+    Byte(u8),
+}
+
+impl Instruction {
+    // Decodes the instruction at the start of `bytes`. Returns None for an
+    // empty slice.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        let opcode = match bytes {
+            [] => return None,
+            [byte] => return Some(Instruction::Byte(*byte)),
+            [hi, lo, ..] => (*hi as u16) << 8 | *lo as u16,
+        };
+
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        use Instruction::*;
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                op if op & 0xFFF0 == 0x00B0 => MegaScrollUp(n),
+                op if op & 0xFFF0 == 0x00C0 => ScrollDown(n),
+                op if op & 0xFFF0 == 0x00D0 => ScrollUp(n),
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                0x00FB => ScrollRight,
+                0x00FC => ScrollLeft,
+                0x00FD => Exit,
+                0x00FE => Lores,
+                0x00FF => Hires,
+                0x0010 => MegaOff,
+                0x0011 => MegaOn,
+                op if op & 0xFF00 == 0x0100 => match bytes {
+                    [_, _, hi, lo, ..] => {
+                        LoadIHigh((nn as u32) << 16 | (*hi as u32) << 8 | *lo as u32)
+                    }
+                    _ => Data(opcode),
+                },
+                op if op & 0xFF00 == 0x0200 => LoadPalette(nn),
+                op if op & 0xFF00 == 0x0300 => SpriteWidth(nn),
+                op if op & 0xFF00 == 0x0400 => SpriteHeight(nn),
+                op if op & 0xFF00 == 0x0500 => Alpha(nn),
+                0x0600 | 0x0601 => DigiSound(n),
+                0x0700 => StopSound,
+                op if op & 0xFFF0 == 0x0800 => BlendMode(n),
+                op if op & 0xFF00 == 0x0900 => CollisionColor(nn),
+                _ => Data(opcode),
+            },
+            0x1000 => Jump(nnn),
+            0x2000 => Call(nnn),
+            0x3000 => SkipEqByte { x, nn },
+            0x4000 => SkipNeByte { x, nn },
+            0x5000 => match n {
+                0x0 => SkipEqReg { x, y },
+                0x2 => SaveRange { x, y },
+                0x3 => LoadRange { x, y },
+                _ => Data(opcode),
+            },
+            0x6000 => LoadByte { x, nn },
+            0x7000 => AddByte { x, nn },
+            0x8000 => match n {
+                0x0 => Move { x, y },
+                0x1 => Or { x, y },
+                0x2 => And { x, y },
+                0x3 => Xor { x, y },
+                0x4 => Add { x, y },
+                0x5 => Sub { x, y },
+                0x6 => Shr { x, y },
+                0x7 => SubN { x, y },
+                0xE => Shl { x, y },
+                _ => Data(opcode),
+            },
+            0x9000 if n == 0 => SkipNeReg { x, y },
+            0xA000 => LoadI(nnn),
+            0xB000 => JumpOffset(nnn),
+            0xC000 => Random { x, nn },
+            0xD000 => Draw { x, y, n },
+            0xE000 => match nn {
+                0x9E => SkipKey(x),
+                0xA1 => SkipNotKey(x),
+                0xF2 => SkipKey2(x),
+                0xF5 => SkipNotKey2(x),
+                _ => Data(opcode),
+            },
+            0xF000 => match nn {
+                0x00 if x == 0 => match bytes {
+                    [_, _, hi, lo, ..] => LoadILong((*hi as u16) << 8 | *lo as u16),
+                    _ => Data(opcode),
+                },
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => GetDelay(x),
+                0x0A => WaitKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1E => AddI(x),
+                0x29 => Font(x),
+                0x30 => BigFont(x),
+                0x33 => Bcd(x),
+                0x3A => Pitch(x),
+                0x55 => Store(x),
+                0x65 => Restore(x),
+                0x75 => SaveFlags(x),
+                0x85 => LoadFlags(x),
+                _ => Data(opcode),
+            },
+            _ => Data(opcode),
+        };
+        Some(instruction)
+    }
+
+    // The inverse of `decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        use Instruction::*;
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |base: u16, x: u8, nn: u8| base | (x as u16) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+
+        let opcode = match *self {
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            MegaOff => 0x0010,
+            MegaOn => 0x0011,
+            LoadIHigh(nnnnnn) => {
+                let [_, high, hi, lo] = nnnnnn.to_be_bytes();
+                return vec![0x01, high, hi, lo];
+            }
+            LoadPalette(nn) => 0x0200 | nn as u16,
+            SpriteWidth(nn) => 0x0300 | nn as u16,
+            SpriteHeight(nn) => 0x0400 | nn as u16,
+            Alpha(nn) => 0x0500 | nn as u16,
+            DigiSound(n) => 0x0600 | n as u16,
+            StopSound => 0x0700,
+            BlendMode(n) => 0x0800 | n as u16,
+            CollisionColor(nn) => 0x0900 | nn as u16,
+            MegaScrollUp(n) => 0x00B0 | n as u16,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqByte { x, nn } => xnn(0x3000, x, nn),
+            SkipNeByte { x, nn } => xnn(0x4000, x, nn),
+            SkipEqReg { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LoadByte { x, nn } => xnn(0x6000, x, nn),
+            AddByte { x, nn } => xnn(0x7000, x, nn),
+            Move { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            Add { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            Shr { x, y } => xy(0x8006, x, y),
+            SubN { x, y } => xy(0x8007, x, y),
+            Shl { x, y } => xy(0x800E, x, y),
+            SkipNeReg { x, y } => xy(0x9000, x, y),
+            LoadI(nnn) => 0xA000 | nnn,
+            JumpOffset(nnn) => 0xB000 | nnn,
+            Random { x, nn } => xnn(0xC000, x, nn),
+            Draw { x, y, n } => xy(0xD000, x, y) | n as u16,
+            SkipKey(x) => xnn(0xE000, x, 0x9E),
+            SkipNotKey(x) => xnn(0xE000, x, 0xA1),
+            SkipKey2(x) => xnn(0xE000, x, 0xF2),
+            SkipNotKey2(x) => xnn(0xE000, x, 0xF5),
+            LoadILong(nnnn) => return vec![0xF0, 0x00, (nnnn >> 8) as u8, nnnn as u8],
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            GetDelay(x) => fx(x, 0x07),
+            WaitKey(x) => fx(x, 0x0A),
+            SetDelay(x) => fx(x, 0x15),
+            SetSound(x) => fx(x, 0x18),
+            AddI(x) => fx(x, 0x1E),
+            Font(x) => fx(x, 0x29),
+            BigFont(x) => fx(x, 0x30),
+            Bcd(x) => fx(x, 0x33),
+            Pitch(x) => fx(x, 0x3A),
+            Store(x) => fx(x, 0x55),
+            Restore(x) => fx(x, 0x65),
+            SaveFlags(x) => fx(x, 0x75),
+            LoadFlags(x) => fx(x, 0x85),
+            Data(word) => word,
+            Byte(byte) => return vec![byte],
+        };
+        vec![(opcode >> 8) as u8, opcode as u8]
+    }
+
+    // Size in bytes; only F000 NNNN, 01NN NNNN and a trailing odd byte
+    // differ from 2.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadILong(_) | Instruction::LoadIHigh(_) => 4,
+            Instruction::Byte(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            ScrollDown(n) => write!(f, "SCD #{:X}", n),
+            ScrollUp(n) => write!(f, "SCU #{:X}", n),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            MegaOff => write!(f, "MEGAOFF"),
+            MegaOn => write!(f, "MEGAON"),
+            LoadIHigh(nnnnnn) => write!(f, "LDHI I, #{:06X}", nnnnnn),
+            LoadPalette(nn) => write!(f, "LDPAL #{:02X}", nn),
+            SpriteWidth(nn) => write!(f, "SPRW #{:02X}", nn),
+            SpriteHeight(nn) => write!(f, "SPRH #{:02X}", nn),
+            Alpha(nn) => write!(f, "ALPHA #{:02X}", nn),
+            DigiSound(n) => write!(f, "DIGISND #{:X}", n),
+            StopSound => write!(f, "STOPSND"),
+            BlendMode(n) => write!(f, "BMODE #{:X}", n),
+            CollisionColor(nn) => write!(f, "CCOL #{:02X}", nn),
+            MegaScrollUp(n) => write!(f, "SCRU #{:X}", n),
+            Jump(nnn) => write!(f, "JP #{:03X}", nnn),
+            Call(nnn) => write!(f, "CALL #{:03X}", nnn),
+            SkipEqByte { x, nn } => write!(f, "SE V{:X}, #{:02X}", x, nn),
+            SkipNeByte { x, nn } => write!(f, "SNE V{:X}, #{:02X}", x, nn),
+            SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => write!(f, "SAVE V{:X}-V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LOAD V{:X}-V{:X}", x, y),
+            LoadByte { x, nn } => write!(f, "LD V{:X}, #{:02X}", x, nn),
+            AddByte { x, nn } => write!(f, "ADD V{:X}, #{:02X}", x, nn),
+            Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(nnn) => write!(f, "LD I, #{:03X}", nnn),
+            JumpOffset(nnn) => write!(f, "JP V0, #{:03X}", nnn),
+            Random { x, nn } => write!(f, "RND V{:X}, #{:02X}", x, nn),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, #{:X}", x, y, n),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            SkipKey2(x) => write!(f, "SKP2 V{:X}", x),
+            SkipNotKey2(x) => write!(f, "SKNP2 V{:X}", x),
+            LoadILong(nnnn) => write!(f, "LD I, LONG #{:04X}", nnnn),
+            Plane(n) => write!(f, "PLANE #{:X}", n),
+            Audio => write!(f, "AUDIO"),
+            GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Font(x) => write!(f, "LD F, V{:X}", x),
+            BigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Pitch(x) => write!(f, "LD PITCH, V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Restore(x) => write!(f, "LD V{:X}, [I]", x),
+            SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            Data(word) => write!(f, "DW #{:04X}", word),
+            Byte(byte) => write!(f, "DB #{:02X}", byte),
+        }
+    }
+}
+
+// A single line of a listing: where the instruction lives, its raw bytes and
+// what they decode to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let raw: Vec<String> = self
+            .bytes
+            .chunks(2)
+            .map(|chunk| chunk.iter().map(|b| format!("{:02X}", b)).collect())
+            .collect();
+        write!(
+            f,
+            "{:03X}  {:<9}  {}",
+            self.addr,
+            raw.join(" "),
+            self.instruction
+        )
+    }
+}
+
+// Decodes `rom` linearly as if it were loaded at `origin`. Sprite data and
+// other inline bytes come out as whatever instructions they happen to match.
+pub fn disassemble(rom: &[u8], origin: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while let Some(instruction) = Instruction::decode(&rom[offset..]) {
+        let len = instruction.size();
+        lines.push(Line {
+            addr: origin + offset,
+            bytes: rom[offset..offset + len].to_vec(),
+            instruction,
+        });
+        offset += len;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_classic_instructions() {
+        assert_eq!(Instruction::decode(&[0x00, 0xE0]), Some(Instruction::Cls));
+        assert_eq!(
+            Instruction::decode(&[0xD1, 0x25]),
+            Some(Instruction::Draw { x: 1, y: 2, n: 5 })
+        );
+        assert_eq!(
+            Instruction::decode(&[0xA2, 0x4C]).unwrap().to_string(),
+            "LD I, #24C"
+        );
+    }
+
+    #[test]
+    fn long_load_takes_four_bytes() {
+        let lines = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE], 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].instruction, Instruction::LoadILong(0x1234));
+        assert_eq!(lines[1].addr, 0x204);
+        assert_eq!(lines[1].instruction, Instruction::Ret);
+    }
+
+    #[test]
+    fn undecodable_words_become_data() {
+        let lines = disassemble(&[0x80, 0x0F, 0xAB], 0x200);
+        assert_eq!(lines[0].instruction, Instruction::Data(0x800F));
+        assert_eq!(lines[1].instruction, Instruction::Byte(0xAB));
+        assert_eq!(lines[0].to_string(), "200  800F       DW #800F");
+    }
+
+    #[test]
+    fn encode_inverts_decode() {
+        let rom = [
+            0x00, 0xE0, 0xA2, 0x2A, 0xD0, 0x15, 0xE3, 0x9E, 0xF2, 0x01, 0xF0, 0x00, 0x12, 0x34,
+            0x58, 0x92, 0xFA, 0x65,
+        ];
+        let encoded: Vec<u8> = disassemble(&rom, 0x200)
+            .iter()
+            .flat_map(|line| line.instruction.encode())
+            .collect();
+        assert_eq!(&encoded[..], &rom[..]);
+    }
+}
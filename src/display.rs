@@ -1,63 +1,453 @@
-use sdl2;
-use sdl2::pixels;
+use sdl2::pixels::{self, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{BlendMode, Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 
-const SCALE_FACTOR: u32 = 20;
-const SCREEN_WIDTH: u32 = 64 * SCALE_FACTOR;
-const SCREEN_HEIGHT: u32 = 32 * SCALE_FACTOR;
+use chip8::frame::Frame;
+use chip8::frontend::Screen;
+use chip8::megachip::{MEGA_HEIGHT, MEGA_WIDTH};
+use chip8::palette::Palette;
+use chip8::phosphor::Phosphor;
+
+// The window opens sized for low resolution at the requested scale, and can
+// then be resized or made fullscreen. The screen is drawn as large as fits
+// at 2:1, centred, with black bars filling the rest. High resolution pixels
+// are half the size, so switching resolution never changes the layout.
+const SCREEN_WIDTH: u32 = 64;
+const SCREEN_HEIGHT: u32 = 32;
+
+// The screen is drawn into a texture the size of the largest screen,
+// MegaChip's, which is then stretched over the viewport.
+const BYTES_PER_PIXEL: usize = 3;
+const PITCH: usize = MEGA_WIDTH * BYTES_PER_PIXEL;
+
+// Overlay text uses a 3x5 font in 4x6 cells, scaled up with the window.
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const CELL_WIDTH: u32 = 4;
+const CELL_HEIGHT: u32 = 6;
+const TEXT: pixels::Color = pixels::Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+const HIGHLIGHT: pixels::Color = pixels::Color {
+    r: 255,
+    g: 220,
+    b: 0,
+    a: 255,
+};
+const BACKDROP: pixels::Color = pixels::Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 200,
+};
+
+// The CRT effect darkens every third row of window pixels, lets lit pixels
+// bleed a little light into their neighbours, and shades the edges in
+// rings that overlap most in the corners, as on a curved tube.
+const SCANLINE_SPACING: u32 = 3;
+const SCANLINE: pixels::Color = pixels::Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 90,
+};
+const BLOOM_ALPHA: u8 = 40;
+const VIGNETTE_RINGS: u32 = 12;
+const VIGNETTE_ALPHA: u8 = 10;
+
+// Debug text drawn over the game, top left, on a translucent backdrop.
+// Each line is a run of spans; highlighted spans stand out in yellow.
+#[derive(Default)]
+pub struct Overlay {
+    lines: Vec<Vec<(String, bool)>>,
+}
+
+impl Overlay {
+    pub fn line(&mut self, text: impl Into<String>) {
+        self.lines.push(vec![(text.into(), false)]);
+    }
+
+    pub fn spans(&mut self, spans: Vec<(String, bool)>) {
+        self.lines.push(spans);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
 
 pub struct Display {
     canvas: Canvas<Window>,
+    palette: Palette,
+    phosphor: Option<Phosphor>,
+    crt: bool,
+    overlay: Overlay,
+    texture: Texture,
+    // What the texture holds, so unchanged rows aren't uploaded again.
+    pixels: Vec<u8>,
+    title: String,
 }
 
 impl Display {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+    // With `vsync`, presenting a frame waits for the display to refresh.
+    pub fn new(sdl_context: &sdl2::Sdl, scale: u32, palette: Palette, vsync: bool) -> Self {
         let video_subsys = sdl_context.video().unwrap();
         let window = video_subsys
-            .window(
-                "rust-sdl2_gfx: draw line & FPSManager",
-                SCREEN_WIDTH,
-                SCREEN_HEIGHT,
-            )
+            .window("chip8", SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale)
             .position_centered()
+            .resizable()
+            .allow_highdpi()
             .opengl()
             .build()
             .unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        let mut builder = window.into_canvas();
+        if vsync {
+            builder = builder.present_vsync();
+        }
+        let mut canvas = builder.build().unwrap();
 
-        canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        let [r, g, b] = palette.color(0);
+        canvas.set_draw_color(pixels::Color::RGB(r, g, b));
         canvas.clear();
         canvas.present();
 
-        Display { canvas }
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        let pixels = vec![0; PITCH * MEGA_HEIGHT];
+        let mut texture = canvas
+            .texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                MEGA_WIDTH as u32,
+                MEGA_HEIGHT as u32,
+            )
+            .unwrap();
+        let _ = texture.update(None, &pixels, PITCH);
+
+        Display {
+            canvas,
+            palette,
+            phosphor: None,
+            crt: false,
+            overlay: Overlay::default(),
+            texture,
+            pixels,
+            title: "chip8".to_string(),
+        }
+    }
+
+    // Sets the window title, leaving the window alone if it hasn't changed.
+    pub fn set_title(&mut self, title: &str) {
+        if title != self.title {
+            let _ = self.canvas.window_mut().set_title(title);
+            self.title = title.to_string();
+        }
+    }
+
+    pub fn set_phosphor(&mut self, phosphor: Option<Phosphor>) {
+        self.phosphor = phosphor;
     }
 
-    pub fn draw(&mut self, gfx: &[[u8; 64]; 32]) {
-        for (y, row) in gfx.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(e) = window.set_fullscreen(fullscreen) {
+            eprintln!("Could not change fullscreen mode: {}", e);
+        }
+    }
+
+    // Where the CHIP-8 screen goes in the window, recomputed on every draw
+    // so it follows resizes. This is in drawable pixels, which on high-DPI
+    // screens is more than the window's size in points.
+    fn viewport(&self) -> Rect {
+        let (width, height) = self.canvas.output_size().unwrap_or((0, 0));
+        let fit_width = width
+            .min(height * SCREEN_WIDTH / SCREEN_HEIGHT)
+            .max(SCREEN_WIDTH);
+        let fit_height = fit_width * SCREEN_HEIGHT / SCREEN_WIDTH;
+        Rect::new(
+            (width.saturating_sub(fit_width) / 2) as i32,
+            (height.saturating_sub(fit_height) / 2) as i32,
+            fit_width,
+            fit_height,
+        )
+    }
 
-                self.canvas.set_draw_color(color(col));
-                let _ = self.canvas.fill_rect(Rect::new(
-                    x as i32,
-                    y as i32,
-                    SCALE_FACTOR,
-                    SCALE_FACTOR,
-                ));
+    // The window's size as a --scale, or None while it is fullscreen.
+    pub fn scale(&self) -> Option<u32> {
+        let window = self.canvas.window();
+        if window.fullscreen_state() != FullscreenType::Off {
+            return None;
+        }
+        let (width, height) = window.size();
+        Some((width / SCREEN_WIDTH).min(height / SCREEN_HEIGHT).max(1))
+    }
+
+    pub fn set_crt(&mut self, crt: bool) {
+        self.crt = crt;
+    }
+
+    pub fn crt(&self) -> bool {
+        self.crt
+    }
+
+    // Call once per frame, before `draw`. Returns true while pixels are
+    // still fading out, since that needs a redraw even when the frame
+    // itself drew nothing.
+    pub fn fade(&mut self, frame: &Frame) -> bool {
+        self.phosphor
+            .as_mut()
+            .is_some_and(|phosphor| phosphor.update(frame))
+    }
+
+    // Replaces the overlay; it is drawn from the next `draw` on. Returns
+    // true if a redraw is needed to show the change, i.e. unless both the
+    // old and the new overlay are empty.
+    pub fn set_overlay(&mut self, overlay: Overlay) -> bool {
+        let changed = !overlay.is_empty() || !self.overlay.is_empty();
+        self.overlay = overlay;
+        changed
+    }
+
+    fn draw_overlay(&mut self, viewport: Rect) {
+        if self.overlay.is_empty() {
+            return;
+        }
+        let pixel = (viewport.width() / SCREEN_WIDTH / 8).max(1);
+        let (x0, y0) = (viewport.x() as u32, viewport.y() as u32);
+        let columns = self.overlay.lines.iter().map(|line| {
+            line.iter()
+                .map(|(text, _)| text.chars().count())
+                .sum::<usize>()
+        });
+        let columns = columns.max().unwrap_or(0) as u32;
+        let rows = self.overlay.lines.len() as u32;
+        self.canvas.set_draw_color(BACKDROP);
+        let _ = self.canvas.fill_rect(Rect::new(
+            x0 as i32,
+            y0 as i32,
+            (columns * CELL_WIDTH + 1) * pixel,
+            (rows * CELL_HEIGHT + 1) * pixel,
+        ));
+
+        let mut text = Vec::new();
+        let mut highlighted = Vec::new();
+        for (row, line) in self.overlay.lines.iter().enumerate() {
+            let mut column = 0;
+            for (span, highlight) in line {
+                let rects = if *highlight {
+                    &mut highlighted
+                } else {
+                    &mut text
+                };
+                for c in span.chars() {
+                    let left = x0 + (column * CELL_WIDTH + 1) * pixel;
+                    let top = y0 + (row as u32 * CELL_HEIGHT + 1) * pixel;
+                    for (y, bits) in glyph(c).iter().enumerate() {
+                        for x in 0..GLYPH_WIDTH {
+                            if bits & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                                rects.push(Rect::new(
+                                    (left + x * pixel) as i32,
+                                    (top + y as u32 * pixel) as i32,
+                                    pixel,
+                                    pixel,
+                                ));
+                            }
+                        }
+                    }
+                    column += 1;
+                }
+            }
+        }
+        self.canvas.set_draw_color(TEXT);
+        let _ = self.canvas.fill_rects(&text);
+        self.canvas.set_draw_color(HIGHLIGHT);
+        let _ = self.canvas.fill_rects(&highlighted);
+    }
+
+    fn draw_crt(&mut self, viewport: Rect, bloom: &[(Rect, [u8; 3])]) {
+        let (x0, y0) = (viewport.x(), viewport.y());
+        let (window_width, window_height) = (viewport.width(), viewport.height());
+
+        let spread = (window_width / SCREEN_WIDTH / 4).max(1);
+        for &(rect, [r, g, b]) in bloom {
+            self.canvas
+                .set_draw_color(pixels::Color::RGBA(r, g, b, BLOOM_ALPHA));
+            let _ = self.canvas.fill_rect(Rect::new(
+                rect.x() - spread as i32,
+                rect.y() - spread as i32,
+                rect.width() + 2 * spread,
+                rect.height() + 2 * spread,
+            ));
+        }
+
+        let scanlines: Vec<Rect> = (SCANLINE_SPACING - 1..window_height)
+            .step_by(SCANLINE_SPACING as usize)
+            .map(|y| Rect::new(x0, y0 + y as i32, window_width, 1))
+            .collect();
+        self.canvas.set_draw_color(SCANLINE);
+        let _ = self.canvas.fill_rects(&scanlines);
+
+        let ring = (window_height / 4 / VIGNETTE_RINGS).max(1);
+        self.canvas
+            .set_draw_color(pixels::Color::RGBA(0, 0, 0, VIGNETTE_ALPHA));
+        for i in 0..VIGNETTE_RINGS {
+            let inset = i * ring;
+            let (width, height) = (window_width - 2 * inset, window_height - 2 * inset);
+            let (left, top) = (x0 + inset as i32, y0 + inset as i32);
+            let _ = self.canvas.fill_rects(&[
+                Rect::new(left, top, width, ring),
+                Rect::new(left, top + (height - ring) as i32, width, ring),
+                Rect::new(left, top + ring as i32, ring, height - 2 * ring),
+                Rect::new(
+                    left + (width - ring) as i32,
+                    top + ring as i32,
+                    ring,
+                    height - 2 * ring,
+                ),
+            ]);
+        }
+    }
+
+    #[cfg(feature = "image")]
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+}
+
+impl Screen for Display {
+    fn draw(&mut self, frame: &Frame) {
+        let (width, height) = frame.screen_size();
+        let viewport = self.viewport();
+
+        // Only the rows whose colours changed are uploaded, in one update
+        // covering the first to the last of them.
+        let mut row = vec![0; width * BYTES_PER_PIXEL];
+        let mut changed: Option<(usize, usize)> = None;
+        for y in 0..height {
+            for x in 0..width {
+                let color = match &self.phosphor {
+                    Some(phosphor) if frame.mega.is_none() => phosphor.color(&self.palette, x, y),
+                    _ => frame.color(&self.palette, x, y),
+                };
+                row[x * BYTES_PER_PIXEL..(x + 1) * BYTES_PER_PIXEL].copy_from_slice(&color);
             }
+            let shown = &mut self.pixels[y * PITCH..y * PITCH + row.len()];
+            if *shown != row[..] {
+                shown.copy_from_slice(&row);
+                changed = Some(changed.map_or((y, y), |(first, _)| (first, y)));
+            }
+        }
+        if let Some((first, last)) = changed {
+            let rows = Rect::new(0, first as i32, width as u32, (last - first + 1) as u32);
+            let _ = self
+                .texture
+                .update(rows, &self.pixels[first * PITCH..], PITCH);
+        }
+
+        self.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let screen = Rect::new(0, 0, width as u32, height as u32);
+        let _ = self.canvas.copy(&self.texture, screen, viewport);
+
+        if self.crt {
+            let mut bloom = Vec::new();
+            let background = self.palette.color(0);
+            for y in 0..height {
+                for x in 0..width {
+                    let color = frame.color(&self.palette, x, y);
+                    if color != background {
+                        bloom.push((cell(viewport, (width, height), x, y), color));
+                    }
+                }
+            }
+            self.draw_crt(viewport, &bloom);
         }
+        self.draw_overlay(viewport);
         self.canvas.present();
     }
 }
 
-fn color(value: u8) -> pixels::Color {
-    if value == 0 {
-        pixels::Color::RGB(0, 0, 0)
-    } else {
-        pixels::Color::RGB(0, 255, 0)
+// The part of the viewport showing CHIP-8 pixel (x, y) at the given
+// resolution.
+fn cell(viewport: Rect, (width, height): (usize, usize), x: usize, y: usize) -> Rect {
+    let (x, y, width, height) = (x as u32, y as u32, width as u32, height as u32);
+    let left = x * viewport.width() / width;
+    let right = (x + 1) * viewport.width() / width;
+    let top = y * viewport.height() / height;
+    let bottom = (y + 1) * viewport.height() / height;
+    Rect::new(
+        viewport.x() + left as i32,
+        viewport.y() + top as i32,
+        right - left,
+        bottom - top,
+    )
+}
+
+// Rows of a 3x5 glyph, most significant of the low three bits on the left.
+// Covers what the overlays print, ROM names included; anything else shows
+// as '?'.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0, 0, 0, 0, 0],
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        '.' => [0, 0, 0, 0, 2],
+        '>' => [4, 2, 1, 2, 4],
+        '_' => [0, 0, 0, 0, 7],
+        '+' => [0, 2, 7, 2, 0],
+        '/' => [1, 1, 2, 4, 4],
+        ',' => [0, 0, 0, 2, 4],
+        '\'' => [2, 2, 0, 0, 0],
+        '!' => [2, 2, 2, 0, 2],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        '[' => [3, 2, 2, 2, 3],
+        ']' => [6, 2, 2, 2, 6],
+        _ => [7, 1, 2, 0, 2],
     }
 }
@@ -0,0 +1,118 @@
+// Draws frames on any embedded-graphics `DrawTarget`, so the no_std core
+// can drive the SSD1306, ILI9341 and similar display drivers on a board.
+// Each CHIP-8 pixel becomes a `scale` x `scale` block in one of two
+// colours; XO-CHIP planes and MegaChip colours are all just "on", which is
+// what a monochrome panel can show anyway. The whole screen is sent as one
+// contiguous fill, which drivers turn into a single window write.
+use embedded_graphics::prelude::{DrawTarget, Point, Size};
+use embedded_graphics::primitives::Rectangle;
+
+use crate::frame::Frame;
+use crate::frontend::Screen;
+use crate::palette::Palette;
+
+pub struct EmbeddedScreen<D: DrawTarget> {
+    display: D,
+    scale: u32,
+    on: D::Color,
+    off: D::Color,
+}
+
+impl<D: DrawTarget> EmbeddedScreen<D> {
+    pub fn new(display: D, scale: u32, on: D::Color, off: D::Color) -> Self {
+        EmbeddedScreen {
+            display,
+            scale: scale.max(1),
+            on,
+            off,
+        }
+    }
+
+    // The display, e.g. to flush a buffered driver after drawing.
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn into_inner(self) -> D {
+        self.display
+    }
+
+    // Like `Screen::draw`, but hands back the driver's error.
+    pub fn draw_frame(&mut self, frame: &Frame) -> Result<(), D::Error> {
+        let (width, height) = frame.screen_size();
+        let scale = self.scale as usize;
+        let area = Rectangle::new(
+            Point::zero(),
+            Size::new((width * scale) as u32, (height * scale) as u32),
+        );
+        let palette = Palette::default();
+        let background = palette.color(0);
+        let (on, off) = (self.on, self.off);
+        let colors = (0..height * scale).flat_map(move |y| {
+            (0..width * scale).map(move |x| {
+                if frame.color(&palette, x / scale, y / scale) == background {
+                    off
+                } else {
+                    on
+                }
+            })
+        });
+        self.display.fill_contiguous(&area, colors)
+    }
+}
+
+// Drawing errors are dropped here; use `draw_frame` to see them.
+impl<D: DrawTarget> Screen for EmbeddedScreen<D> {
+    fn draw(&mut self, frame: &Frame) {
+        let _ = self.draw_frame(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::pixelcolor::BinaryColor;
+
+    fn frame(pixels: &[(usize, usize)]) -> Frame {
+        let mut frame = Frame::default();
+        for &(x, y) in pixels {
+            frame.gfx.set(x, y, 1);
+        }
+        frame
+    }
+
+    #[test]
+    fn draws_every_pixel_in_the_two_colours() {
+        let mut screen =
+            EmbeddedScreen::new(MockDisplay::new(), 1, BinaryColor::On, BinaryColor::Off);
+        screen.draw_frame(&frame(&[(0, 0), (63, 31)])).unwrap();
+
+        let display = screen.into_inner();
+        assert_eq!(
+            display.affected_area(),
+            Rectangle::new(Point::zero(), Size::new(64, 32))
+        );
+        assert_eq!(display.get_pixel(Point::new(0, 0)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(63, 31)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(1, 0)), Some(BinaryColor::Off));
+    }
+
+    #[test]
+    fn scales_pixels_into_blocks() {
+        let mut screen =
+            EmbeddedScreen::new(MockDisplay::new(), 3, BinaryColor::On, BinaryColor::Off);
+        // The mock display is 64x64, smaller than the scaled screen.
+        screen.display_mut().set_allow_out_of_bounds_drawing(true);
+        screen.draw(&frame(&[(1, 0)]));
+
+        let display = screen.into_inner();
+        let on = |x, y| display.get_pixel(Point::new(x, y)) == Some(BinaryColor::On);
+        for y in 0..6 {
+            let row: Vec<bool> = (0..8).map(|x| on(x, y)).collect();
+            let lit = y < 3;
+            assert_eq!(row, [false, false, false, lit, lit, lit, false, false]);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use std::thread;
+use std::time::Instant;
+
+use crate::blocks::BlockCache;
+use crate::cheat::Cheats;
+use crate::disasm::Instruction;
+use crate::error::Chip8Error;
+use crate::frame::{Frame, Frames, FRAME_DURATION};
+use crate::frontend::{Buzzer, Keypad, Screen};
+use crate::headless::Halt;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::processor::{Framebuffer, CPU};
+use crate::profile::Profiler;
+use crate::quirks::Quirks;
+#[cfg(feature = "lua")]
+use crate::script::Script;
+use crate::trace::Tracer;
+use crate::watch::{self, Accesses};
+
+// What one `Chip8::step` did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    // The instruction executed, or None if the CPU was halted or waiting
+    // for a key.
+    pub instruction: Option<Instruction>,
+    pub pc: usize,
+    pub next_pc: usize,
+    // The registers and memory it read and wrote; see `watch::Accesses`.
+    // A key arriving for FX0A counts as a write to its register.
+    pub accesses: Accesses,
+    // Whether it changed the screen, and whether it started or stopped
+    // the buzzer.
+    pub drew: bool,
+    pub sound_changed: bool,
+}
+
+// A CPU plus the keypad state fed to it, for frontends that would rather
+// not drive `CPU::cycle` by hand.
+#[derive(Default)]
+pub struct Chip8 {
+    cpu: CPU,
+    keys: [bool; 16],
+    // What the last `run_frame` produced.
+    frame: Frame,
+    // The CPU as it was right after the ROM was loaded, for `reset`.
+    boot: Option<Box<CPU>>,
+    // Cleared whenever memory may have changed behind its back, i.e.
+    // whenever the CPU is handed out or replaced.
+    blocks: BlockCache,
+    #[cfg(feature = "jit")]
+    jit: Option<Box<Jit>>,
+}
+
+impl Chip8 {
+    pub fn new() -> Self {
+        Chip8::default()
+    }
+
+    // Wraps a CPU set up with `CPU::builder`. Until a ROM is loaded,
+    // `reset` goes back to it.
+    pub fn from_cpu(cpu: CPU) -> Self {
+        Chip8 {
+            boot: Some(Box::new(cpu.clone())),
+            cpu,
+            ..Chip8::default()
+        }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), Chip8Error> {
+        self.cpu.load(filename)?;
+        self.blocks.clear();
+        self.boot = Some(Box::new(self.cpu.clone()));
+        Ok(())
+    }
+
+    // Loads a ROM that is already in memory, e.g. one passed in over FFI.
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.cpu.load_bytes(rom)?;
+        self.blocks.clear();
+        self.boot = Some(Box::new(self.cpu.clone()));
+        Ok(())
+    }
+
+    // Restarts the loaded ROM from power-on, random seed included, keeping
+    // the current speed, quirks and RPL flags.
+    pub fn reset(&mut self) {
+        let boot = self.boot.as_deref().cloned().unwrap_or_default();
+        self.cpu = CPU {
+            cycles_per_frame: self.cpu.cycles_per_frame,
+            quirks: self.cpu.quirks,
+            rpl: self.cpu.rpl,
+            tracer: self.cpu.tracer.take(),
+            profiler: self.cpu.profiler.take(),
+            cheats: std::mem::take(&mut self.cpu.cheats),
+            #[cfg(feature = "lua")]
+            script: self.cpu.script.take(),
+            draw_flag: true,
+            ..boot
+        };
+        self.keys = [false; 16];
+        self.blocks.clear();
+    }
+
+    // Makes this a MegaChip machine; see `CPU::set_megachip`. Call before
+    // loading the ROM.
+    pub fn set_megachip(&mut self) {
+        self.cpu.set_megachip();
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.quirks = quirks;
+    }
+
+    // Number of instructions executed per 60 Hz frame.
+    pub fn set_speed(&mut self, cycles_per_frame: usize) {
+        self.cpu.cycles_per_frame = cycles_per_frame;
+    }
+
+    pub fn speed(&self) -> usize {
+        self.cpu.cycles_per_frame
+    }
+
+    // Logs every executed instruction; see `trace::Tracer`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.tracer = tracer;
+    }
+
+    // Counts executed instructions; see `profile::Profiler`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.cpu.profiler = profiler;
+    }
+
+    // Freezes memory; see `cheat::Cheats`.
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cpu.cheats = cheats;
+        self.blocks.clear();
+    }
+
+    // Runs a script's hooks alongside the program; see `script::Script`.
+    #[cfg(feature = "lua")]
+    pub fn set_script(&mut self, script: Option<Script>) {
+        self.cpu.script = script;
+        self.blocks.clear();
+    }
+
+    // Runs frames through the experimental native code backend; see
+    // `jit::Jit`. Fails if it can't generate code for this machine.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, on: bool) -> Result<(), String> {
+        self.blocks.clear();
+        self.jit = if on {
+            Some(Box::new(Jit::new()?))
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn jit_enabled(&self) -> bool {
+        self.jit.is_some()
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.cpu.set_seed(seed);
+    }
+
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.keys[key & 0xF] = pressed;
+    }
+
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+    }
+
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    // The second player's keypad, for CHIP-8X and two-player programs; see
+    // `CPU::keypad2`. It stays as set until set again.
+    pub fn set_keys2(&mut self, keys: [bool; 16]) {
+        self.cpu.keypad2 = keys;
+    }
+
+    pub fn keys2(&self) -> [bool; 16] {
+        self.cpu.keypad2
+    }
+
+    // Executes a single instruction with the current keypad state and
+    // reports what it did.
+    pub fn step(&mut self) -> Result<Step, Chip8Error> {
+        let cpu = &mut self.cpu;
+        let pc = cpu.pc;
+        let instruction = if cpu.halted || cpu.keypad_waiting {
+            None
+        } else {
+            cpu.memory
+                .get(pc..cpu.memory.len().min(pc + 4))
+                .and_then(Instruction::decode)
+        };
+        let mut accesses = match instruction {
+            Some(instruction) => watch::accesses(instruction, cpu),
+            None => Accesses::default(),
+        };
+        let (waiting, sound, drawn) = (cpu.keypad_waiting, cpu.sound_timer > 0, cpu.draw_flag);
+        cpu.draw_flag = false;
+
+        let result = self.blocks.step(cpu, 1, self.keys);
+        let cpu = &mut self.cpu;
+        let drew = cpu.draw_flag;
+        cpu.draw_flag |= drawn;
+        result?;
+
+        if waiting && !cpu.keypad_waiting {
+            accesses.writes = 1 << cpu.keypad_register;
+        }
+        let step = Step {
+            instruction,
+            pc,
+            next_pc: cpu.pc,
+            accesses,
+            drew,
+            sound_changed: sound != (cpu.sound_timer > 0),
+        };
+        Ok(step)
+    }
+
+    // Runs one 60 Hz frame with `keys` held: a frame's worth of
+    // instructions, then the timers tick once. Frontends call this once per
+    // frame and pace the calls themselves.
+    pub fn run_frame(&mut self, keys: [bool; 16]) -> Result<&Frame, Chip8Error> {
+        self.keys = keys;
+        // The JIT only knows the 16-bit I, not MegaChip's.
+        #[cfg(feature = "jit")]
+        if let (Some(jit), None) = (&mut self.jit, &self.cpu.mega) {
+            self.frame = jit.run_frame(&mut self.cpu, keys)?;
+            return Ok(&self.frame);
+        }
+        self.frame = self.blocks.run_frame(&mut self.cpu, keys)?;
+        Ok(&self.frame)
+    }
+
+    // The screen and sound as of the last completed frame.
+    pub fn last_frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    // Runs without a frontend; see `CPU::run_headless`.
+    pub fn run_headless(&mut self, max_cycles: usize) -> Result<Halt, Chip8Error> {
+        self.blocks
+            .run_headless(&mut self.cpu, max_cycles, self.keys)
+    }
+
+    pub fn frames<I: Keypad>(&mut self, input: I) -> Frames<'_, I> {
+        self.blocks.clear();
+        self.cpu.frames(input)
+    }
+
+    // Runs in real time until the keypad stops producing input or the
+    // program faults.
+    pub fn run<S, K, B>(
+        &mut self,
+        screen: &mut S,
+        mut keypad: K,
+        buzzer: &mut B,
+    ) -> Result<(), Chip8Error>
+    where
+        S: Screen,
+        K: Keypad,
+        B: Buzzer,
+    {
+        // Frames are timed from when they were due rather than from when
+        // the last one finished, so time spent emulating and drawing doesn't
+        // slow the program down. After a stall the schedule starts over
+        // instead of racing to catch up.
+        let mut due = Instant::now();
+        while let Some(keys) = keypad.keys() {
+            let frame = self.run_frame(keys)?;
+            buzzer.set_pattern(frame.audio_pattern, frame.pitch);
+            buzzer.set_playing(frame.sound);
+            if frame.draw {
+                screen.draw(frame);
+            }
+            due += FRAME_DURATION;
+            match due.checked_duration_since(Instant::now()) {
+                Some(wait) => thread::sleep(wait),
+                None => due = Instant::now(),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> Result<Vec<u8>, Chip8Error> {
+        self.cpu.save_state()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.blocks.clear();
+        self.cpu.load_state(data)
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.cpu.gfx
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.cpu.screen_size()
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.cpu.sound_timer > 0
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    // Clears the block cache, since the caller may change any of memory.
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        self.blocks.clear();
+        &mut self.cpu
+    }
+
+    // Lends the CPU to `f` and then drops only the cached blocks whose code
+    // it changed, for tools polled every frame that rarely write memory.
+    pub fn with_cpu_mut<R>(&mut self, f: impl FnOnce(&mut CPU) -> R) -> R {
+        let result = f(&mut self.cpu);
+        self.blocks.revalidate(&self.cpu);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_restarts_the_rom() {
+        let path = std::env::temp_dir().join(format!("chip8-reset-{}.ch8", std::process::id()));
+        // Add 1 to V0 forever.
+        std::fs::write(&path, [0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut chip8 = Chip8::new();
+        chip8.load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        chip8.set_speed(20);
+        chip8.run_frame([false; 16]).unwrap();
+        assert_eq!(chip8.cpu().v[0], 10);
+
+        chip8.reset();
+        assert_eq!(chip8.cpu().v[0], 0);
+        assert_eq!(chip8.cpu().pc, 0x200);
+        assert_eq!(chip8.speed(), 20);
+        chip8.run_frame([false; 16]).unwrap();
+        assert_eq!(chip8.cpu().v[0], 10);
+    }
+
+    #[test]
+    fn run_frame_returns_the_finished_frame() {
+        let mut chip8 = Chip8::new();
+        // V0 = 5, sound timer = V0, draw the 0 glyph, loop.
+        chip8
+            .load_bytes(&[0x60, 0x05, 0xF0, 0x18, 0xD0, 0x05, 0x12, 0x06])
+            .unwrap();
+        let frame = chip8.run_frame([true; 16]).unwrap().clone();
+        assert!(frame.draw && frame.sound);
+        assert_eq!(chip8.keys(), [true; 16]);
+        assert_eq!(chip8.last_frame(), &frame);
+        assert_eq!(chip8.cpu().sound_timer, 4);
+    }
+
+    #[test]
+    fn tools_only_drop_the_blocks_they_change() {
+        let mut chip8 = Chip8::new();
+        // Add 1 to V0 and V1, loop.
+        chip8
+            .load_bytes(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00])
+            .unwrap();
+        // Twice round the loop per frame.
+        chip8.set_speed(6);
+        chip8.run_frame([false; 16]).unwrap();
+        assert_eq!(chip8.blocks.len(), 1);
+
+        chip8.with_cpu_mut(|cpu| cpu.v[0] = 0);
+        chip8.with_cpu_mut(|cpu| cpu.memory[0x300] = 1);
+        assert_eq!(chip8.blocks.len(), 1);
+
+        // 7102 now adds 2 to V1, which the cached block mustn't hide.
+        chip8.with_cpu_mut(|cpu| cpu.memory[0x203] = 2);
+        assert!(chip8.blocks.is_empty());
+        chip8.run_frame([false; 16]).unwrap();
+        assert_eq!(chip8.cpu().v[1], 2 + 2 * 2);
+    }
+
+    #[test]
+    fn step_reports_what_happened() {
+        let mut chip8 = Chip8::new();
+        // V0 = 5, sound timer = V0, draw at V0,V0, wait for a key into V3.
+        chip8
+            .load_bytes(&[0x60, 0x05, 0xF0, 0x18, 0xD0, 0x05, 0xF3, 0x0A])
+            .unwrap();
+
+        let step = chip8.step().unwrap();
+        assert_eq!(
+            step.instruction,
+            Some(Instruction::LoadByte { x: 0, nn: 5 })
+        );
+        assert_eq!((step.pc, step.next_pc), (0x200, 0x202));
+        assert_eq!((step.accesses.reads, step.accesses.writes), (0, 1));
+        assert!(!step.drew && !step.sound_changed);
+
+        assert!(chip8.step().unwrap().sound_changed);
+        let step = chip8.step().unwrap();
+        assert!(step.drew);
+        assert_eq!(step.accesses.writes, 1 << 0xF);
+
+        chip8.step().unwrap();
+        let step = chip8.step().unwrap();
+        assert_eq!(step.instruction, None);
+        assert_eq!((step.pc, step.next_pc), (0x208, 0x208));
+        assert_eq!(step.accesses.writes, 0);
+
+        chip8.set_key(7, true);
+        let step = chip8.step().unwrap();
+        assert_eq!(step.accesses.writes, 1 << 3);
+        assert_eq!(chip8.cpu().v[3], 7);
+    }
+}
@@ -0,0 +1,90 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    EmptyRom,
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    // The ROM looks like another kind of file, described by the string.
+    NotARom(&'static str),
+    UnknownOpcode {
+        opcode: u16,
+        pc: usize,
+    },
+    StackOverflow {
+        pc: usize,
+    },
+    StackUnderflow {
+        pc: usize,
+    },
+    MemoryOutOfBounds {
+        addr: usize,
+        pc: usize,
+    },
+    InvalidState(String),
+    InvalidRecording(String),
+    Script(String),
+    InvalidArchive(String),
+    InvalidConfig(String),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Chip8Error::Io(e) => write!(f, "I/O error: {}", e),
+            Chip8Error::EmptyRom => write!(f, "ROM is empty"),
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "ROM is {} bytes but at most {} bytes fit in memory",
+                size, max
+            ),
+            Chip8Error::NotARom(kind) => write!(f, "Not a CHIP-8 ROM: it looks like {}", kind),
+            Chip8Error::UnknownOpcode { opcode, pc } => {
+                write!(f, "Unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            Chip8Error::StackOverflow { pc } => write!(f, "Stack overflow at {:03X}", pc),
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "Return with an empty stack at {:03X}", pc)
+            }
+            Chip8Error::MemoryOutOfBounds { addr, pc } => write!(
+                f,
+                "Memory access at {:X} is out of bounds (at {:03X})",
+                addr, pc
+            ),
+            Chip8Error::InvalidState(reason) => write!(f, "Invalid save state: {}", reason),
+            Chip8Error::InvalidRecording(reason) => {
+                write!(f, "Invalid input recording: {}", reason)
+            }
+            Chip8Error::Script(reason) => write!(f, "Script error: {}", reason),
+            Chip8Error::InvalidArchive(reason) => write!(f, "Invalid archive: {}", reason),
+            Chip8Error::InvalidConfig(reason) => write!(f, "Invalid configuration: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
@@ -0,0 +1,262 @@
+// C API for embedding the interpreter in other languages, declared in
+// include/chip8.h. Build the library with
+//
+//     cargo rustc --lib --crate-type cdylib --release --features ffi
+//
+// (or `--crate-type staticlib`) and link against it.
+//
+// Every function takes a handle from `chip8_new`. Handles and buffers must
+// be valid for the duration of the call, a handle must not be used from two
+// threads at once, and `chip8_free` must be called exactly once per handle.
+// Functions returning int return 0 on success and -1 on failure.
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::c_int;
+use std::slice;
+
+use crate::emulator::Chip8;
+use crate::error::Chip8Error;
+
+fn status(result: Result<(), Chip8Error>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+// Copies `data` to `out` if it fits in `len` bytes. Returns the length of
+// `data` either way, so callers can ask for the size with a null buffer.
+unsafe fn copy_out(data: &[u8], out: *mut u8, len: usize) -> usize {
+    if !out.is_null() && len >= data.len() {
+        slice::from_raw_parts_mut(out, data.len()).copy_from_slice(data);
+    }
+    data.len()
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, rom: *const u8, len: usize) -> c_int {
+    if rom.is_null() {
+        return -1;
+    }
+    status((*chip8).load_bytes(slice::from_raw_parts(rom, len)))
+}
+
+// Restarts the loaded ROM.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip8: *mut Chip8) {
+    (*chip8).reset();
+}
+
+// Executes one instruction.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(chip8: *mut Chip8) -> c_int {
+    status((*chip8).step().map(|_| ()))
+}
+
+// Runs one 60 Hz frame: a frame's worth of instructions, then the timers.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> c_int {
+    let keys = (*chip8).keys();
+    status((*chip8).run_frame(keys).map(|_| ()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_speed(chip8: *mut Chip8, cycles_per_frame: usize) {
+    (*chip8).set_speed(cycles_per_frame);
+}
+
+// Bit n of `keys` is keypad key n.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(chip8: *mut Chip8, keys: u16) {
+    (*chip8).set_keys(keypad(keys));
+}
+
+// The second player's keypad, which only CHIP-8X's EXF2/EXF5 read.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys2(chip8: *mut Chip8, keys: u16) {
+    (*chip8).set_keys2(keypad(keys));
+}
+
+fn keypad(keys: u16) -> [bool; 16] {
+    let mut pressed = [false; 16];
+    for (i, key) in pressed.iter_mut().enumerate() {
+        *key = keys & (1 << i) != 0;
+    }
+    pressed
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screen_width(chip8: *const Chip8) -> usize {
+    (*chip8).screen_size().0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screen_height(chip8: *const Chip8) -> usize {
+    (*chip8).screen_size().1
+}
+
+// The visible screen, one byte per pixel in rows of `chip8_screen_width`.
+// Pixels are 0 or 1, or up to 3 for XO-CHIP programs drawing on both planes.
+// On the MegaChip screen they are the palette index last drawn instead.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_framebuffer(
+    chip8: *const Chip8,
+    out: *mut u8,
+    len: usize,
+) -> usize {
+    let chip8 = &*chip8;
+    let (width, height) = chip8.screen_size();
+    if let Some(mega) = chip8.cpu().megachip() {
+        return copy_out(&mega.screen.indexes, out, len);
+    }
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in chip8.framebuffer().rows().take(height) {
+        pixels.extend_from_slice(&row[..width]);
+    }
+    copy_out(&pixels, out, len)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(chip8: *const Chip8) -> bool {
+    (*chip8).sound_active()
+}
+
+// Writes a save state to `out` and returns its size, or 0 on failure. As
+// with the framebuffer, nothing is written if `len` is too small.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_state(chip8: *const Chip8, out: *mut u8, len: usize) -> usize {
+    match (*chip8).save_state() {
+        Ok(state) => copy_out(&state, out, len),
+        Err(_) => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_state(chip8: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    if data.is_null() {
+        return -1;
+    }
+    status((*chip8).load_state(slice::from_raw_parts(data, len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn runs_a_rom_through_the_c_api() {
+        // I = the font digit F; draw it at (V1, V1) = (0, 0).
+        let rom = [0x60, 0x0F, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+        unsafe {
+            let chip8 = chip8_new();
+            assert_eq!(chip8_load_rom(chip8, rom.as_ptr(), rom.len()), 0);
+            assert_eq!(chip8_run_frame(chip8), 0);
+
+            let size = chip8_get_framebuffer(chip8, ptr::null_mut(), 0);
+            assert_eq!(size, chip8_screen_width(chip8) * chip8_screen_height(chip8));
+            let mut pixels = vec![0; size];
+            chip8_get_framebuffer(chip8, pixels.as_mut_ptr(), size);
+            assert_eq!(pixels[..4], [1, 1, 1, 1]);
+
+            let mut state = vec![0; chip8_get_state(chip8, ptr::null_mut(), 0)];
+            assert_eq!(
+                chip8_get_state(chip8, state.as_mut_ptr(), state.len()),
+                state.len()
+            );
+            chip8_reset(chip8);
+            assert_eq!(
+                chip8_get_framebuffer(chip8, pixels.as_mut_ptr(), size),
+                size
+            );
+            assert_eq!(pixels[..4], [0, 0, 0, 0]);
+            assert_eq!(chip8_set_state(chip8, state.as_ptr(), state.len()), 0);
+            chip8_get_framebuffer(chip8, pixels.as_mut_ptr(), size);
+            assert_eq!(pixels[..4], [1, 1, 1, 1]);
+
+            assert_eq!(chip8_set_state(chip8, state.as_ptr(), 3), -1);
+            chip8_free(chip8);
+        }
+    }
+
+    // The C spelling of a Rust type in an exported signature.
+    fn c_type(rust: &str) -> String {
+        let (constness, pointee) = match rust.split_once(' ') {
+            Some(("*const", pointee)) => ("const ", pointee),
+            Some(("*mut", pointee)) => ("", pointee),
+            _ => ("", rust),
+        };
+        let c = match pointee {
+            "u8" => "uint8_t",
+            "u16" => "uint16_t",
+            "usize" => "size_t",
+            "c_int" => "int",
+            other => other,
+        };
+        if pointee == rust {
+            c.to_string()
+        } else {
+            format!("{}{} *", constness, c)
+        }
+    }
+
+    // `int chip8_step(Chip8 *chip8);` for `fn chip8_step(chip8: *mut Chip8)
+    // -> c_int`, spaced the way the header is.
+    fn c_prototype(name: &str, params: &str, ret: &str) -> String {
+        let declare = |ty: String, name: &str| match ty.ends_with('*') {
+            true => format!("{}{}", ty, name),
+            false => format!("{} {}", ty, name),
+        };
+        let params: Vec<String> = params
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, ty) = param.split_once(": ").unwrap();
+                declare(c_type(ty), name)
+            })
+            .collect();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        let ret = if ret.is_empty() { "void" } else { ret };
+        format!("{}({});", declare(c_type(ret), name), params)
+    }
+
+    // The header is written by hand so it can carry C-side documentation;
+    // this keeps its prototypes in step with the Rust signatures.
+    #[test]
+    fn header_matches_every_signature() {
+        let squash = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let header = squash(include_str!("../include/chip8.h")).replace("( ", "(");
+        let source = include_str!("ffi.rs");
+        let exported = source.split("extern \"C\" fn ").skip(1).map(|rest| {
+            let signature = squash(&rest[..rest.find('{').unwrap()]);
+            let (name, rest) = signature.split_once('(').unwrap();
+            let (params, ret) = rest.rsplit_once(')').unwrap();
+            let ret = ret.trim().trim_start_matches("-> ");
+            c_prototype(name, params, ret)
+        });
+        for prototype in exported {
+            assert!(
+                header.contains(&prototype),
+                "include/chip8.h doesn't declare {}",
+                prototype
+            );
+        }
+    }
+}
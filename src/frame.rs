@@ -0,0 +1,101 @@
+use alloc::string::String;
+use core::fmt;
+use core::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Chip8Error;
+use crate::frontend::Keypad;
+use crate::megachip::MegaScreen;
+use crate::palette::Palette;
+use crate::processor::{Framebuffer, Resolution, CPU};
+
+pub const CYCLES_PER_FRAME: usize = 8;
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub gfx: Framebuffer,
+    pub resolution: Resolution,
+    // The MegaChip screen, while it is on; `gfx` isn't shown then.
+    pub mega: Option<MegaScreen>,
+    pub draw: bool,
+    pub sound: bool,
+    // The XO-CHIP audio pattern (F002) and pitch (FX3A) for the buzzer to
+    // play. All zeros means the program never loaded one.
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
+}
+
+impl Frame {
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.resolution.size()
+    }
+
+    // The colour of the pixel at (x, y): `palette`'s for a pixel value, or
+    // the MegaChip screen's own.
+    pub fn color(&self, palette: &Palette, x: usize, y: usize) -> [u8; 3] {
+        match &self.mega {
+            Some(mega) => mega.color(x, y),
+            None => palette.color(self.gfx.get(x, y)),
+        }
+    }
+}
+
+// Renders the framebuffer as text, one line per row, for logs and test output.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.screen_size();
+        if let Some(mega) = &self.mega {
+            for y in 0..height {
+                let line: String = (0..width)
+                    .map(|x| if mega.color(x, y) == [0; 3] { '.' } else { '#' })
+                    .collect();
+                writeln!(f, "{}", line)?;
+            }
+            return Ok(());
+        }
+        for row in self.gfx.rows().take(height) {
+            let line: String = row[..width]
+                .iter()
+                .map(|&px| ['.', '#', '+', '@'][(px & 0x3) as usize])
+                .collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs the CPU a frame at a time, e.g. `cpu.frames(input).take(600)` for
+// ten seconds. It ends when the input does, or after yielding an error:
+// the CPU is stuck on the instruction that failed, so running on would
+// only fail again.
+pub struct Frames<'a, I> {
+    cpu: &'a mut CPU,
+    input: I,
+    failed: bool,
+}
+
+impl<'a, I> Frames<'a, I> {
+    pub fn new(cpu: &'a mut CPU, input: I) -> Self {
+        Frames {
+            cpu,
+            input,
+            failed: false,
+        }
+    }
+}
+
+impl<I: Keypad> Iterator for Frames<'_, I> {
+    type Item = Result<Frame, Chip8Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let keypad = self.input.keys()?;
+        let frame = self.cpu.run_frame(keypad);
+        self.failed = frame.is_err();
+        Some(frame)
+    }
+}
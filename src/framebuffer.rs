@@ -0,0 +1,242 @@
+// The screen as bit planes: one u128 per row per XO-CHIP plane, with the
+// leftmost pixel in the top bit. Drawing a sprite row, checking it for
+// collisions, clearing and scrolling are then a few bitwise operations on
+// whole rows. Pixel values combine the planes, plane 1 in bit 0 and plane 2
+// in bit 1, so classic programs only ever produce 0 and 1.
+use core::iter;
+
+use serde::{Deserialize, Serialize};
+
+use crate::megachip::{MEGA_HEIGHT, MEGA_WIDTH};
+use crate::serde_arrays;
+
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_HEIGHT: usize = 64;
+
+// The screen sizes programs switch between, all drawn into the top-left
+// corner of the same framebuffer except MegaChip's, which has its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resolution {
+    // The original 64x32.
+    #[default]
+    Low,
+    // HiRes CHIP-8's two pages stacked into 64x64.
+    Tall,
+    // SCHIP's 128x64.
+    High,
+    // MegaChip's 256x192 in colour; see `megachip`.
+    Mega,
+}
+
+impl Resolution {
+    // Width and height in pixels.
+    pub fn size(self) -> (usize, usize) {
+        match self {
+            Resolution::Low => (SCREEN_WIDTH / 2, SCREEN_HEIGHT / 2),
+            Resolution::Tall => (SCREEN_WIDTH / 2, SCREEN_HEIGHT),
+            Resolution::High => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            Resolution::Mega => (MEGA_WIDTH, MEGA_HEIGHT),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Framebuffer {
+    #[serde(with = "serde_arrays::big_array")]
+    plane1: [u128; SCREEN_HEIGHT],
+    #[serde(with = "serde_arrays::big_array")]
+    plane2: [u128; SCREEN_HEIGHT],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer {
+            plane1: [0; SCREEN_HEIGHT],
+            plane2: [0; SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        let bit = |row: u128| (row >> (SCREEN_WIDTH - 1 - x)) as u8 & 1;
+        bit(self.plane1[y]) | bit(self.plane2[y]) << 1
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u8) {
+        let bit = 1 << (SCREEN_WIDTH - 1 - x);
+        for (plane, mask) in self.planes_mut(0x3) {
+            if value & mask != 0 {
+                plane[y] |= bit;
+            } else {
+                plane[y] &= !bit;
+            }
+        }
+    }
+
+    // Row `y` as one pixel value per byte.
+    pub fn row(&self, y: usize) -> [u8; SCREEN_WIDTH] {
+        let mut row = [0; SCREEN_WIDTH];
+        for (x, px) in row.iter_mut().enumerate() {
+            *px = self.get(x, y);
+        }
+        row
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = [u8; SCREEN_WIDTH]> + '_ {
+        (0..SCREEN_HEIGHT).map(move |y| self.row(y))
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.plane1
+            .iter()
+            .chain(self.plane2.iter())
+            .all(|&row| row == 0)
+    }
+
+    // Clears the planes selected by the `planes` mask.
+    pub fn clear(&mut self, planes: u8) {
+        for (plane, _) in self.planes_mut(planes) {
+            *plane = [0; SCREEN_HEIGHT];
+        }
+    }
+
+    // XORs `bits`, a row of pixels in screen position, into row `y` of the
+    // plane selected by `mask` (1 or 2). Returns whether it turned any lit
+    // pixel off.
+    pub(crate) fn xor_row(&mut self, mask: u8, y: usize, bits: u128) -> bool {
+        let plane = if mask == 1 {
+            &mut self.plane1
+        } else {
+            &mut self.plane2
+        };
+        let collision = plane[y] & bits != 0;
+        plane[y] ^= bits;
+        collision
+    }
+
+    // Moves the selected planes of the top-left `width` x `height` area by
+    // (dx, dy) pixels, clearing whatever scrolls in from the edges.
+    pub(crate) fn scroll(
+        &mut self,
+        planes: u8,
+        (width, height): (usize, usize),
+        dx: isize,
+        dy: isize,
+    ) {
+        let visible = row_mask(width);
+        for (plane, _) in self.planes_mut(planes) {
+            let old = *plane;
+            for (y, row) in plane[..height].iter_mut().enumerate() {
+                let sy = y as isize - dy;
+                let src = if sy >= 0 && sy < height as isize {
+                    old[sy as usize] & visible
+                } else {
+                    0
+                };
+                let shifted = match dx {
+                    0 => src,
+                    dx if dx.unsigned_abs() >= SCREEN_WIDTH => 0,
+                    dx if dx > 0 => src >> dx,
+                    dx => src << -dx,
+                };
+                *row = (*row & !visible) | (shifted & visible);
+            }
+        }
+    }
+
+    fn planes_mut(&mut self, planes: u8) -> impl Iterator<Item = (&mut [u128; SCREEN_HEIGHT], u8)> {
+        iter::once((&mut self.plane1, 1))
+            .chain(iter::once((&mut self.plane2, 2)))
+            .filter(move |&(_, mask)| planes & mask != 0)
+    }
+}
+
+// The `cols` most significant bits of `sprite` placed at column `x` of a
+// screen `width` pixels wide, either clipped at the right edge or wrapped
+// around to the left.
+pub(crate) fn sprite_row(sprite: u16, cols: usize, x: usize, width: usize, clip: bool) -> u128 {
+    let bits = (u128::from(sprite) >> (16 - cols)) << (SCREEN_WIDTH - cols);
+    let visible = row_mask(width);
+    if width == SCREEN_WIDTH {
+        return if clip {
+            bits >> x
+        } else {
+            bits.rotate_right(x as u32)
+        };
+    }
+    // Narrower screens leave room for the overhang in the low bits.
+    let placed = bits >> x;
+    let overhang = if clip {
+        0
+    } else {
+        (placed & !visible) << width
+    };
+    (placed | overhang) & visible
+}
+
+// The bits of a row that are on a screen `width` pixels wide.
+fn row_mask(width: usize) -> u128 {
+    if width >= SCREEN_WIDTH {
+        !0
+    } else {
+        !(!0u128 >> width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_combine_planes() {
+        let mut gfx = Framebuffer::default();
+        gfx.set(3, 2, 1);
+        gfx.set(127, 63, 3);
+        assert_eq!(gfx.get(3, 2), 1);
+        assert_eq!(gfx.get(127, 63), 3);
+        assert_eq!(gfx.row(2)[..5], [0, 0, 0, 1, 0]);
+
+        gfx.clear(1);
+        assert_eq!(gfx.get(3, 2), 0);
+        assert_eq!(gfx.get(127, 63), 2);
+        gfx.set(127, 63, 0);
+        assert!(gfx.is_blank());
+    }
+
+    #[test]
+    fn sprite_rows_wrap_or_clip() {
+        let top = |x: usize| 1u128 << (SCREEN_WIDTH - 1 - x);
+        // 8 pixels at x = 60 on the 64 wide screen: 60..63 then 0..3.
+        let wrapped = sprite_row(0xFF00, 8, 60, 64, false);
+        assert_eq!(wrapped, (0..4).chain(60..64).map(top).sum::<u128>());
+        let clipped = sprite_row(0xFF00, 8, 60, 64, true);
+        assert_eq!(clipped, (60..64).map(top).sum::<u128>());
+
+        let wrapped = sprite_row(0xC000, 2, 127, 128, false);
+        assert_eq!(wrapped, top(127) | top(0));
+        assert_eq!(sprite_row(0xC000, 2, 127, 128, true), top(127));
+    }
+
+    #[test]
+    fn scrolling_stays_inside_the_screen() {
+        let mut gfx = Framebuffer::default();
+        gfx.set(62, 0, 1);
+        gfx.scroll(1, (64, 32), 4, 0);
+        assert!(
+            gfx.is_blank(),
+            "pixel scrolled off the low resolution screen"
+        );
+
+        gfx.set(10, 30, 1);
+        gfx.scroll(1, (64, 32), -4, 1);
+        assert_eq!(gfx.get(6, 31), 1);
+        gfx.scroll(1, (64, 32), 0, 1);
+        assert!(gfx.is_blank());
+
+        // The bottom half of the 64x64 screen scrolls with the top.
+        gfx.set(0, 40, 1);
+        gfx.scroll(1, Resolution::Tall.size(), 4, 20);
+        assert_eq!(gfx.get(4, 60), 1);
+    }
+}
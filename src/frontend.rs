@@ -0,0 +1,61 @@
+use crate::frame::Frame;
+
+// The pieces a frontend provides to run the emulator: somewhere to show
+// frames, a keypad to read, and a buzzer for the sound timer.
+
+pub trait Screen {
+    fn draw(&mut self, frame: &Frame);
+}
+
+// Hands the CPU a keypad state once per frame. Returning None ends the
+// frame stream (e.g. the window was closed).
+pub trait Keypad {
+    fn keys(&mut self) -> Option<[bool; 16]>;
+}
+
+pub trait Buzzer {
+    // Called once per frame with whether the sound timer is running.
+    fn set_playing(&mut self, on: bool);
+
+    // The XO-CHIP pattern and pitch to play instead of a plain tone, from
+    // `Frame`. Buzzers that only beep can ignore it.
+    fn set_pattern(&mut self, _pattern: [u8; 16], _pitch: u8) {}
+
+    // How many times the output ran out of sound while the sound timer was
+    // running, for buzzers that queue it.
+    fn underruns(&self) -> u32 {
+        0
+    }
+}
+
+impl<F> Keypad for F
+where
+    F: FnMut() -> Option<[bool; 16]>,
+{
+    fn keys(&mut self) -> Option<[bool; 16]> {
+        self()
+    }
+}
+
+// Lets an optional device (e.g. audio that failed to open) stand in for one.
+impl<B: Buzzer> Buzzer for Option<B> {
+    fn set_playing(&mut self, on: bool) {
+        if let Some(buzzer) = self {
+            buzzer.set_playing(on);
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        if let Some(buzzer) = self {
+            buzzer.set_pattern(pattern, pitch);
+        }
+    }
+
+    fn underruns(&self) -> u32 {
+        self.as_ref().map_or(0, B::underruns)
+    }
+}
+
+impl Buzzer for () {
+    fn set_playing(&mut self, _on: bool) {}
+}
@@ -0,0 +1,484 @@
+// A GDB remote serial protocol server, so GDB or an IDE speaking the same
+// protocol can attach over TCP with `target remote :PORT`. It drives the
+// same `Debugger` as the hotkeys: the frontend calls `Server::poll` once a
+// frame, before running it.
+//
+// There is no CHIP-8 architecture in GDB, so the registers are described
+// in target.xml: V0-VF (8 bits), I and PC (16 bits, little-endian), then
+// SP, DT and ST (8 bits). Breakpoints (Z0/Z1) map to address breakpoints
+// and watchpoints (Z2-Z4) to the debugger's memory watchpoints.
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::debugger::Debugger;
+use crate::processor::{CPU, MEMORY_SIZE};
+use crate::watch::{Location, Watchpoint};
+
+const REGISTERS: usize = 21;
+const PACKET_SIZE: usize = 0x1000;
+
+pub struct Server {
+    listener: TcpListener,
+    client: Option<(TcpStream, Session)>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            client: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Accepts a debugger if none is attached and answers its requests. The
+    // program stops when GDB attaches and runs on when it detaches.
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut CPU) {
+        if self.client.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    let _ = stream.set_nodelay(true);
+                    debugger.pause();
+                    self.client = Some((stream, Session::default()));
+                }
+            }
+        }
+        let done = match &mut self.client {
+            Some((stream, session)) => serve(stream, session, debugger, cpu).is_err(),
+            None => false,
+        };
+        if done {
+            self.client = None;
+            debugger.resume();
+        }
+    }
+}
+
+fn serve(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    debugger: &mut Debugger,
+    cpu: &mut CPU,
+) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => session.input.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let mut output = session.receive(debugger, cpu);
+    if let Some(reply) = session.stop_reply(debugger) {
+        output.push_str(&packet(&reply));
+    }
+    if !output.is_empty() {
+        stream.write_all(output.as_bytes())?;
+    }
+    if session.closed {
+        return Err(io::ErrorKind::ConnectionAborted.into());
+    }
+    Ok(())
+}
+
+// The protocol state of one connection, apart from the socket.
+#[derive(Default)]
+struct Session {
+    input: Vec<u8>,
+    // Set after `c` or `s` until the program stops again.
+    running: bool,
+    closed: bool,
+}
+
+impl Session {
+    // Handles every complete packet in the input. Returns what to send
+    // back: an acknowledgement for each packet plus any replies.
+    fn receive(&mut self, debugger: &mut Debugger, cpu: &mut CPU) -> String {
+        let mut output = String::new();
+        loop {
+            match self.input.first() {
+                None => break,
+                // Ctrl-C in GDB.
+                Some(0x03) => {
+                    self.input.remove(0);
+                    debugger.pause();
+                    self.running = false;
+                    output.push_str(&packet("S02"));
+                }
+                Some(b'$') => {
+                    let end = match self.input.iter().position(|&b| b == b'#') {
+                        Some(end) if self.input.len() >= end + 3 => end,
+                        _ => break,
+                    };
+                    let data = String::from_utf8_lossy(&self.input[1..end]).into_owned();
+                    self.input.drain(..end + 3);
+                    output.push('+');
+                    if let Some(reply) = self.handle(&data, debugger, cpu) {
+                        output.push_str(&packet(&reply));
+                    }
+                }
+                // Acknowledgements, and anything else between packets.
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        output
+    }
+
+    // The stop reply owed for a `c` or `s`, once the program has stopped.
+    fn stop_reply(&mut self, debugger: &Debugger) -> Option<String> {
+        if self.running && debugger.is_paused() && !debugger.has_pending() {
+            self.running = false;
+            Some("S05".to_string())
+        } else {
+            None
+        }
+    }
+
+    // The reply to one packet; None for `c` and `s`, which are answered
+    // when the program stops.
+    fn handle(&mut self, data: &str, debugger: &mut Debugger, cpu: &mut CPU) -> Option<String> {
+        let (command, args) = data.split_at(data.len().min(1));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => hex((0..REGISTERS).flat_map(|n| register(cpu, n))),
+            "G" => match bytes(args) {
+                Some(data) => {
+                    let mut data = data.into_iter();
+                    for n in 0..REGISTERS {
+                        let len = register(cpu, n).len();
+                        let value: Vec<u8> = data.by_ref().take(len).collect();
+                        set_register(cpu, n, &value);
+                    }
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS => hex(register(cpu, n)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, bytes(value)?))
+                });
+                match parsed {
+                    Some((n, value)) if n < REGISTERS => {
+                        set_register(cpu, n, &value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match range(args) {
+                Some((addr, len)) => hex(cpu.memory[addr..addr + len].iter().copied()),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range_text, data)| Some((range(range_text)?, bytes(data)?)));
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len => {
+                        cpu.memory[addr..addr + len].copy_from_slice(&data);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "c" => {
+                debugger.resume();
+                self.running = true;
+                return None;
+            }
+            "s" => {
+                debugger.pause();
+                debugger.step();
+                self.running = true;
+                return None;
+            }
+            "Z" | "z" => match point(args) {
+                Some(point) => {
+                    set_point(debugger, point, command == "Z");
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            "D" => {
+                self.closed = true;
+                "OK".to_string()
+            }
+            "k" => {
+                self.closed = true;
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+}
+
+fn query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE);
+    }
+    if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        let (offset, len) = match range.split_once(',').and_then(|(offset, len)| {
+            Some((
+                usize::from_str_radix(offset, 16).ok()?,
+                usize::from_str_radix(len, 16).ok()?,
+            ))
+        }) {
+            Some(range) => range,
+            None => return "E01".to_string(),
+        };
+        let xml = target_xml();
+        let chunk = xml.get(offset.min(xml.len())..).unwrap_or_default();
+        let chunk = &chunk[..len.min(chunk.len())];
+        let more = offset + chunk.len() < xml.len();
+        return format!("{}{}", if more { 'm' } else { 'l' }, chunk);
+    }
+    match query {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for n in 0..16 {
+        let _ = write!(xml, "<reg name=\"v{:x}\" bitsize=\"8\"/>", n);
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\"/>\
+         <reg name=\"dt\" bitsize=\"8\"/>\
+         <reg name=\"st\" bitsize=\"8\"/>\
+         </feature></target>",
+    );
+    xml
+}
+
+// Register `n` in GDB's numbering, as target-order bytes.
+fn register(cpu: &CPU, n: usize) -> Vec<u8> {
+    match n {
+        0..=15 => vec![cpu.v[n]],
+        16 => (cpu.i as u16).to_le_bytes().to_vec(),
+        17 => (cpu.pc as u16).to_le_bytes().to_vec(),
+        18 => vec![cpu.sp as u8],
+        19 => vec![cpu.delay_timer],
+        _ => vec![cpu.sound_timer],
+    }
+}
+
+fn set_register(cpu: &mut CPU, n: usize, value: &[u8]) {
+    let byte = value.first().copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, value.get(1).copied().unwrap_or(0)]);
+    match n {
+        0..=15 => cpu.v[n] = byte,
+        16 => cpu.i = word as u32,
+        17 => cpu.pc = word as usize % MEMORY_SIZE,
+        18 => cpu.sp = (byte as usize).min(cpu.stack.len()),
+        19 => cpu.delay_timer = byte,
+        _ => cpu.sound_timer = byte,
+    }
+}
+
+enum Point {
+    Breakpoint(usize),
+    Watchpoints(Vec<Watchpoint>),
+}
+
+// Parses the `type,addr,kind` of a Z or z packet.
+fn point(args: &str) -> Option<Point> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let addr = usize::from_str_radix(fields.next()?, 16).ok()?;
+    let len = usize::from_str_radix(fields.next()?, 16).ok()?;
+    let (on_read, on_write) = match kind {
+        "0" | "1" => return Some(Point::Breakpoint(addr)),
+        "2" => (false, true),
+        "3" => (true, false),
+        "4" => (true, true),
+        _ => return None,
+    };
+    let watches = (addr..addr + len.clamp(1, 16))
+        .map(|addr| Watchpoint {
+            location: Location::Memory(addr),
+            on_read,
+            on_write,
+        })
+        .collect();
+    Some(Point::Watchpoints(watches))
+}
+
+fn set_point(debugger: &mut Debugger, point: Point, insert: bool) {
+    match point {
+        Point::Breakpoint(addr) if insert => debugger.add_breakpoint(addr),
+        Point::Breakpoint(addr) => {
+            debugger.remove_breakpoint(addr);
+        }
+        Point::Watchpoints(watches) => {
+            for watch in watches {
+                if insert {
+                    debugger.add_watchpoint(watch);
+                } else {
+                    debugger.remove_watchpoint(&watch);
+                }
+            }
+        }
+    }
+}
+
+// `addr,len` in hex, checked against the size of memory.
+fn range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    if addr.checked_add(len)? > MEMORY_SIZE {
+        return None;
+    }
+    Some((addr, len))
+}
+
+fn hex<I: IntoIterator<Item = u8>>(bytes: I) -> String {
+    bytes.into_iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Frames `data` as `$data#checksum`.
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(session: &mut Session, debugger: &mut Debugger, cpu: &mut CPU, data: &str) -> String {
+        session.input.extend_from_slice(packet(data).as_bytes());
+        session.receive(debugger, cpu)
+    }
+
+    #[test]
+    fn packets_are_checksummed() {
+        assert_eq!(packet("OK"), "$OK#9a");
+        assert_eq!(packet(""), "$#00");
+    }
+
+    #[test]
+    fn reads_and_writes_registers_and_memory() {
+        let (mut session, mut debugger, mut cpu) =
+            (Session::default(), Debugger::new(), CPU::new());
+        cpu.v[1] = 0xAB;
+        cpu.i = 0x0321;
+
+        let reply = send(&mut session, &mut debugger, &mut cpu, "g");
+        let registers = &reply[2..reply.len() - 3];
+        assert_eq!(registers.len(), 2 * 23);
+        assert!(registers.starts_with("00ab"));
+        assert_eq!(&registers[32..40], "21030002");
+
+        assert_eq!(
+            send(&mut session, &mut debugger, &mut cpu, "M300,2:beef"),
+            format!("+{}", packet("OK"))
+        );
+        assert_eq!(cpu.memory[0x300..0x302], [0xBE, 0xEF]);
+        assert_eq!(
+            send(&mut session, &mut debugger, &mut cpu, "m2ff,3"),
+            format!("+{}", packet("00beef"))
+        );
+        assert_eq!(
+            send(&mut session, &mut debugger, &mut cpu, "mffff,2"),
+            format!("+{}", packet("E01"))
+        );
+
+        send(&mut session, &mut debugger, &mut cpu, "P11=0402");
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn step_replies_once_the_instruction_ran() {
+        let (mut session, mut debugger, mut cpu) =
+            (Session::default(), Debugger::new(), CPU::new());
+        cpu.memory[0x200..0x204].copy_from_slice(&[0x60, 0x07, 0x12, 0x02]);
+        debugger.pause();
+
+        assert_eq!(send(&mut session, &mut debugger, &mut cpu, "s"), "+");
+        assert_eq!(session.stop_reply(&debugger), None);
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(session.stop_reply(&debugger), Some("S05".to_string()));
+        assert_eq!(cpu.v[0], 7);
+    }
+
+    #[test]
+    fn breakpoints_stop_a_continue() {
+        let (mut session, mut debugger, mut cpu) =
+            (Session::default(), Debugger::new(), CPU::new());
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x60, 0x07, 0x70, 0x01, 0x12, 0x02]);
+        debugger.pause();
+
+        send(&mut session, &mut debugger, &mut cpu, "Z0,204,2");
+        send(&mut session, &mut debugger, &mut cpu, "c");
+        assert!(!debugger.is_paused());
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.pc, 0x204);
+        assert_eq!(session.stop_reply(&debugger), Some("S05".to_string()));
+
+        send(&mut session, &mut debugger, &mut cpu, "z0,204,2");
+        assert_eq!(debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn serves_over_tcp() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let (mut debugger, mut cpu) = (Debugger::new(), CPU::new());
+
+        client.write_all(packet("?").as_bytes()).unwrap();
+        let expected = format!("+{}", packet("S05"));
+        let mut reply = Vec::new();
+        let mut buf = [0; 64];
+        client
+            .set_read_timeout(Some(std::time::Duration::from_millis(20)))
+            .unwrap();
+        for _ in 0..100 {
+            server.poll(&mut debugger, &mut cpu);
+            if let Ok(n) = client.read(&mut buf) {
+                reply.extend_from_slice(&buf[..n]);
+            }
+            if reply.len() >= expected.len() {
+                break;
+            }
+        }
+        assert!(debugger.is_paused());
+        assert_eq!(String::from_utf8(reply).unwrap(), expected);
+    }
+}
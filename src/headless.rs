@@ -0,0 +1,114 @@
+use core::fmt;
+
+use crate::error::Chip8Error;
+use crate::processor::CPU;
+
+// Why a headless run stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Halt {
+    // 00FD was executed.
+    Exit { cycles: usize },
+    // An instruction jumped to itself, the usual way test ROMs signal they
+    // are done.
+    Loop { pc: usize, cycles: usize },
+    // FX0A is waiting for a key the fixed keypad will never press.
+    KeyWait { cycles: usize },
+    // The cycle budget ran out.
+    Limit { cycles: usize },
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Halt::Exit { cycles } => write!(f, "exited after {} cycles", cycles),
+            Halt::Loop { pc, cycles } => {
+                write!(f, "looping at {:03X} after {} cycles", pc, cycles)
+            }
+            Halt::KeyWait { cycles } => write!(f, "waiting for a key after {} cycles", cycles),
+            Halt::Limit { cycles } => write!(f, "stopped at the {} cycle limit", cycles),
+        }
+    }
+}
+
+impl CPU {
+    // Runs without any frontend until the program stops making progress or
+    // `max_cycles` instructions have executed. Timers still tick once every
+    // `cycles_per_frame` instructions, so the program sees the same timing
+    // as it would in a window.
+    pub fn run_headless(
+        &mut self,
+        max_cycles: usize,
+        keypad: [bool; 16],
+    ) -> Result<Halt, Chip8Error> {
+        for cycles in 0..max_cycles {
+            if self.halted {
+                return Ok(Halt::Exit { cycles });
+            }
+            if self.keypad_waiting && !keypad.contains(&true) {
+                return Ok(Halt::KeyWait { cycles });
+            }
+
+            let pc = self.pc;
+            self.cycle(keypad)?;
+            if self.pc == pc && !self.halted && !self.vblank_wait {
+                return Ok(Halt::Loop {
+                    pc,
+                    cycles: cycles + 1,
+                });
+            }
+            if (cycles + 1) % self.cycles_per_frame.max(1) == 0 {
+                self.end_frame()?;
+            }
+        }
+        Ok(Halt::Limit { cycles: max_cycles })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu_with(program: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.memory[0x200..0x200 + program.len()].copy_from_slice(program);
+        cpu
+    }
+
+    #[test]
+    fn stops_on_a_jump_to_self() {
+        // 6005 / 1202
+        let mut cpu = cpu_with(&[0x60, 0x05, 0x12, 0x02]);
+        let halt = cpu.run_headless(100, [false; 16]).unwrap();
+        assert_eq!(
+            halt,
+            Halt::Loop {
+                pc: 0x202,
+                cycles: 2
+            }
+        );
+        assert_eq!(cpu.v[0], 5);
+    }
+
+    #[test]
+    fn stops_on_exit() {
+        let mut cpu = cpu_with(&[0x00, 0xFD]);
+        let halt = cpu.run_headless(100, [false; 16]).unwrap();
+        assert_eq!(halt, Halt::Exit { cycles: 1 });
+    }
+
+    #[test]
+    fn stops_waiting_for_a_key() {
+        let mut cpu = cpu_with(&[0xF0, 0x0A]);
+        let halt = cpu.run_headless(100, [false; 16]).unwrap();
+        assert_eq!(halt, Halt::KeyWait { cycles: 1 });
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        // 7001 / 1200
+        let mut cpu = cpu_with(&[0x70, 0x01, 0x12, 0x00]);
+        let halt = cpu.run_headless(10, [false; 16]).unwrap();
+        assert_eq!(halt, Halt::Limit { cycles: 10 });
+        assert_eq!(cpu.v[0], 5);
+    }
+}
@@ -0,0 +1,42 @@
+use chip8::processor::CPU;
+
+use crate::display::Overlay;
+
+// Live registers, timers and stack for the overlay.
+#[derive(Default)]
+pub struct RegisterHud {
+    visible: bool,
+}
+
+impl RegisterHud {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn write(&self, cpu: &CPU, overlay: &mut Overlay) {
+        if !self.visible {
+            return;
+        }
+        overlay.line(format!(
+            "PC {:04X}  I {:04X}  SP {:X}",
+            cpu.pc, cpu.i, cpu.sp
+        ));
+        overlay.line(format!(
+            "DT {:02X}  ST {:02X}",
+            cpu.delay_timer, cpu.sound_timer
+        ));
+        for (row, registers) in cpu.v.chunks(4).enumerate() {
+            let line: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(n, v)| format!("V{:X} {:02X}", row * 4 + n, v))
+                .collect();
+            overlay.line(line.join("  "));
+        }
+        let stack: Vec<String> = cpu.stack[..cpu.sp]
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect();
+        overlay.line(format!("STACK {}", stack.join(" ")));
+    }
+}
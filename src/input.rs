@@ -1,60 +1,654 @@
-use sdl2;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+use std::fs;
+
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+
+use chip8::frontend::Keypad;
+
+// Maps a controller's left stick onto four CHIP-8 keys. Only the dominant
+// axis counts, so diagonals resolve to a single direction. Keymap files set
+// it with `stick.` bindings; see `KeyMap::bind`.
+pub struct StickMapping {
+    pub up: usize,
+    pub down: usize,
+    pub left: usize,
+    pub right: usize,
+    pub dead_zone: i16,
+    pub sensitivity: f32,
+}
+
+impl Default for StickMapping {
+    fn default() -> Self {
+        StickMapping {
+            up: 0x2,
+            down: 0x8,
+            left: 0x4,
+            right: 0x6,
+            dead_zone: 8000,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl StickMapping {
+    fn direction(&self, x: i16, y: i16) -> Option<usize> {
+        let x = x as f32 * self.sensitivity;
+        let y = y as f32 * self.sensitivity;
+        let dead_zone = self.dead_zone as f32;
+
+        if x.abs() <= dead_zone && y.abs() <= dead_zone {
+            None
+        } else if x.abs() >= y.abs() {
+            Some(if x < 0.0 { self.left } else { self.right })
+        } else {
+            Some(if y < 0.0 { self.up } else { self.down })
+        }
+    }
+
+    // Applies `stick.<setting>=<value>`: up, down, left or right and a hex
+    // key, deadzone and 0 to 32767, or sensitivity and a multiplier.
+    fn set(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        let direction = match setting {
+            "up" => &mut self.up,
+            "down" => &mut self.down,
+            "left" => &mut self.left,
+            "right" => &mut self.right,
+            "deadzone" => {
+                self.dead_zone = value
+                    .parse()
+                    .ok()
+                    .filter(|&dead_zone| dead_zone >= 0)
+                    .ok_or_else(|| format!("stick.deadzone must be 0 to 32767, got '{}'", value))?;
+                return Ok(());
+            }
+            "sensitivity" => {
+                self.sensitivity = value
+                    .parse()
+                    .ok()
+                    .filter(|&sensitivity: &f32| sensitivity > 0.0)
+                    .ok_or_else(|| {
+                        format!(
+                            "stick.sensitivity must be a positive number, got '{}'",
+                            value
+                        )
+                    })?;
+                return Ok(());
+            }
+            _ => return Err(format!("'stick.{}' is not a stick setting", setting)),
+        };
+        *direction = parse_key(value)?;
+        Ok(())
+    }
+}
+
+fn parse_key(key: &str) -> Result<usize, String> {
+    match usize::from_str_radix(key, 16) {
+        Ok(i) if i < 16 => Ok(i),
+        _ => Err(format!("'{}' is not a CHIP-8 key (0-F)", key)),
+    }
+}
+
+// Emulator controls that act on the frontend rather than the CHIP-8 keypad.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    ToggleDebugger,
+    Step,
+    FrameAdvance,
+    SpeedUp,
+    SpeedDown,
+    Screenshot,
+    RecordGif,
+    Pause,
+    Reset,
+    ToggleMemory,
+    ToggleHud,
+    TogglePerf,
+    ToggleCrt,
+    ToggleFullscreen,
+    ToggleSlowMotion,
+    ToggleMute,
+    TogglePanel,
+    // The nth cheat, from 0.
+    ToggleCheat(usize),
+    MemoryPageUp,
+    MemoryPageDown,
+    MemoryFollowPc,
+}
+
+// Binds each CHIP-8 key to a physical key. The default puts the COSMAC VIP
+// hex pad on the 1234/QWER/ASDF/ZXCV block, by position rather than by
+// letter, so it lands in the same place on any keyboard layout.
+//
+// Controller buttons are bound as well: any number of buttons can press
+// the same CHIP-8 key, and the default maps the d-pad to 2/4/6/8 and A to
+// 5, the usual directions and fire button.
+//
+// A second player, for CHIP-8X and two-player programs, has a keypad of
+// their own and the second controller. Their keys 1-9 sit on the numeric
+// keypad's 3x3 block by position, with 0 on keypad 0, A on the keypad's
+// point, B on Enter, C on / and D on *; E and F are unbound.
+pub struct KeyMap {
+    players: [Bindings; 2],
+}
+
+struct Bindings {
+    scancodes: [Option<Scancode>; 16],
+    buttons: HashMap<Button, usize>,
+    stick: StickMapping,
+}
+
+impl Bindings {
+    fn new(scancodes: [Option<Scancode>; 16]) -> Self {
+        Bindings {
+            scancodes,
+            buttons: [
+                (Button::DPadUp, 0x2),
+                (Button::DPadLeft, 0x4),
+                (Button::DPadRight, 0x6),
+                (Button::DPadDown, 0x8),
+                (Button::A, 0x5),
+            ]
+            .iter()
+            .copied()
+            .collect(),
+            stick: StickMapping::default(),
+        }
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let player1 = [
+            Scancode::X,    // 0
+            Scancode::Num1, // 1
+            Scancode::Num2, // 2
+            Scancode::Num3, // 3
+            Scancode::Q,    // 4
+            Scancode::W,    // 5
+            Scancode::E,    // 6
+            Scancode::A,    // 7
+            Scancode::S,    // 8
+            Scancode::D,    // 9
+            Scancode::Z,    // A
+            Scancode::C,    // B
+            Scancode::Num4, // C
+            Scancode::R,    // D
+            Scancode::F,    // E
+            Scancode::V,    // F
+        ];
+        let player2 = [
+            Some(Scancode::Kp0),        // 0
+            Some(Scancode::Kp7),        // 1
+            Some(Scancode::Kp8),        // 2
+            Some(Scancode::Kp9),        // 3
+            Some(Scancode::Kp4),        // 4
+            Some(Scancode::Kp5),        // 5
+            Some(Scancode::Kp6),        // 6
+            Some(Scancode::Kp1),        // 7
+            Some(Scancode::Kp2),        // 8
+            Some(Scancode::Kp3),        // 9
+            Some(Scancode::KpPeriod),   // A
+            Some(Scancode::KpEnter),    // B
+            Some(Scancode::KpDivide),   // C
+            Some(Scancode::KpMultiply), // D
+            None,                       // E
+            None,                       // F
+        ];
+        KeyMap {
+            players: [Bindings::new(player1.map(Some)), Bindings::new(player2)],
+        }
+    }
+}
+
+impl KeyMap {
+    // Applies a binding of the form `<hex key>=<SDL scancode name>`, e.g.
+    // `5=Up` or `A=Space`, or `<hex key>=pad:<SDL button name>` for a
+    // controller button, e.g. `5=pad:b` or `2=pad:dpup`. Prefixed with
+    // `2:`, e.g. `2:5=Keypad 5`, it binds the second player's key instead.
+    // A keyboard key only ever presses one CHIP-8 key, so it is taken off
+    // whichever key had it before. `stick.<setting>=<value>` configures the
+    // controller's stick instead; see `StickMapping::set`.
+    pub fn bind(&mut self, binding: &str) -> Result<(), String> {
+        let (player, binding) = match binding.trim_start().strip_prefix("2:") {
+            Some(rest) => (1, rest),
+            None => (0, binding),
+        };
+        let (key, name) = binding
+            .split_once('=')
+            .ok_or_else(|| format!("Expected KEY=SCANCODE, got '{}'", binding))?;
+        let key = key.trim();
+        let name = name.trim();
+
+        if let Some(setting) = key.strip_prefix("stick.") {
+            return self.players[player].stick.set(setting, name);
+        }
+        let index = parse_key(key)?;
+        if let Some(button) = name.strip_prefix("pad:") {
+            let button = Button::from_string(button)
+                .ok_or_else(|| format!("'{}' is not a known controller button", button))?;
+            self.players[player].buttons.insert(button, index);
+            return Ok(());
+        }
+        let scancode = Scancode::from_name(name)
+            .ok_or_else(|| format!("'{}' is not a known key name", name))?;
+        for bindings in &mut self.players {
+            for slot in &mut bindings.scancodes {
+                if *slot == Some(scancode) {
+                    *slot = None;
+                }
+            }
+        }
+        self.players[player].scancodes[index] = Some(scancode);
+        Ok(())
+    }
+
+    // Reads one binding per line; blank lines and lines starting with # are
+    // skipped.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.bind(line)
+                .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+        }
+        Ok(())
+    }
+
+    // The key `scancode` presses on `player`'s keypad (0 or 1), if any.
+    fn key(&self, player: usize, scancode: Scancode) -> Option<usize> {
+        self.players[player]
+            .scancodes
+            .iter()
+            .position(|&s| s == Some(scancode))
+    }
+
+    fn stick(&self, player: usize) -> &StickMapping {
+        &self.players[player].stick
+    }
+
+    fn buttons(&self, player: usize) -> impl Iterator<Item = (Button, usize)> + '_ {
+        self.players[player]
+            .buttons
+            .iter()
+            .map(|(&button, &key)| (button, key))
+    }
+}
 
 pub struct Input {
     events: sdl2::EventPump,
+    controllers: Option<sdl2::GameControllerSubsystem>,
+    // One controller per player, in the order they were found.
+    pads: [Option<GameController>; 2],
+    keymap: KeyMap,
+    // The second player's keypad as of the last `poll`.
+    keys2: [bool; 16],
+    hotkeys: Vec<Hotkey>,
+    window_changed: bool,
+    focused: bool,
+    // The debug panel's window, whose events are set aside for it.
+    panel: Option<u32>,
+    panel_events: Vec<Event>,
+    panel_focused: bool,
 }
 
 impl Input {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+    pub fn new(sdl_context: &sdl2::Sdl, keymap: KeyMap) -> Self {
+        let controllers = sdl_context.game_controller().ok();
+        let mut pads = [None, None];
+        if let Some(controllers) = &controllers {
+            open_controllers(controllers, &mut pads);
+        }
+
         Input {
             events: sdl_context.event_pump().unwrap(),
+            controllers,
+            pads,
+            keymap,
+            keys2: [false; 16],
+            hotkeys: Vec::new(),
+            window_changed: false,
+            focused: true,
+            panel: None,
+            panel_events: Vec::new(),
+            panel_focused: false,
         }
     }
 
     pub fn poll(&mut self) -> Result<[bool; 16], ()> {
-        for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                return Err(());
+        let events: Vec<Event> = self.events.poll_iter().collect();
+        for event in events {
+            if self.panel.is_some() && window_of(&event) == self.panel {
+                self.panel_event(event);
+                continue;
+            }
+            match event {
+                Event::Quit { .. } => return Err(()),
+                Event::ControllerDeviceAdded { .. } => {
+                    if let Some(controllers) = &self.controllers {
+                        open_controllers(controllers, &mut self.pads);
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => self.window_changed = true,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => self.focused = false,
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => self.focused = true,
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => match key {
+                    Keycode::Escape => return Err(()),
+                    Keycode::Return if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                        self.hotkeys.push(Hotkey::ToggleFullscreen)
+                    }
+                    Keycode::F11 => self.hotkeys.push(Hotkey::ToggleFullscreen),
+                    Keycode::Num1
+                    | Keycode::Num2
+                    | Keycode::Num3
+                    | Keycode::Num4
+                    | Keycode::Num5
+                    | Keycode::Num6
+                    | Keycode::Num7
+                    | Keycode::Num8
+                    | Keycode::Num9
+                        if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                    {
+                        let n = key as i32 - Keycode::Num1 as i32;
+                        self.hotkeys.push(Hotkey::ToggleCheat(n as usize))
+                    }
+                    Keycode::F1 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.hotkeys.push(Hotkey::TogglePanel)
+                    }
+                    Keycode::P => self.hotkeys.push(Hotkey::Pause),
+                    Keycode::M => self.hotkeys.push(Hotkey::ToggleMute),
+                    Keycode::Backspace => self.hotkeys.push(Hotkey::Reset),
+                    Keycode::F2 => self.hotkeys.push(Hotkey::ToggleMemory),
+                    Keycode::F3 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.hotkeys.push(Hotkey::TogglePerf)
+                    }
+                    Keycode::F3 => self.hotkeys.push(Hotkey::ToggleHud),
+                    Keycode::F4 => self.hotkeys.push(Hotkey::ToggleSlowMotion),
+                    Keycode::PageUp => self.hotkeys.push(Hotkey::MemoryPageUp),
+                    Keycode::PageDown => self.hotkeys.push(Hotkey::MemoryPageDown),
+                    Keycode::Home => self.hotkeys.push(Hotkey::MemoryFollowPc),
+                    Keycode::F5 => self.hotkeys.push(Hotkey::SaveState),
+                    Keycode::F9 => self.hotkeys.push(Hotkey::LoadState),
+                    Keycode::F1 => self.hotkeys.push(Hotkey::ToggleDebugger),
+                    Keycode::F6 => self.hotkeys.push(Hotkey::FrameAdvance),
+                    Keycode::F7 => self.hotkeys.push(Hotkey::ToggleCrt),
+                    Keycode::F10 => self.hotkeys.push(Hotkey::Step),
+                    Keycode::F8 => self.hotkeys.push(Hotkey::RecordGif),
+                    Keycode::F12 => self.hotkeys.push(Hotkey::Screenshot),
+                    Keycode::Equals | Keycode::KpPlus => self.hotkeys.push(Hotkey::SpeedUp),
+                    Keycode::Minus | Keycode::KpMinus => self.hotkeys.push(Hotkey::SpeedDown),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let mut chip8_keys = [[false; 16]; 2];
+        // Typing into the debug panel shouldn't press CHIP-8 keys.
+        let keyboard = self.events.keyboard_state();
+        let scancodes = keyboard.pressed_scancodes().filter(|_| !self.panel_focused);
+        for scancode in scancodes {
+            for (player, keys) in chip8_keys.iter_mut().enumerate() {
+                if let Some(i) = self.keymap.key(player, scancode) {
+                    keys[i] = true;
+                }
+            }
+        }
+
+        for (player, pad) in self.pads.iter().enumerate() {
+            let controller = match pad.as_ref().filter(|c| c.attached()) {
+                Some(controller) => controller,
+                None => continue,
             };
+            let x = controller.axis(Axis::LeftX);
+            let y = controller.axis(Axis::LeftY);
+            if let Some(i) = self.keymap.stick(player).direction(x, y) {
+                chip8_keys[player][i] = true;
+            }
+            for (button, i) in self.keymap.buttons(player) {
+                if controller.button(button) {
+                    chip8_keys[player][i] = true;
+                }
+            }
         }
 
-        let keys: Vec<Keycode> = self
-            .events
+        self.keys2 = chip8_keys[1];
+        Ok(chip8_keys[0])
+    }
+
+    // The second player's keypad as of the last `poll`.
+    pub fn keys2(&self) -> [bool; 16] {
+        self.keys2
+    }
+
+    // Rewind and fast-forward last for as long as their key is held, so they
+    // are read as states rather than queued as hotkeys.
+    pub fn rewind_held(&self) -> bool {
+        self.events
             .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
-
-        let mut chip8_keys = [false; 16];
-
-        for key in keys {
-            let index = match key {
-                Keycode::Num1 => Some(0x1),
-                Keycode::Num2 => Some(0x2),
-                Keycode::Num3 => Some(0x3),
-                Keycode::Num4 => Some(0xc),
-                Keycode::Q => Some(0x4),
-                Keycode::W => Some(0x5),
-                Keycode::E => Some(0x6),
-                Keycode::R => Some(0xd),
-                Keycode::A => Some(0x7),
-                Keycode::S => Some(0x8),
-                Keycode::D => Some(0x9),
-                Keycode::F => Some(0xe),
-                Keycode::Z => Some(0xa),
-                Keycode::X => Some(0x0),
-                Keycode::C => Some(0xb),
-                Keycode::V => Some(0xf),
-                _ => None,
-            };
+            .is_scancode_pressed(Scancode::Tab)
+    }
+
+    pub fn fast_forward_held(&self) -> bool {
+        self.events
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Grave)
+    }
+
+    // Whether the window was resized or uncovered since the last call, so
+    // the screen has to be drawn again.
+    pub fn window_changed(&mut self) -> bool {
+        std::mem::take(&mut self.window_changed)
+    }
+
+    // Whether the window has keyboard focus.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    // Hotkeys pressed since the last call, oldest first.
+    pub fn hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
+
+    // Sets aside the events for the debug panel's window from now on, or
+    // stops if None.
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub fn set_panel(&mut self, window: Option<u32>) {
+        self.panel = window;
+        self.panel_events.clear();
+        self.panel_focused = false;
+    }
 
-            if let Some(i) = index {
-                chip8_keys[i] = true;
+    // Events for the debug panel since the last call, oldest first.
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub fn panel_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.panel_events)
+    }
+
+    // Focus moving between the game and the panel still counts as focused,
+    // and closing the panel hides it rather than quitting.
+    fn panel_event(&mut self, event: Event) {
+        match event {
+            Event::Window {
+                win_event: WindowEvent::FocusGained,
+                ..
+            } => {
+                self.focused = true;
+                self.panel_focused = true;
+            }
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => {
+                self.focused = false;
+                self.panel_focused = false;
             }
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => self.hotkeys.push(Hotkey::TogglePanel),
+            event => self.panel_events.push(event),
+        }
+    }
+}
+
+fn window_of(event: &Event) -> Option<u32> {
+    match *event {
+        Event::Window { window_id, .. }
+        | Event::KeyDown { window_id, .. }
+        | Event::KeyUp { window_id, .. }
+        | Event::TextEditing { window_id, .. }
+        | Event::TextInput { window_id, .. }
+        | Event::MouseMotion { window_id, .. }
+        | Event::MouseButtonDown { window_id, .. }
+        | Event::MouseButtonUp { window_id, .. }
+        | Event::MouseWheel { window_id, .. } => Some(window_id),
+        _ => None,
+    }
+}
+
+// Fills the empty slots in `pads` with controllers that aren't open yet.
+// Ones that were unplugged are replaced.
+fn open_controllers(
+    controllers: &sdl2::GameControllerSubsystem,
+    pads: &mut [Option<GameController>; 2],
+) {
+    for pad in pads.iter_mut() {
+        if pad.as_ref().is_some_and(|c| !c.attached()) {
+            *pad = None;
+        }
+    }
+    let count = controllers.num_joysticks().unwrap_or(0);
+    for i in (0..count).filter(|&i| controllers.is_game_controller(i)) {
+        let controller = match controllers.open(i) {
+            Ok(controller) => controller,
+            Err(_) => continue,
+        };
+        let id = controller.instance_id();
+        if pads.iter().flatten().any(|c| c.instance_id() == id) {
+            continue;
+        }
+        match pads.iter_mut().find(|pad| pad.is_none()) {
+            Some(free) => *free = Some(controller),
+            None => return,
         }
+    }
+}
+
+impl Keypad for Input {
+    fn keys(&mut self) -> Option<[bool; 16]> {
+        self.poll().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_used_key_moves_it() {
+        let mut keymap = KeyMap::default();
+        assert_eq!(keymap.key(0, Scancode::Q), Some(0x4));
+        keymap.bind("5=Q").unwrap();
+        assert_eq!(keymap.key(0, Scancode::Q), Some(0x5));
+        assert_eq!(keymap.players[0].scancodes[0x4], None);
+        assert_eq!(keymap.key(0, Scancode::W), None);
+
+        // Across keypads too.
+        keymap.bind("2:1=Q").unwrap();
+        assert_eq!(keymap.key(0, Scancode::Q), None);
+        assert_eq!(keymap.key(1, Scancode::Q), Some(0x1));
+    }
+
+    #[test]
+    fn binds_keys_and_buttons() {
+        let mut keymap = KeyMap::default();
+        keymap.bind(" A = Space ").unwrap();
+        keymap.bind("2:F=Up").unwrap();
+        keymap.bind("3=pad:x").unwrap();
+        assert_eq!(keymap.key(0, Scancode::Space), Some(0xA));
+        assert_eq!(keymap.key(1, Scancode::Up), Some(0xF));
+        assert!(keymap.buttons(0).any(|binding| binding == (Button::X, 0x3)));
+        assert!(!keymap.buttons(1).any(|(button, _)| button == Button::X));
+
+        assert!(keymap.bind("G=Space").is_err());
+        assert!(keymap.bind("5").is_err());
+        assert!(keymap.bind("5=NoSuchKey").is_err());
+        assert!(keymap.bind("5=pad:nosuchbutton").is_err());
+    }
+
+    #[test]
+    fn stick_has_a_dead_zone() {
+        let stick = StickMapping::default();
+        assert_eq!(stick.direction(8000, -8000), None);
+        assert_eq!(stick.direction(8001, 0), Some(0x6));
+        assert_eq!(stick.direction(-8001, 0), Some(0x4));
+        assert_eq!(stick.direction(0, -8001), Some(0x2));
+        assert_eq!(stick.direction(0, 8001), Some(0x8));
+        // The larger axis wins, x on a tie.
+        assert_eq!(stick.direction(9000, -9001), Some(0x2));
+        assert_eq!(stick.direction(-9000, 9000), Some(0x4));
+
+        let sensitive = StickMapping {
+            sensitivity: 2.0,
+            ..StickMapping::default()
+        };
+        assert_eq!(sensitive.direction(4000, 0), None);
+        assert_eq!(sensitive.direction(4001, 0), Some(0x6));
+    }
+
+    #[test]
+    fn binds_the_stick() {
+        let mut keymap = KeyMap::default();
+        keymap.bind("stick.deadzone=12000").unwrap();
+        keymap.bind("stick.up=5").unwrap();
+        keymap.bind("2:stick.sensitivity=0.5").unwrap();
+        assert_eq!(keymap.stick(0).direction(0, -12000), None);
+        assert_eq!(keymap.stick(0).direction(0, -12001), Some(0x5));
+        assert_eq!(keymap.stick(1).direction(0, -16001), Some(0x2));
+        assert_eq!(keymap.stick(1).direction(0, -16000), None);
+
+        assert!(keymap.bind("stick.deadzone=-1").is_err());
+        assert!(keymap.bind("stick.deadzone=40000").is_err());
+        assert!(keymap.bind("stick.sensitivity=0").is_err());
+        assert!(keymap.bind("stick.up=G").is_err());
+        assert!(keymap.bind("stick.turbo=1").is_err());
+    }
+
+    #[test]
+    fn loads_a_keymap_file() {
+        let path = std::env::temp_dir().join("chip8-input-test.keymap");
+        fs::write(&path, "# arrows\n\n5=Up\n4 = Left\n").unwrap();
+        let mut keymap = KeyMap::default();
+        keymap.load(path.to_str().unwrap()).unwrap();
+        assert_eq!(keymap.key(0, Scancode::Up), Some(0x5));
+        assert_eq!(keymap.key(0, Scancode::Left), Some(0x4));
+        assert_eq!(keymap.key(0, Scancode::Q), None);
 
-        Ok(chip8_keys)
+        fs::write(&path, "5=Up\nnonsense\n").unwrap();
+        let err = keymap.load(path.to_str().unwrap()).unwrap_err();
+        assert!(err.contains(":2: "), "{}", err);
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,356 @@
+// An experimental backend that compiles straight-line runs of CHIP-8
+// register arithmetic (6XNN, 7XNN, 8XYN, ANNN and FX1E) into native code
+// with Cranelift. Everything else, including every jump, skip, draw and
+// memory access, still goes through `CPU::cycle`, so the compiled blocks
+// only ever touch V0-VF and I.
+//
+// Blocks are cached by address along with the bytes they were compiled
+// from. A block whose code has changed since is compiled again, and one
+// that keeps changing, i.e. self-modifying code, is left to the
+// interpreter from then on. Blocks are compiled for the quirks in effect,
+// so changing them starts a fresh cache.
+//
+// Tracing, profiling and scripts see every instruction, so a CPU with any
+// of them set is simply interpreted.
+use std::collections::HashMap;
+use std::mem;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::error::Chip8Error;
+use crate::frame::Frame;
+use crate::processor::CPU;
+use crate::quirks::Quirks;
+
+// Longer runs are split, so a block never overshoots a frame by much.
+const MAX_BLOCK: usize = 64;
+// Times the code at an address may change before it is only interpreted.
+const MAX_REWRITES: u32 = 4;
+
+// Compiled code takes pointers to V0-VF and I.
+type Code = unsafe extern "C" fn(*mut u8, *mut u32);
+
+struct Entry {
+    // The code the entry was made from, to notice when it's overwritten.
+    bytes: Vec<u8>,
+    // None where the code can't be compiled or keeps changing.
+    code: Option<Code>,
+    rewrites: u32,
+}
+
+impl Entry {
+    fn instructions(&self) -> usize {
+        self.bytes.len() / 2
+    }
+}
+
+pub struct Jit {
+    module: JITModule,
+    builder: FunctionBuilderContext,
+    blocks: HashMap<usize, Entry>,
+    quirks: Quirks,
+}
+
+fn compilable(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x6000 | 0x7000 | 0xA000 => true,
+        0x8000 => matches!(opcode & 0xF, 0x0..=0x7 | 0xE),
+        0xF000 => opcode & 0xFF == 0x1E,
+        _ => false,
+    }
+}
+
+// The compilable instructions from `pc` on, as raw bytes.
+fn straight_line(memory: &[u8], pc: usize) -> Vec<u8> {
+    let code = memory.get(pc..).unwrap_or_default();
+    let count = code
+        .chunks_exact(2)
+        .take(MAX_BLOCK)
+        .take_while(|op| compilable((op[0] as u16) << 8 | op[1] as u16))
+        .count();
+    code[..count * 2].to_vec()
+}
+
+impl Jit {
+    // Fails if Cranelift can't generate code for this machine.
+    pub fn new() -> Result<Self, String> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()
+            .map_err(|e| e.to_string())?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| e.to_string())?;
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        Ok(Jit {
+            module,
+            builder: FunctionBuilderContext::new(),
+            blocks: HashMap::new(),
+            quirks: Quirks::default(),
+        })
+    }
+
+    // Like `CPU::run_frame`, running compiled blocks where it can.
+    pub fn run_frame(&mut self, cpu: &mut CPU, keypad: [bool; 16]) -> Result<Frame, Chip8Error> {
+        cpu.draw_flag = false;
+        let mut left = cpu.cycles_per_frame;
+        while left > 0 {
+            cpu.keypad = keypad;
+            match self.run_block(cpu, left) {
+                0 => {
+                    cpu.cycle(keypad)?;
+                    left -= 1;
+                }
+                executed => left -= executed,
+            }
+        }
+        cpu.end_frame()?;
+        Ok(cpu.frame())
+    }
+
+    // Runs the block at PC if there is a compiled one of at most `limit`
+    // instructions, returning how many it executed.
+    pub fn run_block(&mut self, cpu: &mut CPU, limit: usize) -> usize {
+        let hooked = cpu.tracer.is_some() || cpu.profiler.is_some();
+        #[cfg(feature = "lua")]
+        let hooked = hooked || cpu.script.is_some();
+        if hooked || cpu.halted || cpu.keypad_waiting || cpu.vblank_wait {
+            return 0;
+        }
+        if cpu.quirks != self.quirks {
+            self.blocks.clear();
+            self.quirks = cpu.quirks;
+        }
+
+        let entry = self.entry(cpu);
+        let count = entry.instructions();
+        let code = match entry.code {
+            Some(code) if count <= limit => code,
+            _ => return 0,
+        };
+        // The code only reads and writes the registers it's given.
+        unsafe { code(cpu.v.as_mut_ptr(), &mut cpu.i) };
+        let last = cpu.pc + count * 2 - 2;
+        cpu.opcode = (cpu.memory[last] as u16) << 8 | cpu.memory[last + 1] as u16;
+        cpu.pc += count * 2;
+        if !cpu.cheats.is_empty() {
+            cpu.cheats.apply(&mut cpu.memory);
+        }
+        count
+    }
+
+    // The cache entry for PC, made or remade if the code there changed.
+    fn entry(&mut self, cpu: &CPU) -> &Entry {
+        let pc = cpu.pc;
+        let current = match self.blocks.get(&pc) {
+            Some(entry) => cpu.memory.get(pc..pc + entry.bytes.len()) == Some(&entry.bytes[..]),
+            None => false,
+        };
+        if !current {
+            let bytes = straight_line(cpu.addressable(), pc);
+            let rewrites = self.blocks.get(&pc).map_or(0, |entry| entry.rewrites + 1);
+            let code = if bytes.is_empty() || rewrites > MAX_REWRITES {
+                None
+            } else {
+                self.compile(&bytes)
+            };
+            let entry = Entry {
+                bytes,
+                code,
+                rewrites,
+            };
+            self.blocks.insert(pc, entry);
+        }
+        &self.blocks[&pc]
+    }
+
+    // Number of addresses with compiled code, for reporting.
+    pub fn compiled_blocks(&self) -> usize {
+        self.blocks.values().filter(|e| e.code.is_some()).count()
+    }
+
+    // Compiles a run of compilable instructions. A failure just leaves the
+    // code to the interpreter.
+    fn compile(&mut self, bytes: &[u8]) -> Option<Code> {
+        let pointer = self.module.target_config().pointer_type();
+        let mut ctx = self.module.make_context();
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        let id = self
+            .module
+            .declare_anonymous_function(&ctx.func.signature)
+            .ok()?;
+
+        let mut b = FunctionBuilder::new(&mut ctx.func, &mut self.builder);
+        let block = b.create_block();
+        b.append_block_params_for_function_params(block);
+        b.switch_to_block(block);
+        b.seal_block(block);
+        let (v_ptr, i_ptr) = (b.block_params(block)[0], b.block_params(block)[1]);
+        let flags = MemFlags::trusted();
+
+        let v: Vec<Variable> = (0..16).map(Variable::from_u32).collect();
+        for (n, &var) in v.iter().enumerate() {
+            b.declare_var(var, types::I8);
+            let value = b.ins().load(types::I8, flags, v_ptr, n as i32);
+            b.def_var(var, value);
+        }
+        let i = Variable::from_u32(16);
+        b.declare_var(i, types::I32);
+        let value = b.ins().load(types::I32, flags, i_ptr, 0);
+        b.def_var(i, value);
+
+        for op in bytes.chunks_exact(2) {
+            let opcode = (op[0] as u16) << 8 | op[1] as u16;
+            let x = (opcode >> 8 & 0xF) as usize;
+            let y = (opcode >> 4 & 0xF) as usize;
+            let nn = (opcode & 0xFF) as i64;
+            let (vx, vy) = (b.use_var(v[x]), b.use_var(v[y]));
+            // The result for VX and then VF, which wins when X is F.
+            let (result, flag): (Value, Option<Value>) = match opcode & 0xF00F {
+                op if op & 0xF000 == 0x6000 => (b.ins().iconst(types::I8, nn), None),
+                op if op & 0xF000 == 0x7000 => (b.ins().iadd_imm(vx, nn), None),
+                op if op & 0xF000 == 0xA000 => {
+                    let nnn = b.ins().iconst(types::I32, (opcode & 0xFFF) as i64);
+                    b.def_var(i, nnn);
+                    continue;
+                }
+                op if op & 0xF000 == 0xF000 => {
+                    // MegaChip's 24-bit I is never compiled; see `Chip8::run_frame`.
+                    let offset = b.ins().uextend(types::I32, vx);
+                    let i_value = b.use_var(i);
+                    let sum = b.ins().iadd(i_value, offset);
+                    let sum = b.ins().band_imm(sum, 0xFFFF);
+                    b.def_var(i, sum);
+                    continue;
+                }
+                0x8000 => (vy, None),
+                0x8001..=0x8003 => {
+                    let result = match opcode & 0xF {
+                        1 => b.ins().bor(vx, vy),
+                        2 => b.ins().band(vx, vy),
+                        _ => b.ins().bxor(vx, vy),
+                    };
+                    let reset = self.quirks.vf_reset.then(|| b.ins().iconst(types::I8, 0));
+                    (result, reset)
+                }
+                0x8004 => {
+                    let sum = b.ins().iadd(vx, vy);
+                    (sum, Some(b.ins().icmp(IntCC::UnsignedLessThan, sum, vx)))
+                }
+                0x8005 => (
+                    b.ins().isub(vx, vy),
+                    Some(b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vx, vy)),
+                ),
+                0x8007 => (
+                    b.ins().isub(vy, vx),
+                    Some(b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vy, vx)),
+                ),
+                _ => {
+                    let value = if self.quirks.shift_uses_vy { vy } else { vx };
+                    if opcode & 0xF == 0x6 {
+                        (b.ins().ushr_imm(value, 1), Some(b.ins().band_imm(value, 1)))
+                    } else {
+                        (b.ins().ishl_imm(value, 1), Some(b.ins().ushr_imm(value, 7)))
+                    }
+                }
+            };
+            b.def_var(v[x], result);
+            if let Some(flag) = flag {
+                b.def_var(v[0xF], flag);
+            }
+        }
+
+        for (n, &var) in v.iter().enumerate() {
+            let value = b.use_var(var);
+            b.ins().store(flags, value, v_ptr, n as i32);
+        }
+        let value = b.use_var(i);
+        b.ins().store(flags, value, i_ptr, 0);
+        b.ins().return_(&[]);
+        b.finalize();
+
+        self.module.define_function(id, &mut ctx).ok()?;
+        self.module.clear_context(&mut ctx);
+        self.module.finalize_definitions().ok()?;
+        let code = self.module.get_finalized_function(id);
+        // The signature was declared to match `Code` above.
+        Some(unsafe { mem::transmute::<*const u8, Code>(code) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Arithmetic on every register with flags, I updates, and a draw and
+    // jump in between so blocks start and end mid-program.
+    const PROGRAM: &[u8] = &[
+        0x60, 0xF0, 0x61, 0x25, 0x62, 0x0F, 0x63, 0x81, // V0-V3
+        0x80, 0x14, 0x84, 0x05, 0x85, 0x37, 0x86, 0x26, // add, subs, shift
+        0x87, 0x3E, 0x88, 0x21, 0x89, 0x32, 0x8A, 0x13, // shift, or, and, xor
+        0x70, 0x33, 0xA3, 0x00, 0xF1, 0x1E, 0x8F, 0x04, // add, I, I += V1, VF = carry
+        0xD0, 0x15, 0x8E, 0x00, 0x8E, 0x16, 0x12, 0x00, // draw, VE = V0 >> 1, loop
+    ];
+
+    fn run(cpu: &mut CPU, jit: Option<&mut Jit>) {
+        cpu.load_bytes(PROGRAM).unwrap();
+        cpu.cycles_per_frame = 50;
+        match jit {
+            Some(jit) => {
+                for _ in 0..5 {
+                    jit.run_frame(cpu, [false; 16]).unwrap();
+                }
+            }
+            None => {
+                for _ in 0..5 {
+                    cpu.run_frame([false; 16]).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_interpreter_under_every_quirk() {
+        let mut jit = Jit::new().unwrap();
+        for quirks in [Quirks::default(), Quirks::vip(), Quirks::schip()] {
+            let (mut interpreted, mut compiled) = (CPU::new(), CPU::new());
+            interpreted.quirks = quirks;
+            compiled.quirks = quirks;
+            run(&mut interpreted, None);
+            run(&mut compiled, Some(&mut jit));
+            assert_eq!(compiled.v, interpreted.v, "{}", quirks);
+            assert_eq!(
+                (compiled.i, compiled.pc, compiled.opcode),
+                (interpreted.i, interpreted.pc, interpreted.opcode)
+            );
+            assert_eq!(
+                compiled.frame().to_string(),
+                interpreted.frame().to_string()
+            );
+        }
+        assert!(jit.compiled_blocks() > 0);
+    }
+
+    #[test]
+    fn recompiles_changed_code_and_gives_up_on_self_modifying_code() {
+        let mut jit = Jit::new().unwrap();
+        let mut cpu = CPU::new();
+        // V0 += 1, loop.
+        cpu.load_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        cpu.cycles_per_frame = 2;
+        jit.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!(cpu.v[0], 1);
+
+        for n in 2..=MAX_REWRITES as u8 + 2 {
+            cpu.memory[0x201] = n;
+            jit.run_frame(&mut cpu, [false; 16]).unwrap();
+        }
+        assert_eq!(cpu.v[0], (2..=MAX_REWRITES as u8 + 2).sum::<u8>() + 1);
+        assert_eq!(jit.compiled_blocks(), 0);
+    }
+}
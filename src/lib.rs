@@ -0,0 +1,102 @@
+// Without the default `std` feature only the interpreter core is built:
+// the CPU, framebuffer, font, quirks and frames. It is no_std and needs an
+// allocator but nothing else from the platform, so it can run on a
+// microcontroller. Load ROMs with `CPU::load_bytes` and seed CXNN with
+// `CPU::set_seed` there.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "image")]
+pub mod animation;
+#[cfg(feature = "zip")]
+pub mod archive;
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod blocks;
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod cheat;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
+#[cfg(feature = "std")]
+pub mod emulator;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod frame;
+pub mod framebuffer;
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod gdb;
+pub mod headless;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod megachip;
+#[cfg(feature = "std")]
+pub mod pacing;
+pub mod palette;
+#[cfg(feature = "std")]
+pub mod phosphor;
+pub mod processor;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod programs;
+#[cfg(feature = "std")]
+pub mod project;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod recent;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(feature = "std")]
+pub mod romdb;
+#[cfg(feature = "std")]
+pub mod rpl;
+#[cfg(feature = "std")]
+pub mod savestate;
+#[cfg(feature = "image")]
+pub mod screenshot;
+#[cfg(feature = "lua")]
+pub mod script;
+mod serde_arrays;
+#[cfg(feature = "std")]
+pub mod settings;
+#[cfg(feature = "cpal")]
+pub mod sound;
+#[cfg(feature = "std")]
+pub mod tone;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "wasm")]
+pub mod web;
+
+pub use builder::CpuBuilder;
+#[cfg(feature = "std")]
+pub use debugger::Debugger;
+#[cfg(feature = "std")]
+pub use disasm::Instruction;
+#[cfg(feature = "std")]
+pub use emulator::{Chip8, Step};
+pub use error::Chip8Error;
+pub use frame::Frame;
+pub use frontend::{Buzzer, Keypad, Screen};
+pub use headless::Halt;
+pub use palette::Palette;
+pub use processor::{Framebuffer, CPU};
+pub use quirks::Quirks;
@@ -1,29 +1,1157 @@
-use std::thread;
-use std::time::Duration;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 
-use sdl2;
+use chip8::cheat::Cheats;
+use chip8::profile::Profiler;
+use chip8::programs::{self, Program, Programs};
+#[cfg(feature = "sdl")]
+use chip8::project::DebugProject;
+use chip8::recent::RecentRoms;
+use chip8::romdb::RomDb;
+use chip8::rpl::RplFile;
+use chip8::settings;
+#[cfg(any(feature = "sdl", feature = "cpal"))]
+use chip8::tone::Tone;
+use chip8::trace::Tracer;
+use chip8::{asm, disasm, Chip8, Chip8Error, Quirks};
+
+// With cpal, the SDL frontend plays sound through `chip8::sound` instead.
+#[cfg(all(feature = "sdl", not(feature = "cpal")))]
+mod audio;
+#[cfg(feature = "sdl")]
+mod browser;
+mod cli;
+#[cfg(feature = "sdl")]
 mod display;
-mod font;
+#[cfg(feature = "sdl")]
+mod hud;
+#[cfg(feature = "sdl")]
 mod input;
-mod processor;
+#[cfg(feature = "sdl")]
+mod memview;
+#[cfg(feature = "egui")]
+mod panel;
+#[cfg(feature = "sdl")]
+mod perf;
+mod picker;
+#[cfg(all(feature = "sdl", feature = "image"))]
+mod thumbnail;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(all(not(feature = "sdl"), feature = "winit"))]
+mod window;
 
 fn main() {
-    let file_name = "Astro Dodge [Revival Studios, 2008].ch8";
-    let mut cpu = processor::CPU::new();
-    cpu.load(file_name);
+    let options = match cli::Command::from_args(env::args().skip(1)) {
+        Ok(cli::Command::Run(options)) => *options,
+        Ok(cli::Command::Recent) => {
+            list_recent();
+            return;
+        }
+        Ok(cli::Command::Disasm(rom)) => {
+            disassemble(&rom);
+            return;
+        }
+        Ok(cli::Command::Asm { source, output }) => {
+            assemble(&source, output);
+            return;
+        }
+        Err(cli::Error::Cancelled) => return,
+        Err(e @ cli::Error::Help) => {
+            println!("{}", e);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let mut chip8 = Chip8::new();
+    if options.megachip {
+        chip8.set_megachip();
+    }
+    let program = archive_program(&options);
+    let tickrate = program.as_ref().and_then(|program| program.tickrate);
+    chip8.set_speed(match tickrate {
+        Some(tickrate) if !options.speed_chosen => tickrate,
+        _ => options.speed,
+    });
+    let quirks = match &program {
+        Some(program) if !options.quirks_chosen => Some(program.quirks),
+        _ => known_rom_quirks(&options),
+    };
+    chip8.set_quirks(quirks.unwrap_or(options.quirks));
+    if let Some(seed) = options.seed {
+        chip8.set_seed(seed);
+    }
+    if let Some(path) = &options.trace {
+        let tracer = if path == "-" {
+            Ok(Tracer::stderr())
+        } else {
+            Tracer::file(path)
+        };
+        match tracer {
+            Ok(tracer) => chip8.set_tracer(Some(tracer)),
+            Err(e) => {
+                eprintln!("Could not create {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+    let profiler = if options.profile {
+        Some(Profiler::new())
+    } else {
+        None
+    };
+    chip8.set_profiler(profiler.clone());
+    load_cheats(&mut chip8, &options);
+    if let Some(path) = &options.script {
+        load_script(&mut chip8, path);
+    }
+    if options.jit {
+        enable_jit(&mut chip8);
+    }
+    let loaded = read_rom(&options.rom, options.entry.as_deref())
+        .and_then(|rom| chip8.load_bytes(&rom).map(|()| rom));
+    let rom = match loaded {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Could not load {}: {}", options.rom, e);
+            process::exit(1);
+        }
+    };
+    for (addr, path) in &options.memory_loads {
+        if let Err(e) = chip8.cpu_mut().load_memory(*addr, path) {
+            eprintln!("Could not load {} into memory: {}", path, e);
+            process::exit(1);
+        }
+    }
+    // Failing to update the list shouldn't get in the way of playing.
+    let mut recent = RecentRoms::load();
+    recent.add(&options.rom);
+    let _ = recent.save();
+    let mut rpl = rpl_file(&options);
+    if let Some(rpl) = &mut rpl {
+        if let Err(e) = rpl.load(chip8.cpu_mut()) {
+            eprintln!(
+                "Could not load RPL flags from {}: {}",
+                rpl.path().display(),
+                e
+            );
+        }
+    }
+    let resume_file = resume_path(&options, &rom);
+    let resumed = resume_file
+        .as_deref()
+        .is_some_and(|path| resume(&mut chip8, path));
+
+    let result = if options.headless {
+        chip8.run_headless(options.cycles).map(|halt| {
+            print!("{}", chip8.cpu().frame());
+            println!("{}", halt);
+        })
+    } else if options.tui {
+        run_tui(&mut chip8, &options)
+    } else {
+        run_window(&mut chip8, &options, resumed)
+    };
+
+    if let Some(rpl) = &mut rpl {
+        save_rpl(&chip8, rpl);
+    }
+    // A program that stopped with an error or exited has nothing to resume.
+    if let Some(path) = &resume_file {
+        if result.is_ok() && !chip8.cpu().halted {
+            save_resume(&chip8, path);
+        }
+    }
+
+    if let Some(profiler) = profiler {
+        eprint!("{}", profiler.profile());
+    }
+    for (addr, len, path) in &options.dumps {
+        match chip8.cpu().dump_memory(*addr, *len, path) {
+            Ok(()) => eprintln!("Dumped {:X} bytes from {:03X} to {}", len, addr, path),
+            Err(e) => eprintln!("Could not dump memory to {}: {}", path, e),
+        }
+    }
+    if let Err(e) = result {
+        eprintln!("Emulation stopped: {}", e);
+        process::exit(1);
+    }
+}
+
+// The buzzer's tone from the config directory's tone.txt, then <rom>.tone
+// for this game, then --volume and --audio-buffer.
+#[cfg(any(feature = "sdl", feature = "cpal"))]
+fn load_tone(options: &cli::Options) -> Tone {
+    let mut tone = Tone::default();
+    let game_tone = Path::new(&options.rom).with_extension("tone");
+    let toned = chip8::config::config_dir()
+        .map(|dir| dir.join("tone.txt"))
+        .into_iter()
+        .chain(Some(game_tone))
+        .filter(|path| path.exists())
+        .try_for_each(|path| tone.apply_file(path));
+    if let Err(e) = toned {
+        eprintln!("{}", e);
+        process::exit(2);
+    }
+    if let Some(percent) = options.volume {
+        tone.volume = percent / 100.0;
+    }
+    if options.audio_buffer.is_some() {
+        tone.buffer = options.audio_buffer;
+    }
+    tone
+}
+
+// Remembers the mute hotkey's setting in the config directory's tone.txt.
+#[cfg(feature = "sdl")]
+fn save_muted(muted: bool) {
+    let path = match chip8::config::config_dir() {
+        Some(dir) => dir.join("tone.txt"),
+        None => return,
+    };
+    let value = if muted { "yes" } else { "no" };
+    if let Err(e) = chip8::tone::save_setting(&path, "muted", value) {
+        eprintln!("Could not save {}: {}", path.display(), e);
+    }
+}
+
+// Remembers a setting changed while playing in the config directory's
+// settings.txt.
+#[cfg(feature = "sdl")]
+fn save_setting(name: &str, value: &str) {
+    let path = match chip8::settings::Settings::path() {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(e) = chip8::tone::save_setting(&path, name, value) {
+        eprintln!("Could not save {}: {}", path.display(), e);
+    }
+}
+
+// Writes back the speed, scale and CRT effect if they were changed while
+// playing. The speed is only kept when the game started at the one from
+// settings.txt: a speed from --speed, programs.json or MegaChip belongs to
+// that game, and so does changing it.
+#[cfg(feature = "sdl")]
+fn save_settings(
+    chip8: &Chip8,
+    start_speed: usize,
+    speed_changed: bool,
+    display: &display::Display,
+    options: &cli::Options,
+) {
+    let saved_speed = options
+        .settings
+        .speed
+        .unwrap_or(chip8::frame::CYCLES_PER_FRAME);
+    let own_speed = options.speed_chosen || options.megachip || start_speed != saved_speed;
+    if speed_changed && !own_speed && chip8.speed() != saved_speed {
+        save_setting("speed", &chip8.speed().to_string());
+    }
+    if let Some(scale) = display.scale().filter(|&scale| scale != options.scale) {
+        save_setting("scale", &scale.to_string());
+    }
+    if display.crt() != options.crt {
+        save_setting("crt", if display.crt() { "yes" } else { "no" });
+    }
+}
+
+// Sound through cpal, or none if the output device can't be opened.
+#[cfg(feature = "cpal")]
+fn open_speaker(tone: Tone) -> Option<chip8::sound::Speaker> {
+    chip8::sound::Speaker::new(tone)
+        .map_err(|e| eprintln!("Audio disabled: {}", e))
+        .ok()
+}
+
+// Cheats from --cheats, or else <ROM>.cheats if there is one.
+fn load_cheats(chip8: &mut Chip8, options: &cli::Options) {
+    let game_cheats = Path::new(&options.rom).with_extension("cheats");
+    let path = match &options.cheats {
+        Some(path) => PathBuf::from(path),
+        None if game_cheats.exists() => game_cheats,
+        None => return,
+    };
+    match Cheats::load(&path) {
+        Ok(cheats) => {
+            for (n, cheat) in cheats.iter().enumerate() {
+                eprintln!("Cheat {}: {}", n + 1, cheat.name);
+            }
+            chip8.set_cheats(cheats);
+        }
+        Err(e) => {
+            eprintln!("Could not load cheats: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "lua")]
+fn load_script(chip8: &mut Chip8, path: &str) {
+    match chip8::script::Script::load(path) {
+        Ok(script) => chip8.set_script(Some(script)),
+        Err(e) => {
+            eprintln!("Could not load {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
 
-    let sleep_duration = Duration::from_millis(2);
+#[cfg(not(feature = "lua"))]
+fn load_script(_: &mut Chip8, _: &str) {
+    eprintln!("This build cannot run scripts; rebuild with --features lua");
+    process::exit(1);
+}
+
+#[cfg(feature = "jit")]
+fn enable_jit(chip8: &mut Chip8) {
+    if let Err(e) = chip8.set_jit(true) {
+        eprintln!("Could not start the JIT: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn enable_jit(_: &mut Chip8) {
+    eprintln!("This build has no JIT; rebuild with --features jit");
+    process::exit(1);
+}
+
+// Reads a ROM file, or the ROM `entry` names inside a .zip.
+#[cfg(feature = "zip")]
+fn read_rom(path: &str, entry: Option<&str>) -> Result<Vec<u8>, Chip8Error> {
+    chip8::archive::read_rom(Path::new(path), entry)
+}
+
+#[cfg(not(feature = "zip"))]
+fn read_rom(path: &str, entry: Option<&str>) -> Result<Vec<u8>, Chip8Error> {
+    if entry.is_some() || path.to_ascii_lowercase().ends_with(".zip") {
+        eprintln!("This build cannot load ROMs from .zip files; rebuild with --features zip");
+        process::exit(1);
+    }
+    chip8::processor::read_rom(path)
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(chip8: &mut Chip8, options: &cli::Options) -> Result<(), Chip8Error> {
+    let terminal = match tui::Terminal::new() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            eprintln!("Could not set up the terminal: {}", e);
+            process::exit(1);
+        }
+    };
+    #[cfg(feature = "cpal")]
+    // Without a mute hotkey, muted means no sound at all.
+    let mut buzzer = Some(load_tone(options))
+        .filter(|tone| !tone.muted)
+        .and_then(open_speaker);
+    #[cfg(not(feature = "cpal"))]
+    let mut buzzer = ();
+    let result = chip8.run(
+        &mut terminal.screen(options.palette),
+        terminal.keys(),
+        &mut buzzer,
+    );
+    // Restore the terminal before anything is printed to it.
+    drop(terminal);
+    result
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_chip8: &mut Chip8, _options: &cli::Options) -> Result<(), Chip8Error> {
+    eprintln!("This build has no terminal frontend; rebuild with --features tui");
+    process::exit(2);
+}
+
+#[cfg(all(not(feature = "sdl"), feature = "winit"))]
+fn run_window(chip8: &mut Chip8, options: &cli::Options, _resumed: bool) -> Result<(), Chip8Error> {
+    #[cfg(feature = "cpal")]
+    let buzzer = Some(load_tone(options))
+        .filter(|tone| !tone.muted)
+        .and_then(open_speaker);
+    #[cfg(not(feature = "cpal"))]
+    let buzzer = ();
+    window::run(
+        chip8,
+        options.scale,
+        options.palette,
+        options.phosphor,
+        buzzer,
+    )
+}
+
+#[cfg(not(any(feature = "sdl", feature = "winit")))]
+fn run_window(
+    _chip8: &mut Chip8,
+    _options: &cli::Options,
+    _resumed: bool,
+) -> Result<(), Chip8Error> {
+    eprintln!("This build has no windowed frontend; use --tui or --headless");
+    process::exit(2);
+}
+
+#[cfg(feature = "sdl")]
+fn run_window(chip8: &mut Chip8, options: &cli::Options, resumed: bool) -> Result<(), Chip8Error> {
+    use std::time::Instant;
+
+    use chip8::gdb;
+    use chip8::pacing::FrameClock;
+    use chip8::phosphor::Phosphor;
+    use chip8::replay::Recording;
+    use chip8::rewind::Rewind;
+    use chip8::{Buzzer, Debugger, Keypad};
+    use input::Hotkey;
+
+    // Frames run per frame shown while fast-forwarding, and frames shown
+    // per frame run in slow motion.
+    const FAST_FORWARD_FRAMES: usize = 8;
+    const SLOW_MOTION_FRAMES: usize = 4;
+    // How long the window says a game was resumed, in frames shown.
+    const RESUMED_NOTICE_FRAMES: usize = 180;
+
+    // Bindings for this game, `<rom>.keymap` next to the ROM, go on top of
+    // --keymap and are overridden by --key.
+    let mut keymap = input::KeyMap::default();
+    let game_keymap = Path::new(&options.rom).with_extension("keymap");
+    let bound = options
+        .keymap
+        .iter()
+        .map(String::as_str)
+        .chain(game_keymap.to_str().filter(|_| game_keymap.exists()))
+        .try_for_each(|path| keymap.load(path))
+        .and_then(|()| options.bindings.iter().try_for_each(|b| keymap.bind(b)));
+    if let Err(e) = bound {
+        eprintln!("{}", e);
+        process::exit(2);
+    }
 
     let sdl_context = sdl2::init().unwrap();
-    let mut display = display::Display::new(&sdl_context);
-    let mut input = input::Input::new(&sdl_context);
+    let mut display =
+        display::Display::new(&sdl_context, options.scale, options.palette, options.vsync);
+    display.set_phosphor(options.phosphor.map(Phosphor::new));
+    display.set_crt(options.crt);
+    let mut input = input::Input::new(&sdl_context, keymap);
+    let tone = load_tone(options);
+    let mut muted = tone.muted;
+    #[cfg(feature = "cpal")]
+    let mut audio = open_speaker(tone);
+    #[cfg(not(feature = "cpal"))]
+    let mut audio = audio::Audio::new(&sdl_context, tone)
+        .map_err(|e| eprintln!("Audio disabled: {}", e))
+        .ok();
+
+    #[cfg(feature = "image")]
+    let mut thumbnail = thumbnail::ThumbnailCapture::new(Path::new(&options.rom));
+
+    let mut memory_viewer = memview::MemoryViewer::default();
+    let mut hud = hud::RegisterHud::default();
+    let mut perf = perf::PerfOverlay::default();
+    #[cfg(feature = "egui")]
+    let mut panel: Option<panel::Panel> = None;
+
+    let mut debugger = Debugger::new();
+    for &addr in &options.breakpoints {
+        debugger.add_breakpoint(addr);
+    }
+    for pattern in &options.opcode_breakpoints {
+        debugger.add_opcode_breakpoint(pattern.clone());
+    }
+    for &watch in &options.watchpoints {
+        debugger.add_watchpoint(watch);
+    }
+    let project = load_project(&options.rom, &mut debugger, &mut memory_viewer);
+    let mut gdb = options
+        .gdb
+        .map(|port| match gdb::Server::bind(("127.0.0.1", port)) {
+            Ok(server) => {
+                eprintln!("Waiting for GDB on 127.0.0.1:{}", port);
+                server
+            }
+            Err(e) => {
+                eprintln!("Could not listen for GDB on port {}: {}", port, e);
+                process::exit(1);
+            }
+        });
+    #[cfg(feature = "remote")]
+    let mut remote =
+        options.remote.map(
+            |port| match chip8::remote::Server::bind(("127.0.0.1", port)) {
+                Ok(server) => {
+                    eprintln!("Serving the debugging API on ws://127.0.0.1:{}", port);
+                    server
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Could not listen for WebSocket clients on port {}: {}",
+                        port, e
+                    );
+                    process::exit(1);
+                }
+            },
+        );
+    #[cfg(not(feature = "remote"))]
+    if options.remote.is_some() {
+        eprintln!("This build has no WebSocket debugging API; rebuild with --features remote");
+        process::exit(1);
+    }
+
+    let state_path = Path::new(&options.rom).with_extension("state");
+
+    let replay = options
+        .replay
+        .as_ref()
+        .map(|path| match Recording::load(path) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("Could not load {}: {}", path, e);
+                process::exit(1);
+            }
+        });
+    if let Some(replay) = &replay {
+        chip8.set_seed(replay.seed);
+    }
+    let mut playback = replay.as_ref().map(Recording::playback);
+    let mut rewind = Rewind::default();
+    #[cfg(feature = "image")]
+    let mut gif = None;
+    let mut recording = options
+        .record
+        .as_ref()
+        .map(|path| (path, Recording::new(chip8.cpu().rng)));
+
+    let mut slow_motion = false;
+    let mut slow_frames = 0;
+    let mut clock = FrameClock::new();
+    let start_speed = chip8.speed();
+    let mut speed_changed = false;
+    let mut resumed_notice = if resumed { RESUMED_NOTICE_FRAMES } else { 0 };
+    // Instructions run since the last frame shown, for the perf overlay.
+    let mut ran = 0;
+    while let Ok(keys) = input.poll() {
+        perf.frame(std::mem::take(&mut ran));
+        chip8.set_keys2(input.keys2());
+        let mut stepped = false;
+        let mut advanced = false;
+        let mut redraw = input.window_changed();
+        for hotkey in input.hotkeys() {
+            match hotkey {
+                Hotkey::SaveState => match chip8.cpu().save_state_file(&state_path) {
+                    Ok(()) => eprintln!("Saved state to {}", state_path.display()),
+                    Err(e) => eprintln!("Could not save state: {}", e),
+                },
+                Hotkey::LoadState => match chip8.cpu_mut().load_state_file(&state_path) {
+                    Ok(()) => eprintln!("Loaded state from {}", state_path.display()),
+                    Err(e) => eprintln!("Could not load state: {}", e),
+                },
+                Hotkey::Pause => {
+                    debugger.toggle_pause();
+                    eprintln!(
+                        "{}",
+                        if debugger.is_paused() {
+                            "Paused"
+                        } else {
+                            "Resumed"
+                        }
+                    );
+                }
+                Hotkey::Reset => {
+                    chip8.reset();
+                    rewind.clear();
+                    // Recordings and replays start over with the ROM so
+                    // they stay in step with it.
+                    if let Some(replay) = &replay {
+                        chip8.set_seed(replay.seed);
+                        playback = Some(replay.playback());
+                    }
+                    if let Some((_, recording)) = &mut recording {
+                        recording.frames.clear();
+                    }
+                    resumed_notice = 0;
+                    eprintln!("Reset");
+                }
+                Hotkey::ToggleDebugger => {
+                    debugger.toggle_pause();
+                    if debugger.is_paused() {
+                        println!("{}", debugger.view(chip8.cpu()));
+                    }
+                }
+                Hotkey::Step => {
+                    stepped = debugger.is_paused();
+                    debugger.step();
+                }
+                Hotkey::FrameAdvance => {
+                    advanced = debugger.is_paused();
+                    debugger.advance_frame();
+                }
+                Hotkey::SpeedUp | Hotkey::SpeedDown => {
+                    let speed = chip8.speed();
+                    // Steps by a quarter so the range from sluggish to fast
+                    // is a handful of presses, but never by less than one.
+                    let speed = if hotkey == Hotkey::SpeedUp {
+                        (speed + 1).max(speed * 5 / 4)
+                    } else {
+                        (speed - 1).min(speed * 4 / 5).max(1)
+                    };
+                    chip8.set_speed(speed);
+                    speed_changed = true;
+                    eprintln!(
+                        "Speed: {} instructions per frame ({} per second)",
+                        speed,
+                        speed * 60
+                    );
+                }
+                Hotkey::ToggleSlowMotion => {
+                    slow_motion = !slow_motion;
+                    eprintln!("Slow motion {}", if slow_motion { "on" } else { "off" });
+                }
+                Hotkey::ToggleMute => {
+                    muted = !muted;
+                    eprintln!("Sound {}", if muted { "muted" } else { "on" });
+                    save_muted(muted);
+                }
+                Hotkey::ToggleMemory => memory_viewer.toggle(),
+                Hotkey::ToggleHud => hud.toggle(),
+                Hotkey::TogglePerf => perf.toggle(),
+                Hotkey::ToggleFullscreen => {
+                    display.toggle_fullscreen();
+                    redraw = true;
+                }
+                Hotkey::ToggleCrt => {
+                    display.set_crt(!display.crt());
+                    redraw = true;
+                }
+                Hotkey::MemoryPageUp => memory_viewer.scroll(chip8.cpu(), -1),
+                Hotkey::MemoryPageDown => memory_viewer.scroll(chip8.cpu(), 1),
+                Hotkey::MemoryFollowPc => memory_viewer.follow_pc(),
+                Hotkey::Screenshot => screenshot(chip8, &display, options),
+                #[cfg(feature = "image")]
+                Hotkey::RecordGif => toggle_gif(&mut gif, &display, options),
+                #[cfg(not(feature = "image"))]
+                Hotkey::RecordGif => {
+                    eprintln!("This build cannot record GIFs; rebuild with --features image")
+                }
+                Hotkey::ToggleCheat(n) => match chip8.cpu_mut().cheats.toggle(n) {
+                    Some(cheat) => eprintln!(
+                        "Cheat {} {}",
+                        cheat.name,
+                        if cheat.enabled { "on" } else { "off" }
+                    ),
+                    None => eprintln!("There is no cheat {}", n + 1),
+                },
+                #[cfg(feature = "egui")]
+                Hotkey::TogglePanel => {
+                    panel = match panel {
+                        Some(_) => None,
+                        None => Some(panel::Panel::new(&sdl_context)),
+                    };
+                    input.set_panel(panel.as_ref().map(panel::Panel::window_id));
+                }
+                #[cfg(not(feature = "egui"))]
+                Hotkey::TogglePanel => {
+                    eprintln!("This build has no debug panel; rebuild with --features egui")
+                }
+            }
+        }
+
+        if let Some(gdb) = &mut gdb {
+            chip8.with_cpu_mut(|cpu| gdb.poll(&mut debugger, cpu));
+        }
+        #[cfg(feature = "remote")]
+        if let Some(remote) = &mut remote {
+            chip8.with_cpu_mut(|cpu| remote.poll(&mut debugger, cpu));
+        }
+        #[cfg(feature = "egui")]
+        if let Some(panel) = &mut panel {
+            for event in input.panel_events() {
+                panel.handle(&event);
+            }
+            chip8.with_cpu_mut(|cpu| panel.draw(&mut debugger, cpu));
+        }
 
-    while let Ok(keypad) = input.poll() {
-        cpu.cycle(keypad);
+        let status = if debugger.is_paused() {
+            Some("paused")
+        } else if input.fast_forward_held() {
+            Some("fast-forward")
+        } else if slow_motion {
+            Some("slow motion")
+        } else {
+            None
+        };
+        display.set_title(&window_title(&options.rom, chip8.speed(), status));
 
-        if cpu.draw_flag {
-            display.draw(&cpu.gfx);
+        // Frames are run for the time that has passed, so a late wake-up
+        // or a slow frame is made up on the next one. Waking early, or a
+        // display refreshing faster than 60 Hz, runs none.
+        let due = clock.tick(Instant::now());
+        if due == 0 {
+            next_frame(
+                &mut display,
+                || chip8.cpu().frame(),
+                redraw,
+                &clock,
+                options.vsync,
+            );
+            continue;
+        }
+
+        // Nothing runs while the player is in another window, so a game
+        // doesn't carry on without them. Like a pause, this consumes no
+        // recorded input.
+        if options.pause_unfocused && !input.focused() {
+            audio.set_playing(false);
+            next_frame(
+                &mut display,
+                || chip8.cpu().frame(),
+                redraw,
+                &clock,
+                options.vsync,
+            );
+            continue;
+        }
+
+        // Rewinding would leave a recording or replay out of step with the
+        // frames that actually ran, so it is only available in free play.
+        let can_rewind = recording.is_none() && playback.is_none();
+        if can_rewind && input.rewind_held() && !debugger.is_paused() {
+            let rewound = rewind.rewind(chip8.cpu_mut());
+            if rewound {
+                audio.set_playing(false);
+                display.fade(&chip8.cpu().frame());
+            }
+            next_frame(
+                &mut display,
+                || chip8.cpu().frame(),
+                rewound,
+                &clock,
+                options.vsync,
+            );
+            continue;
+        }
+
+        // Slow motion runs a frame only every few frames shown. The
+        // emulated machine as a whole slows down, so its timers and sound
+        // stay in step with the program; the skipped frames are like
+        // pauses and consume no recorded input.
+        let fast_forward = input.fast_forward_held();
+        if slow_motion && !fast_forward && !debugger.is_paused() {
+            slow_frames = (slow_frames + 1) % SLOW_MOTION_FRAMES;
+            if slow_frames != 0 {
+                next_frame(
+                    &mut display,
+                    || chip8.cpu().frame(),
+                    redraw,
+                    &clock,
+                    options.vsync,
+                );
+                continue;
+            }
+        }
+
+        // Fast-forward runs several whole frames, timers included, for
+        // each one shown, so waits on the delay timer pass quickly too.
+        let frames = if fast_forward && !debugger.is_paused() {
+            due * FAST_FORWARD_FRAMES
+        } else {
+            due
+        };
+        let mut drawn = false;
+        let mut frame = chip8.cpu().frame();
+        for _ in 0..frames {
+            // Paused frames execute nothing, so they neither consume nor
+            // produce recorded input. An advanced frame is a whole frame
+            // and counts like a running one.
+            let mut keys = keys;
+            if !debugger.is_paused() || advanced {
+                ran += chip8.speed();
+                match playback.as_mut().map(|playback| playback.keys()) {
+                    Some(Some(recorded)) => keys = recorded,
+                    Some(None) => {
+                        eprintln!("Replay finished; the keyboard is live again");
+                        playback = None;
+                    }
+                    None => {}
+                }
+                if let Some((_, recording)) = &mut recording {
+                    recording.push(keys);
+                }
+            }
+
+            frame = match run_frame(chip8, &mut debugger, keys) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    save_recording(&recording);
+                    #[cfg(feature = "image")]
+                    finish_gif(gif, &display);
+                    return Err(e);
+                }
+            };
+            drawn |= frame.draw;
+
+            rewind.record(chip8.cpu());
+
+            #[cfg(feature = "image")]
+            thumbnail.observe(&frame);
+            #[cfg(feature = "image")]
+            if let Some((_, recorder)) = &mut gif {
+                recorder.observe(&frame);
+            }
+
+            if let Some(hit) = debugger.take_hit() {
+                println!("{}", hit);
+                println!("{}", debugger.view(chip8.cpu()));
+                break;
+            } else if stepped || advanced {
+                println!("{}", debugger.view(chip8.cpu()));
+            }
+        }
+
+        audio.set_pattern(frame.audio_pattern, frame.pitch);
+        audio.set_playing(frame.sound && !muted);
+
+        let mut overlay = display::Overlay::default();
+        hud.write(chip8.cpu(), &mut overlay);
+        memory_viewer.write(chip8.cpu(), &mut overlay);
+        perf.write(&mut overlay, audio.underruns());
+        if resumed_notice > 0 {
+            resumed_notice -= 1;
+            overlay.line("RESUMED  BACKSPACE RESTARTS");
+        }
+        let overlay_changed = display.set_overlay(overlay);
+        let fading = display.fade(&frame);
+
+        let redraw = drawn || overlay_changed || fading || redraw;
+        next_frame(&mut display, || frame, redraw, &clock, options.vsync);
+    }
+    save_settings(chip8, start_speed, speed_changed, &display, options);
+    save_project(&options.rom, &project, &debugger, &memory_viewer);
+    save_recording(&recording);
+    #[cfg(feature = "image")]
+    finish_gif(gif, &display);
+    Ok(())
+}
+
+// The ROM's file name, its speed in instructions per second and what the
+// emulator is doing, e.g. "pong.ch8 - 480 IPS - paused".
+#[cfg(feature = "sdl")]
+fn window_title(rom: &str, speed: usize, status: Option<&str>) -> String {
+    let name = Path::new(rom)
+        .file_name()
+        .map_or_else(|| rom.into(), |name| name.to_string_lossy());
+    let mut title = format!("{} - {} IPS", name, speed * 60);
+    if let Some(status) = status {
+        title += " - ";
+        title += status;
+    }
+    title
+}
+
+// Picks up the debugging session from <ROM>.debug, returning it as loaded
+// so that quitting only writes it back if something changed.
+#[cfg(feature = "sdl")]
+fn load_project(
+    rom: &str,
+    debugger: &mut chip8::Debugger,
+    memory_viewer: &mut memview::MemoryViewer,
+) -> DebugProject {
+    let path = DebugProject::path_for(rom);
+    if !path.exists() {
+        return DebugProject::default();
+    }
+    match DebugProject::load(&path) {
+        Ok(project) => {
+            project.apply(debugger);
+            memory_viewer.set_position(project.memory_view);
+            DebugProject::from_debugger(debugger, memory_viewer.position())
+        }
+        Err(e) => {
+            eprintln!("Could not load the debug project: {}", e);
+            DebugProject::default()
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn save_project(
+    rom: &str,
+    loaded: &DebugProject,
+    debugger: &chip8::Debugger,
+    memory_viewer: &memview::MemoryViewer,
+) {
+    let project = DebugProject::from_debugger(debugger, memory_viewer.position());
+    if project == *loaded {
+        return;
+    }
+    let path = DebugProject::path_for(rom);
+    if let Err(e) = project.save(&path) {
+        eprintln!("Could not save the debug project {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn save_recording(recording: &Option<(&String, chip8::replay::Recording)>) {
+    if let Some((path, recording)) = recording {
+        match recording.save(path) {
+            Ok(()) => eprintln!("Saved input recording to {}", path),
+            Err(e) => eprintln!("Could not save input recording: {}", e),
+        }
+    }
+}
+
+// Frames the debugger has nothing to stop in run through the block cache,
+// or the JIT when it's on; the rest an instruction at a time. Calls made
+// in the fast frames show as unknown in the debugger's call stack.
+#[cfg(feature = "sdl")]
+fn run_frame(
+    chip8: &mut Chip8,
+    debugger: &mut chip8::Debugger,
+    keys: [bool; 16],
+) -> Result<chip8::Frame, Chip8Error> {
+    if debugger.is_idle() {
+        return chip8.run_frame(keys).cloned();
+    }
+    debugger.run_frame(chip8.cpu_mut(), keys)
+}
+
+// Waits until the next frame is due, drawing `frame` first if `redraw`.
+// With vsync, drawing is what waits, so the window is always drawn.
+#[cfg(feature = "sdl")]
+fn next_frame(
+    display: &mut display::Display,
+    frame: impl FnOnce() -> chip8::Frame,
+    redraw: bool,
+    clock: &chip8::pacing::FrameClock,
+    vsync: bool,
+) {
+    use chip8::Screen;
+
+    if redraw || vsync {
+        display.draw(&frame());
+    }
+    if !vsync {
+        std::thread::sleep(clock.until_next(std::time::Instant::now()));
+    }
+}
+
+// Saves the screen as the window shows it, next to the ROM.
+#[cfg(all(feature = "sdl", feature = "image"))]
+fn screenshot(chip8: &Chip8, display: &display::Display, options: &cli::Options) {
+    let path = chip8::screenshot::timestamped_path(&options.rom, "png");
+    let frame = chip8.cpu().frame();
+    match frame.save_screenshot(display.palette(), options.scale, &path) {
+        Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+        Err(e) => eprintln!("Could not save screenshot: {}", e),
+    }
+}
+
+#[cfg(all(feature = "sdl", feature = "image"))]
+type GifCapture = Option<(PathBuf, chip8::animation::GifRecorder)>;
+
+// Starts a GIF recording next to the ROM, or stops and writes out the
+// running one.
+#[cfg(all(feature = "sdl", feature = "image"))]
+fn toggle_gif(gif: &mut GifCapture, display: &display::Display, options: &cli::Options) {
+    if gif.is_some() {
+        finish_gif(gif.take(), display);
+    } else {
+        let path = chip8::screenshot::timestamped_path(&options.rom, "gif");
+        eprintln!("Recording GIF to {}", path.display());
+        *gif = Some((path, chip8::animation::GifRecorder::new()));
+    }
+}
+
+#[cfg(all(feature = "sdl", feature = "image"))]
+fn finish_gif(gif: GifCapture, display: &display::Display) {
+    const GIF_SCALE: u32 = 4;
+
+    if let Some((path, recorder)) = gif {
+        match recorder.save(display.palette(), GIF_SCALE, &path) {
+            Ok(()) => eprintln!("Saved {} frames to {}", recorder.len(), path.display()),
+            Err(e) => eprintln!("Could not save {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(all(feature = "sdl", not(feature = "image")))]
+fn screenshot(_chip8: &Chip8, _display: &display::Display, _options: &cli::Options) {
+    eprintln!("This build cannot save screenshots; rebuild with --features image");
+}
+
+// Where the ROM's RPL flags persist between runs. Headless runs, recordings
+// and replays start from clear flags so they stay reproducible.
+fn rpl_file(options: &cli::Options) -> Option<RplFile> {
+    if options.headless || options.record.is_some() || options.replay.is_some() {
+        None
+    } else {
+        Some(RplFile::for_rom(&options.rom))
+    }
+}
+
+// Where the state is saved on quitting, unless settings.txt turns resuming
+// off. Like RPL flags, this is left out of runs that have to be
+// reproducible, and the terminal has no key to restart a resumed game.
+fn resume_path(options: &cli::Options, rom: &[u8]) -> Option<PathBuf> {
+    let reproducible = options.headless || options.record.is_some() || options.replay.is_some();
+    if reproducible || options.tui || !options.settings.resume {
+        return None;
+    }
+    settings::resume_path(rom)
+}
+
+// Loads the state saved when this ROM was last quit, without asking:
+// there may be no terminal to ask on, and restarting the game undoes it.
+// Returns whether it did.
+fn resume(chip8: &mut Chip8, path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    match chip8.cpu_mut().load_state_file(path) {
+        Ok(()) => {
+            eprintln!("Resumed where you left off; restart the game to start afresh");
+            true
+        }
+        Err(e) => {
+            eprintln!("Could not resume from {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+fn save_resume(chip8: &Chip8, path: &Path) {
+    let saved = match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).map_err(Chip8Error::from),
+        None => Ok(()),
+    }
+    .and_then(|()| chip8.cpu().save_state_file(path));
+    match saved {
+        Ok(()) => eprintln!("Saved state to resume from next time"),
+        Err(e) => eprintln!("Could not save state to {}: {}", path.display(), e),
+    }
+}
+
+fn save_rpl(chip8: &Chip8, rpl: &mut RplFile) {
+    match rpl.save(chip8.cpu()) {
+        Ok(true) => eprintln!("Saved RPL flags to {}", rpl.path().display()),
+        Ok(false) => {}
+        Err(e) => eprintln!(
+            "Could not save RPL flags to {}: {}",
+            rpl.path().display(),
+            e
+        ),
+    }
+}
+
+// Quirks for a ROM found in the database, unless the user picked their own.
+fn known_rom_quirks(options: &cli::Options) -> Option<Quirks> {
+    if options.quirks_chosen || !options.use_rom_db {
+        return None;
+    }
+    let mut db = RomDb::builtin();
+    if let Some(path) = &options.rom_db {
+        match RomDb::load(path) {
+            Ok(extra) => db.extend(extra),
+            Err(e) => {
+                eprintln!("Could not load ROM database {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    // A missing ROM is reported properly when it is loaded.
+    let rom = read_rom(&options.rom, options.entry.as_deref()).ok()?;
+    let entry = db.lookup(&rom)?;
+    eprintln!(
+        "Recognised {}; using {} quirks ({})",
+        entry.title, entry.platform, entry.quirks
+    );
+    Some(entry.quirks)
+}
+
+// The ROM's entry in a CHIP-8 Archive programs.json, if there is one,
+// announced along with its key hints.
+fn archive_program(options: &cli::Options) -> Option<Program> {
+    if !options.use_rom_db {
+        return None;
+    }
+    let rom = Path::new(&options.rom);
+    let programs = match Programs::load(programs::metadata_path(rom)?) {
+        Ok(programs) => programs,
+        Err(e) => {
+            eprintln!("Could not read {}", e);
+            return None;
+        }
+    };
+    let program = programs.get(rom)?.clone();
+    let by = if program.authors.is_empty() {
+        String::new()
+    } else {
+        format!(" by {}", program.authors.join(", "))
+    };
+    eprintln!("{}{} ({})", program.title, by, program.platform);
+    if !options.quirks_chosen {
+        eprintln!("Using {} quirks ({})", program.platform, program.quirks);
+    }
+    if !program.keys.is_empty() {
+        let keys: Vec<String> = program
+            .keys
+            .iter()
+            .map(|(action, key)| format!("{} = {}", action, key))
+            .collect();
+        eprintln!("Keys: {}", keys.join(", "));
+    }
+    Some(program)
+}
+
+fn list_recent() {
+    let recent = RecentRoms::load();
+    if recent.is_empty() {
+        println!("No ROMs loaded yet");
+    }
+    for (n, rom) in recent.iter().enumerate() {
+        println!("{:>2}  {}", n + 1, rom.display());
+    }
+}
+
+fn disassemble(rom: &str) {
+    let bytes = match read_rom(rom, None) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Could not load {}: {}", rom, e);
+            process::exit(1);
+        }
+    };
+    for line in disasm::disassemble(&bytes, 0x200) {
+        println!("{}", line);
+    }
+}
+
+// Writes next to the source with a .ch8 extension unless told otherwise.
+fn assemble(source: &str, output: Option<String>) {
+    let text = match fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", source, e);
+            process::exit(1);
+        }
+    };
+    let rom = match asm::assemble(&text) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}: {}", source, e);
+            process::exit(1);
         }
-        thread::sleep(sleep_duration);
+    };
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(source).with_extension("ch8"));
+    if let Err(e) = fs::write(&output, rom) {
+        eprintln!("Could not write {}: {}", output.display(), e);
+        process::exit(1);
     }
 }
@@ -0,0 +1,310 @@
+// MegaChip8 (Revival Studios, 2007): a 256x192 screen of 8-bit colour
+// sprites on top of SCHIP. A CPU only understands it once it has been made
+// a MegaChip machine with `CPU::set_megachip`; the program then switches
+// the extension on and off itself:
+//
+//   0011       MEGAON    256x192 colour from here on
+//   0010       MEGAOFF   back to SCHIP's screens
+//   01NN NNNN  LDHI I    I = NNNNNN, reaching past 64K
+//   02NN       LDPAL     NN colours from I, 4 bytes each (alpha, red, green,
+//                        blue), into palette entries 1 to NN
+//   03NN       SPRW      sprite width NN, 0 meaning 256
+//   04NN       SPRH      sprite height NN, 0 meaning 256
+//   05NN       ALPHA     screen alpha NN
+//   060N       DIGISND   play the sampled sound at I, once if N is 1
+//   0700       STOPSND
+//   080N       BMODE     blend mode N; see `Blend`
+//   09NN       CCOL      collision colour NN
+//   00BN       SCRU      scroll up N lines
+//
+// While it is on, DXYN draws a SPRW x SPRH sprite from I, one palette
+// index per byte with 0 transparent, and sets VF if it covers a pixel of
+// the collision colour. With I in the fonts (below 0x100) it draws an
+// ordinary N-line glyph in colour 255 instead. Drawing happens off screen:
+// 00E0 shows what has been drawn and clears it for the next picture.
+//
+// The screen alpha is kept but not applied, and sampled sound isn't played;
+// both are accepted so programs that use them still run.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+
+// The most a MegaChip program can address with its 24-bit I. Memory only
+// grows as far as the ROM needs, in 64K steps; see `CPU::load_bytes`.
+pub const MEGA_MEMORY_SIZE: usize = 0x100_0000;
+
+// MegaChip programs redraw the whole screen every frame and need far more
+// instructions per frame than CHIP-8 ones.
+pub const MEGA_CYCLES_PER_FRAME: usize = 3000;
+
+// How BMODE mixes a sprite's colours with what is already drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Blend {
+    #[default]
+    Normal,
+    // The sprite at 25%, 50% or 75% opacity.
+    Quarter,
+    Half,
+    ThreeQuarters,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    pub fn from_mode(mode: u8) -> Option<Self> {
+        let blend = match mode {
+            0 => Blend::Normal,
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::ThreeQuarters,
+            4 => Blend::Add,
+            5 => Blend::Multiply,
+            _ => return None,
+        };
+        Some(blend)
+    }
+
+    // `sprite` drawn over `screen`, one channel at a time.
+    fn mix(self, sprite: [u8; 3], screen: [u8; 3]) -> [u8; 3] {
+        let channel = |s: u8, d: u8| {
+            let (s, d) = (s as u32, d as u32);
+            let mixed = match self {
+                Blend::Normal => s,
+                Blend::Quarter => (s + 3 * d) / 4,
+                Blend::Half => (s + d) / 2,
+                Blend::ThreeQuarters => (3 * s + d) / 4,
+                Blend::Add => (s + d).min(255),
+                Blend::Multiply => s * d / 255,
+            };
+            mixed as u8
+        };
+        [
+            channel(sprite[0], screen[0]),
+            channel(sprite[1], screen[1]),
+            channel(sprite[2], screen[2]),
+        ]
+    }
+}
+
+// A 256x192 picture: the palette index each pixel was last drawn with,
+// which collisions check, and the colour it came out after blending.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MegaScreen {
+    pub indexes: Vec<u8>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Default for MegaScreen {
+    fn default() -> Self {
+        MegaScreen {
+            indexes: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            colors: vec![[0; 3]; MEGA_WIDTH * MEGA_HEIGHT],
+        }
+    }
+}
+
+impl MegaScreen {
+    pub fn color(&self, x: usize, y: usize) -> [u8; 3] {
+        self.colors[y * MEGA_WIDTH + x]
+    }
+
+    fn clear(&mut self) {
+        self.indexes.iter_mut().for_each(|index| *index = 0);
+        self.colors.iter_mut().for_each(|color| *color = [0; 3]);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MegaChip {
+    // Set by MEGAON, cleared by MEGAOFF.
+    pub enabled: bool,
+    // 256 colours; entry 0 is never drawn.
+    pub palette: Vec<[u8; 3]>,
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub alpha: u8,
+    pub blend: Blend,
+    pub collision_color: u8,
+    // Where sprites are drawn, and what 00E0 last showed.
+    pub drawing: MegaScreen,
+    pub screen: MegaScreen,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip {
+            enabled: false,
+            palette: vec![[0; 3]; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision_color: 0,
+            drawing: MegaScreen::default(),
+            screen: MegaScreen::default(),
+        }
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip::default()
+    }
+
+    // LDPAL: `data` is four bytes per colour, alpha first, for entries 1
+    // onwards.
+    pub fn load_palette(&mut self, data: &[u8]) {
+        for (entry, argb) in self.palette[1..].iter_mut().zip(data.chunks_exact(4)) {
+            *entry = [argb[1], argb[2], argb[3]];
+        }
+    }
+
+    // SPRW and SPRH, where 0 stands for 256.
+    pub fn set_sprite_size(&mut self, width: Option<u8>, height: Option<u8>) {
+        let size = |n: u8| if n == 0 { 256 } else { n as usize };
+        if let Some(width) = width {
+            self.sprite_width = size(width);
+        }
+        if let Some(height) = height {
+            self.sprite_height = size(height);
+        }
+    }
+
+    // Bytes DXYN reads from I for a colour sprite.
+    pub fn sprite_len(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    // Draws a colour sprite, `sprite_width` indexes per row, with its top
+    // left corner at (x, y). Whatever falls off the right or bottom edge is
+    // cut off. Returns whether it covered the collision colour.
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: usize, y: usize) -> bool {
+        let mut collision = false;
+        for (row, indexes) in sprite.chunks(self.sprite_width.max(1)).enumerate() {
+            for (col, &index) in indexes.iter().enumerate() {
+                collision |= self.plot(x + col, y + row, index);
+            }
+        }
+        collision
+    }
+
+    // Draws an ordinary 8-pixel-wide glyph, e.g. from the font, in colour
+    // 255.
+    pub fn draw_glyph(&mut self, glyph: &[u8], x: usize, y: usize) -> bool {
+        let mut collision = false;
+        for (row, &bits) in glyph.iter().enumerate() {
+            for col in 0..8 {
+                if bits & 0x80 >> col != 0 {
+                    collision |= self.plot(x + col, y + row, 255);
+                }
+            }
+        }
+        collision
+    }
+
+    fn plot(&mut self, x: usize, y: usize, index: u8) -> bool {
+        if index == 0 || x >= MEGA_WIDTH || y >= MEGA_HEIGHT {
+            return false;
+        }
+        let at = y * MEGA_WIDTH + x;
+        // Bare background never collides, whatever CCOL says.
+        let under = self.drawing.indexes[at];
+        let collision = under != 0 && under == self.collision_color;
+        self.drawing.indexes[at] = index;
+        let color = self.palette[index as usize];
+        self.drawing.colors[at] = self.blend.mix(color, self.drawing.colors[at]);
+        collision
+    }
+
+    // 00E0: shows the drawing and starts a new one.
+    pub fn present(&mut self) {
+        self.screen.clone_from(&self.drawing);
+        self.drawing.clear();
+    }
+
+    // Clears both pictures, as switching the extension on does.
+    pub fn clear(&mut self) {
+        self.drawing.clear();
+        self.screen.clear();
+    }
+
+    // Moves the drawing by (dx, dy) pixels, clearing what scrolls in.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let old = self.drawing.clone();
+        for y in 0..MEGA_HEIGHT {
+            for x in 0..MEGA_WIDTH {
+                let (sx, sy) = (x as isize - dx, y as isize - dy);
+                let inside = (0..MEGA_WIDTH as isize).contains(&sx)
+                    && (0..MEGA_HEIGHT as isize).contains(&sy);
+                let at = y * MEGA_WIDTH + x;
+                if inside {
+                    let from = sy as usize * MEGA_WIDTH + sx as usize;
+                    self.drawing.indexes[at] = old.indexes[from];
+                    self.drawing.colors[at] = old.colors[from];
+                } else {
+                    self.drawing.indexes[at] = 0;
+                    self.drawing.colors[at] = [0; 3];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_off_screen_until_presented() {
+        let mut mega = MegaChip::new();
+        mega.load_palette(&[0xFF, 10, 20, 30, 0xFF, 200, 0, 0]);
+        mega.set_sprite_size(Some(2), Some(2));
+        assert!(!mega.draw_sprite(&[1, 0, 2, 1], 254, 190));
+        assert_eq!(mega.screen.color(254, 190), [0; 3]);
+
+        mega.present();
+        assert_eq!(mega.screen.color(254, 190), [10, 20, 30]);
+        // Index 0 is transparent.
+        assert_eq!(mega.screen.color(255, 190), [0; 3]);
+        assert_eq!(mega.screen.color(254, 191), [200, 0, 0]);
+        assert_eq!(mega.drawing, MegaScreen::default());
+    }
+
+    #[test]
+    fn collides_with_the_collision_colour() {
+        let mut mega = MegaChip::new();
+        mega.set_sprite_size(Some(1), Some(1));
+        mega.collision_color = 3;
+        assert!(!mega.draw_sprite(&[2], 5, 5));
+        assert!(!mega.draw_sprite(&[3], 5, 5));
+        assert!(mega.draw_sprite(&[1], 5, 5));
+        assert!(!mega.draw_glyph(&[0x80], 4, 4));
+        assert_eq!(mega.drawing.indexes[4 * MEGA_WIDTH + 4], 255);
+    }
+
+    #[test]
+    fn blends_with_what_is_drawn() {
+        let screen = [100, 200, 40];
+        let sprite = [200, 100, 240];
+        assert_eq!(Blend::Normal.mix(sprite, screen), sprite);
+        assert_eq!(Blend::Half.mix(sprite, screen), [150, 150, 140]);
+        assert_eq!(Blend::Add.mix(sprite, screen), [255, 255, 255]);
+        assert_eq!(Blend::Multiply.mix(sprite, screen), [78, 78, 37]);
+        assert_eq!(Blend::from_mode(6), None);
+    }
+
+    #[test]
+    fn scrolls_the_drawing() {
+        let mut mega = MegaChip::new();
+        mega.palette[1] = [1, 1, 1];
+        mega.set_sprite_size(Some(1), Some(1));
+        mega.draw_sprite(&[1], 10, 10);
+        mega.scroll(-4, -3);
+        assert_eq!(mega.drawing.indexes[7 * MEGA_WIDTH + 6], 1);
+        assert_eq!(mega.drawing.indexes[10 * MEGA_WIDTH + 10], 0);
+    }
+}
@@ -0,0 +1,70 @@
+use chip8::processor::{CPU, MEMORY_SIZE};
+
+use crate::display::Overlay;
+
+const ROW_BYTES: usize = 16;
+const ROWS: usize = 16;
+
+// Hex dump of RAM for the overlay, with the instruction at PC and the byte
+// at I highlighted. It follows PC until scrolled, and goes back to following
+// it on `follow_pc`.
+#[derive(Default)]
+pub struct MemoryViewer {
+    visible: bool,
+    // First row shown, as an address; None while following PC.
+    top: Option<usize>,
+}
+
+impl MemoryViewer {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    // Scrolls by whole pages, starting from wherever the view currently is.
+    pub fn scroll(&mut self, cpu: &CPU, pages: isize) {
+        let top = self.top(cpu) as isize + pages * (ROWS * ROW_BYTES) as isize;
+        let last = (MEMORY_SIZE - ROWS * ROW_BYTES) as isize;
+        self.top = Some(top.clamp(0, last) as usize);
+    }
+
+    pub fn follow_pc(&mut self) {
+        self.top = None;
+    }
+
+    // The first address shown, or None while following PC, for keeping
+    // the view in the ROM's debug project.
+    pub fn position(&self) -> Option<usize> {
+        self.top
+    }
+
+    pub fn set_position(&mut self, top: Option<usize>) {
+        let last = MEMORY_SIZE - ROWS * ROW_BYTES;
+        self.top = top.map(|top| (top & !(ROW_BYTES - 1)).min(last));
+    }
+
+    // Keeps PC on the fourth row so the code just before it stays in view.
+    fn top(&self, cpu: &CPU) -> usize {
+        self.top.unwrap_or_else(|| {
+            let row = cpu.pc & !(ROW_BYTES - 1);
+            row.saturating_sub(3 * ROW_BYTES)
+                .min(MEMORY_SIZE - ROWS * ROW_BYTES)
+        })
+    }
+
+    pub fn write(&self, cpu: &CPU, overlay: &mut Overlay) {
+        if !self.visible {
+            return;
+        }
+        overlay.line(format!("MEMORY  PC {:04X}  I {:04X}", cpu.pc, cpu.i));
+        let top = self.top(cpu);
+        for row in (top..top + ROWS * ROW_BYTES).step_by(ROW_BYTES) {
+            let mut spans = vec![(format!("{:04X}:", row), false)];
+            for addr in row..row + ROW_BYTES {
+                let highlight = addr == cpu.i as usize || addr == cpu.pc || addr == cpu.pc + 1;
+                spans.push((" ".to_string(), false));
+                spans.push((format!("{:02X}", cpu.memory[addr]), highlight));
+            }
+            overlay.spans(spans);
+        }
+    }
+}
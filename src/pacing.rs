@@ -0,0 +1,110 @@
+// Counts the 60 Hz frames owed by the time passed, so a main loop keeps
+// the emulated machine at the same speed however it is held back between
+// frames. A loop that sleeps wakes a little late, and makes up for it by
+// sleeping less next time; see `until_next`. One waiting for the display to
+// refresh needn't be running at 60 Hz: at 120 Hz every other refresh owes
+// nothing, and at 50 Hz some owe two frames.
+use std::time::{Duration, Instant};
+
+use crate::frame::FRAME_DURATION;
+
+// The most frames made up at once. A longer stall, e.g. the window being
+// dragged, is skipped rather than run through in a burst.
+const MAX_CATCH_UP: usize = 4;
+
+#[derive(Debug, Default)]
+pub struct FrameClock {
+    last: Option<Instant>,
+    // Time passed that frames haven't been run for yet, in nanoseconds.
+    // Frames can be run a little early, leaving it negative.
+    owed: i64,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        FrameClock::default()
+    }
+
+    // The number of frames to run now. The first call owes one.
+    pub fn tick(&mut self, now: Instant) -> usize {
+        let last = match self.last.replace(now) {
+            Some(last) => last,
+            None => return 1,
+        };
+        let frame = FRAME_DURATION.as_nanos() as i64;
+        self.owed += (now - last).as_nanos() as i64;
+        // A refresh that comes slightly early still runs its frame, so a
+        // 60 Hz display with a little jitter doesn't alternate between no
+        // frames and two.
+        let frames = (self.owed + frame / 4).max(0) / frame;
+        if frames as usize > MAX_CATCH_UP {
+            self.owed = 0;
+            return MAX_CATCH_UP;
+        }
+        self.owed -= frames * frame;
+        frames as usize
+    }
+
+    // How long from `now` until the next frame is owed in full.
+    pub fn until_next(&self, now: Instant) -> Duration {
+        let last = match self.last {
+            Some(last) => last,
+            None => return Duration::ZERO,
+        };
+        let owed = self.owed + (now - last).as_nanos() as i64;
+        let left = FRAME_DURATION.as_nanos() as i64 - owed;
+        Duration::from_nanos(left.max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks(intervals: &[Duration]) -> Vec<usize> {
+        let mut clock = FrameClock::new();
+        let mut now = Instant::now();
+        clock.tick(now);
+        intervals
+            .iter()
+            .map(|&interval| {
+                now += interval;
+                clock.tick(now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_to_60_hz_whatever_the_refresh_rate() {
+        let ms = Duration::from_millis;
+        assert_eq!(ticks(&[FRAME_DURATION; 4]), [1, 1, 1, 1]);
+        assert_eq!(ticks(&[FRAME_DURATION / 2; 4]), [0, 1, 0, 1]);
+        // 50 Hz
+        assert_eq!(ticks(&[ms(20); 6]), [1, 1, 1, 2, 1, 1]);
+        // Jitter around 60 Hz
+        assert_eq!(ticks(&[ms(15), ms(18), ms(16)]), [1, 1, 1]);
+    }
+
+    #[test]
+    fn sleeps_less_after_waking_late() {
+        let mut clock = FrameClock::new();
+        let start = Instant::now();
+        assert_eq!(clock.until_next(start), Duration::ZERO);
+        clock.tick(start);
+        assert_eq!(clock.until_next(start), FRAME_DURATION);
+
+        let late = start + FRAME_DURATION + Duration::from_millis(2);
+        assert_eq!(clock.tick(late), 1);
+        assert_eq!(
+            clock.until_next(late),
+            FRAME_DURATION - Duration::from_millis(2)
+        );
+        assert_eq!(clock.until_next(late + FRAME_DURATION), Duration::ZERO);
+    }
+
+    #[test]
+    fn skips_long_stalls() {
+        let stalled = ticks(&[Duration::from_secs(1), FRAME_DURATION]);
+        assert_eq!(stalled, [MAX_CATCH_UP, 1]);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+// Colors indexed by pixel value: background, plane 1, plane 2, and pixels
+// set on both XO-CHIP planes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Palette {
+    pub fn color(&self, value: u8) -> [u8; 3] {
+        self.colors[(value & 0x3) as usize]
+    }
+
+    // Named palettes for `--palette`. The XO-CHIP plane colours are darker
+    // and lighter shades of the foreground, so single-plane games look the
+    // same whichever plane they draw on.
+    pub fn preset(name: &str) -> Option<Self> {
+        let colors = match name {
+            "default" => return Some(Palette::default()),
+            "green" | "phosphor" => [
+                [0x05, 0x14, 0x08],
+                [0x33, 0xFF, 0x66],
+                [0x1A, 0x80, 0x33],
+                [0xB3, 0xFF, 0xC6],
+            ],
+            "amber" => [
+                [0x14, 0x0A, 0x00],
+                [0xFF, 0xB0, 0x00],
+                [0x80, 0x58, 0x00],
+                [0xFF, 0xE0, 0x99],
+            ],
+            "lcd" => [
+                [0x9B, 0xBC, 0x0F],
+                [0x0F, 0x38, 0x0F],
+                [0x30, 0x62, 0x30],
+                [0x8B, 0xAC, 0x0F],
+            ],
+            "white" | "mono" => [
+                [0x00, 0x00, 0x00],
+                [0xFF, 0xFF, 0xFF],
+                [0x80, 0x80, 0x80],
+                [0xC0, 0xC0, 0xC0],
+            ],
+            _ => return None,
+        };
+        Some(Palette { colors })
+    }
+
+    pub fn set_background(&mut self, color: [u8; 3]) {
+        self.colors[0] = color;
+    }
+
+    pub fn set_foreground(&mut self, color: [u8; 3]) {
+        self.colors[1] = color;
+    }
+}
+
+// Parses a colour written as RRGGBB, with or without a leading #.
+pub fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([byte(0)?, byte(2)?, byte(4)?])
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [[0, 0, 0], [0, 255, 0], [255, 170, 0], [255, 255, 255]],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        assert_eq!(Palette::preset("default"), Some(Palette::default()));
+        assert_eq!(
+            Palette::preset("amber").unwrap().color(1),
+            [0xFF, 0xB0, 0x00]
+        );
+        assert_eq!(Palette::preset("phosphor"), Palette::preset("green"));
+        assert_eq!(Palette::preset("sepia"), None);
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#FFB000"), Some([0xFF, 0xB0, 0x00]));
+        assert_eq!(parse_color("0a1b2c"), Some([0x0A, 0x1B, 0x2C]));
+        assert_eq!(parse_color("FFB00"), None);
+        assert_eq!(parse_color("GGGGGG"), None);
+        assert_eq!(parse_color("#+1+1+1"), None);
+    }
+}
@@ -0,0 +1,477 @@
+// A debugger window drawn with egui, opened with Shift+F1: registers, a
+// disassembly following PC, a memory hexdump and breakpoints, all driven
+// with the mouse. egui only produces triangles; rather than pull in an
+// OpenGL backend they are rasterised here into a streaming texture, which
+// is plenty fast for a window of text.
+use std::collections::HashMap;
+use std::time::Instant;
+
+use egui::epaint::{ClippedPrimitive, ImageData, Mesh, Primitive};
+use egui::{Color32, Pos2, Rect, RichText, TextureId};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use chip8::disasm::Instruction;
+use chip8::processor::{CPU, MEMORY_SIZE};
+use chip8::watch::Watchpoint;
+use chip8::Debugger;
+
+const WIDTH: u32 = 720;
+const HEIGHT: u32 = 540;
+// Instructions shown from PC on.
+const LISTING: usize = 32;
+// Points scrolled per notch of the mouse wheel.
+const SCROLL_STEP: f32 = 24.0;
+
+pub struct Panel {
+    canvas: Canvas<Window>,
+    texture: Texture,
+    size: (u32, u32),
+    // RGBA, premultiplied.
+    pixels: Vec<u8>,
+    ctx: egui::Context,
+    textures: HashMap<TextureId, Image>,
+    input: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    start: Instant,
+    new_breakpoint: String,
+    new_watchpoint: String,
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl Panel {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        let video_subsys = sdl_context.video().unwrap();
+        let window = video_subsys
+            .window("CHIP-8 debugger", WIDTH, HEIGHT)
+            .resizable()
+            .build()
+            .unwrap();
+        let canvas = window.into_canvas().build().unwrap();
+        let texture = Self::create_texture(&canvas, (WIDTH, HEIGHT));
+        Panel {
+            canvas,
+            texture,
+            size: (WIDTH, HEIGHT),
+            pixels: Vec::new(),
+            ctx: egui::Context::default(),
+            textures: HashMap::new(),
+            input: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            start: Instant::now(),
+            new_breakpoint: String::new(),
+            new_watchpoint: String::new(),
+        }
+    }
+
+    fn create_texture(canvas: &Canvas<Window>, (width, height): (u32, u32)) -> Texture {
+        // ABGR8888 is R, G, B, A in memory on little-endian machines.
+        canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::ABGR8888, width, height)
+            .unwrap()
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Passes on an event for the panel's window.
+    pub fn handle(&mut self, event: &Event) {
+        let pos = |x: i32, y: i32| Pos2::new(x as f32, y as f32);
+        let event = match *event {
+            Event::MouseMotion { x, y, .. } => egui::Event::PointerMoved(pos(x, y)),
+            Event::MouseButtonDown {
+                mouse_btn, x, y, ..
+            }
+            | Event::MouseButtonUp {
+                mouse_btn, x, y, ..
+            } => egui::Event::PointerButton {
+                pos: pos(x, y),
+                button: match mouse_btn {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    _ => return,
+                },
+                pressed: matches!(event, Event::MouseButtonDown { .. }),
+                modifiers: self.modifiers,
+            },
+            Event::MouseWheel { x, y, .. } => {
+                egui::Event::Scroll(egui::vec2(x as f32, y as f32) * SCROLL_STEP)
+            }
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => egui::Event::PointerGone,
+            Event::TextInput { ref text, .. } => egui::Event::Text(text.clone()),
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                repeat,
+                ..
+            }
+            | Event::KeyUp {
+                keycode: Some(keycode),
+                keymod,
+                repeat,
+                ..
+            } => {
+                let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                self.modifiers = egui::Modifiers {
+                    alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+                    ctrl,
+                    shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                    mac_cmd: false,
+                    command: ctrl,
+                };
+                egui::Event::Key {
+                    key: match key(keycode) {
+                        Some(key) => key,
+                        None => return,
+                    },
+                    physical_key: None,
+                    pressed: matches!(event, Event::KeyDown { .. }),
+                    repeat,
+                    modifiers: self.modifiers,
+                }
+            }
+            _ => return,
+        };
+        self.input.push(event);
+    }
+
+    pub fn draw(&mut self, debugger: &mut Debugger, cpu: &mut CPU) {
+        let size = self.canvas.window().size();
+        if size != self.size {
+            self.size = size;
+            self.texture = Self::create_texture(&self.canvas, size);
+        }
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        let input = egui::RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                egui::vec2(width as f32, height as f32),
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.input),
+            ..Default::default()
+        };
+        let (mut new_breakpoint, mut new_watchpoint) = (
+            std::mem::take(&mut self.new_breakpoint),
+            std::mem::take(&mut self.new_watchpoint),
+        );
+        let output = self.ctx.run(input, |ctx| {
+            ui(ctx, debugger, cpu, &mut new_breakpoint, &mut new_watchpoint)
+        });
+        self.new_breakpoint = new_breakpoint;
+        self.new_watchpoint = new_watchpoint;
+
+        for (id, delta) in output.textures_delta.set {
+            let pixels: Vec<Color32> = match &delta.image {
+                ImageData::Color(image) => image.pixels.clone(),
+                ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            };
+            let [w, h] = delta.image.size();
+            match (delta.pos, self.textures.get_mut(&id)) {
+                (Some([x, y]), Some(texture)) => {
+                    for row in 0..h {
+                        let start = (y + row) * texture.width + x;
+                        texture.pixels[start..start + w]
+                            .copy_from_slice(&pixels[row * w..(row + 1) * w]);
+                    }
+                }
+                _ => {
+                    let image = Image {
+                        width: w,
+                        height: h,
+                        pixels,
+                    };
+                    self.textures.insert(id, image);
+                }
+            }
+        }
+
+        self.pixels.clear();
+        self.pixels.resize(width * height * 4, 0);
+        let primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &primitives
+        {
+            if let Primitive::Mesh(mesh) = primitive {
+                if let Some(texture) = self.textures.get(&mesh.texture_id) {
+                    rasterize(&mut self.pixels, width, height, *clip_rect, mesh, texture);
+                }
+            }
+        }
+        for id in output.textures_delta.free {
+            self.textures.remove(&id);
+        }
+
+        let _ = self.texture.update(None, &self.pixels, width * 4);
+        self.canvas.clear();
+        let _ = self.canvas.copy(&self.texture, None, None);
+        self.canvas.present();
+    }
+}
+
+fn key(keycode: Keycode) -> Option<egui::Key> {
+    Some(match keycode {
+        Keycode::Return | Keycode::KpEnter => egui::Key::Enter,
+        Keycode::Backspace => egui::Key::Backspace,
+        Keycode::Delete => egui::Key::Delete,
+        Keycode::Tab => egui::Key::Tab,
+        Keycode::Escape => egui::Key::Escape,
+        Keycode::Left => egui::Key::ArrowLeft,
+        Keycode::Right => egui::Key::ArrowRight,
+        Keycode::Up => egui::Key::ArrowUp,
+        Keycode::Down => egui::Key::ArrowDown,
+        Keycode::Home => egui::Key::Home,
+        Keycode::End => egui::Key::End,
+        Keycode::A => egui::Key::A,
+        Keycode::C => egui::Key::C,
+        Keycode::V => egui::Key::V,
+        Keycode::X => egui::Key::X,
+        _ => return None,
+    })
+}
+
+// Fills the triangles of `mesh` into `pixels`, blending premultiplied
+// colours over what is there.
+fn rasterize(
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    clip: Rect,
+    mesh: &Mesh,
+    texture: &Image,
+) {
+    let clip = clip.intersect(Rect::from_min_size(
+        Pos2::ZERO,
+        egui::vec2(width as f32, height as f32),
+    ));
+    let edge = |a: Pos2, b: Pos2, p: Pos2| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| &mesh.vertices[triangle[k] as usize]);
+        let area = edge(a.pos, b.pos, c.pos);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+        let bounds = Rect::from_points(&[a.pos, b.pos, c.pos]).intersect(clip);
+        if !bounds.is_positive() {
+            continue;
+        }
+        let (x0, x1) = (bounds.min.x.floor() as usize, bounds.max.x.ceil() as usize);
+        let (y0, y1) = (bounds.min.y.floor() as usize, bounds.max.y.ceil() as usize);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(b.pos, c.pos, p) / area;
+                let wb = edge(c.pos, a.pos, p) / area;
+                let wc = 1.0 - wa - wb;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let u = a.uv.x * wa + b.uv.x * wb + c.uv.x * wc;
+                let v = a.uv.y * wa + b.uv.y * wb + c.uv.y * wc;
+                let tx = ((u * texture.width as f32) as usize).min(texture.width - 1);
+                let ty = ((v * texture.height as f32) as usize).min(texture.height - 1);
+                let texel = texture.pixels[ty * texture.width + tx].to_array();
+                let colours = [a.color, b.color, c.color].map(|c| c.to_array());
+
+                let dst = &mut pixels[(y * width + x) * 4..][..4];
+                let mut src = [0.0; 4];
+                for (i, channel) in src.iter_mut().enumerate() {
+                    let shade = colours[0][i] as f32 * wa
+                        + colours[1][i] as f32 * wb
+                        + colours[2][i] as f32 * wc;
+                    *channel = shade * texel[i] as f32 / 255.0;
+                }
+                let keep = 1.0 - src[3] / 255.0;
+                for (d, s) in dst.iter_mut().zip(src) {
+                    *d = (s + *d as f32 * keep).min(255.0) as u8;
+                }
+            }
+        }
+    }
+}
+
+fn ui(
+    ctx: &egui::Context,
+    debugger: &mut Debugger,
+    cpu: &mut CPU,
+    new_breakpoint: &mut String,
+    new_watchpoint: &mut String,
+) {
+    egui::TopBottomPanel::bottom("memory")
+        .resizable(true)
+        .default_height(180.0)
+        .show(ctx, |ui| {
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .show_rows(ui, row_height, MEMORY_SIZE / 16, |ui, rows| {
+                    for row in rows {
+                        let addr = row * 16;
+                        let bytes = &cpu.memory[addr..addr + 16];
+                        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                        let text: String = bytes
+                            .iter()
+                            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                            .collect();
+                        ui.monospace(format!("{:04X}  {}  {}", addr, hex.join(" "), text));
+                    }
+                });
+        });
+
+    egui::SidePanel::left("state")
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let label = if debugger.is_paused() {
+                    "Resume"
+                } else {
+                    "Pause"
+                };
+                if ui.button(label).clicked() {
+                    debugger.toggle_pause();
+                }
+                ui.add_enabled_ui(debugger.is_paused(), |ui| {
+                    if ui.button("Step").clicked() {
+                        debugger.step();
+                    }
+                    if ui.button("Frame").clicked() {
+                        debugger.advance_frame();
+                    }
+                });
+            });
+            ui.separator();
+
+            egui::Grid::new("registers").striped(true).show(ui, |ui| {
+                for row in cpu.v.chunks(4).enumerate() {
+                    let (row, values) = row;
+                    for (col, value) in values.iter().enumerate() {
+                        ui.monospace(format!("V{:X} {:02X}", row * 4 + col, value));
+                    }
+                    ui.end_row();
+                }
+                ui.monospace(format!("I  {:04X}", cpu.i));
+                ui.monospace(format!("PC {:04X}", cpu.pc));
+                ui.monospace(format!("SP {:X}", cpu.sp));
+                ui.end_row();
+                ui.monospace(format!("DT {:02X}", cpu.delay_timer));
+                ui.monospace(format!("ST {:02X}", cpu.sound_timer));
+                ui.end_row();
+            });
+            ui.separator();
+
+            ui.label("Call stack");
+            for frame in debugger.call_stack(cpu) {
+                let entry = frame
+                    .entry
+                    .map_or_else(|| "???".to_string(), |entry| format!("{:03X}", entry));
+                ui.monospace(format!("{} -> {:03X}", entry, frame.return_to));
+            }
+            ui.separator();
+
+            ui.label("Breakpoints");
+            let mut removed = None;
+            for addr in debugger.breakpoints() {
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{:03X}", addr));
+                    if ui.small_button("x").clicked() {
+                        removed = Some(addr);
+                    }
+                });
+            }
+            if let Some(addr) = removed {
+                debugger.remove_breakpoint(addr);
+            }
+            if add_field(ui, new_breakpoint, "Address") {
+                let digits = new_breakpoint.trim_start_matches("0x");
+                if let Ok(addr) = usize::from_str_radix(digits, 16) {
+                    debugger.add_breakpoint(addr);
+                    new_breakpoint.clear();
+                }
+            }
+            ui.separator();
+
+            ui.label("Watchpoints");
+            let mut removed = None;
+            for watch in debugger.watchpoints() {
+                ui.horizontal(|ui| {
+                    ui.monospace(watch.to_string());
+                    if ui.small_button("x").clicked() {
+                        removed = Some(*watch);
+                    }
+                });
+            }
+            if let Some(watch) = removed {
+                debugger.remove_watchpoint(&watch);
+            }
+            if add_field(ui, new_watchpoint, "3A0 or VF:w") {
+                if let Some(watch) = Watchpoint::parse(new_watchpoint) {
+                    debugger.add_watchpoint(watch);
+                    new_watchpoint.clear();
+                }
+            }
+        });
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.label("Click an instruction to toggle a breakpoint");
+        let breakpoints: Vec<usize> = debugger.breakpoints().collect();
+        let mut addr = cpu.pc;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for _ in 0..LISTING {
+                    let bytes = cpu.memory.get(addr..).unwrap_or_default();
+                    let instruction = match Instruction::decode(bytes) {
+                        Some(instruction) => instruction,
+                        None => break,
+                    };
+                    let marker = match (addr == cpu.pc, breakpoints.contains(&addr)) {
+                        (true, _) => ">",
+                        (false, true) => "*",
+                        (false, false) => " ",
+                    };
+                    let mut text =
+                        RichText::new(format!("{} {:03X}  {}", marker, addr, instruction))
+                            .monospace();
+                    if breakpoints.contains(&addr) {
+                        text = text.color(Color32::LIGHT_RED);
+                    }
+                    if ui.selectable_label(addr == cpu.pc, text).clicked() {
+                        debugger.toggle_breakpoint(addr);
+                    }
+                    addr += instruction.size();
+                }
+            });
+    });
+}
+
+// A text field with an Add button. Returns true when either is used.
+fn add_field(ui: &mut egui::Ui, text: &mut String, hint: &str) -> bool {
+    ui.horizontal(|ui| {
+        let field = ui.add(
+            egui::TextEdit::singleline(text)
+                .hint_text(hint)
+                .desired_width(100.0),
+        );
+        let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        ui.button("Add").clicked() || entered
+    })
+    .inner
+}
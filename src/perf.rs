@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use crate::display::Overlay;
+
+// How often the readout is refreshed.
+const PERIOD: Duration = Duration::from_secs(1);
+
+// Frames shown and instructions run per second, how long frames took and
+// how often the sound ran dry, for checking the main loop keeps a steady
+// 60 Hz. Figures cover the last
+// whole second, so they don't flicker from frame to frame.
+#[derive(Default)]
+pub struct PerfOverlay {
+    visible: bool,
+    since: Option<Instant>,
+    last: Option<Instant>,
+    frames: u32,
+    instructions: usize,
+    longest: Duration,
+    readout: Option<Readout>,
+}
+
+struct Readout {
+    fps: f64,
+    ips: f64,
+    average: Duration,
+    longest: Duration,
+}
+
+impl PerfOverlay {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.since = None;
+        self.last = None;
+        self.readout = None;
+    }
+
+    // Call once per frame shown with the instructions run for it, which is
+    // none while paused and several frames' worth while fast-forwarding.
+    pub fn frame(&mut self, instructions: usize) {
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            self.longest = self.longest.max(now - last);
+        }
+        self.last = Some(now);
+        let since = *self.since.get_or_insert(now);
+        self.frames += 1;
+        self.instructions += instructions;
+
+        let elapsed = now - since;
+        if elapsed >= PERIOD {
+            let seconds = elapsed.as_secs_f64();
+            self.readout = Some(Readout {
+                fps: self.frames as f64 / seconds,
+                ips: self.instructions as f64 / seconds,
+                average: elapsed / self.frames,
+                longest: self.longest,
+            });
+            self.since = Some(now);
+            self.frames = 0;
+            self.instructions = 0;
+            self.longest = Duration::ZERO;
+        }
+    }
+
+    // `underruns` is the buzzer's count so far.
+    pub fn write(&self, overlay: &mut Overlay, underruns: u32) {
+        if !self.visible {
+            return;
+        }
+        let readout = match &self.readout {
+            Some(readout) => readout,
+            None => {
+                overlay.line("FPS --  IPS --");
+                return overlay.line(format!("AUDIO UNDERRUNS {}", underruns));
+            }
+        };
+        overlay.line(format!("FPS {:.1}  IPS {:.0}", readout.fps, readout.ips));
+        overlay.line(format!(
+            "FRAME {:.1} MS  MAX {:.1} MS",
+            readout.average.as_secs_f64() * 1000.0,
+            readout.longest.as_secs_f64() * 1000.0
+        ));
+        overlay.line(format!("AUDIO UNDERRUNS {}", underruns));
+    }
+}
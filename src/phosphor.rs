@@ -0,0 +1,105 @@
+// Optional CRT-style persistence: a pixel that switches off fades to the
+// background over a few frames instead of vanishing at once. Sprites that
+// are erased and redrawn every frame, as XOR drawing forces most games to
+// do, then stop flickering.
+use crate::frame::Frame;
+use crate::palette::Palette;
+use crate::processor::{Resolution, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub struct Phosphor {
+    // Per pixel: the value it last showed and how much of that colour is
+    // left, from 1.0 while lit down to 0.0.
+    glow: Vec<(u8, f32)>,
+    step: f32,
+    resolution: Resolution,
+}
+
+impl Phosphor {
+    // Cleared pixels take `frames` frames to fade out.
+    pub fn new(frames: u32) -> Self {
+        Phosphor {
+            glow: vec![(0, 0.0); SCREEN_WIDTH * SCREEN_HEIGHT],
+            step: 1.0 / frames.max(1) as f32,
+            resolution: Resolution::Low,
+        }
+    }
+
+    // Advances the fade by one frame. Returns whether anything is still
+    // fading, in which case the screen needs redrawing even if the program
+    // drew nothing.
+    pub fn update(&mut self, frame: &Frame) -> bool {
+        // Pixels don't line up across a resolution switch.
+        if frame.resolution != self.resolution {
+            self.glow.iter_mut().for_each(|glow| *glow = (0, 0.0));
+            self.resolution = frame.resolution;
+        }
+        // MegaChip programs redraw the whole screen every frame, so there
+        // is nothing to fade; renderers show their colours as they are.
+        if frame.mega.is_some() {
+            return false;
+        }
+        let (width, height) = frame.screen_size();
+        let mut fading = false;
+        for (y, row) in frame.gfx.rows().take(height).enumerate() {
+            for (x, &value) in row[..width].iter().enumerate() {
+                let glow = &mut self.glow[y * SCREEN_WIDTH + x];
+                if value != 0 {
+                    *glow = (value, 1.0);
+                } else if glow.1 > 0.0 {
+                    glow.1 = (glow.1 - self.step).max(0.0);
+                    fading = true;
+                }
+            }
+        }
+        fading
+    }
+
+    // The colour to show at (x, y) as of the last `update`.
+    pub fn color(&self, palette: &Palette, x: usize, y: usize) -> [u8; 3] {
+        let (value, level) = self.glow[y * SCREEN_WIDTH + x];
+        let background = palette.color(0);
+        let lit = palette.color(value);
+        let mix = |i: usize| {
+            let (from, to) = (f32::from(background[i]), f32::from(lit[i]));
+            (from + (to - from) * level).round() as u8
+        };
+        [mix(0), mix(1), mix(2)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CPU;
+
+    #[test]
+    fn cleared_pixels_fade_out() {
+        let palette = Palette::default();
+        let mut phosphor = Phosphor::new(2);
+        let mut frame = CPU::new().frame();
+        frame.gfx.set(5, 3, 1);
+        assert!(!phosphor.update(&frame));
+        assert_eq!(phosphor.color(&palette, 5, 3), palette.color(1));
+
+        frame.gfx.set(5, 3, 0);
+        assert!(phosphor.update(&frame));
+        assert_eq!(phosphor.color(&palette, 5, 3), [0, 128, 0]);
+        assert!(phosphor.update(&frame));
+        assert_eq!(phosphor.color(&palette, 5, 3), palette.color(0));
+        assert!(!phosphor.update(&frame));
+    }
+
+    #[test]
+    fn resolution_switches_clear_the_glow() {
+        let palette = Palette::default();
+        let mut phosphor = Phosphor::new(4);
+        let mut frame = CPU::new().frame();
+        frame.gfx.set(0, 0, 1);
+        phosphor.update(&frame);
+
+        frame.gfx.set(0, 0, 0);
+        frame.resolution = Resolution::High;
+        assert!(!phosphor.update(&frame));
+        assert_eq!(phosphor.color(&palette, 0, 0), palette.color(0));
+    }
+}
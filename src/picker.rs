@@ -0,0 +1,71 @@
+// Asks for a ROM with the desktop's own file dialog, for when the binary is
+// started without one, e.g. by double-clicking it. The dialog comes from
+// whatever the platform already has: zenity or kdialog on Linux and the
+// BSDs, AppleScript on macOS and PowerShell on Windows.
+use std::process::Command;
+
+const TITLE: &str = "Open a CHIP-8 ROM";
+
+#[cfg(target_os = "windows")]
+fn dialogs() -> Vec<Command> {
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms; \
+         $dialog = New-Object System.Windows.Forms.OpenFileDialog; \
+         $dialog.Title = '{}'; \
+         $dialog.Filter = 'CHIP-8 ROMs|*.ch8;*.c8;*.sc8;*.xo8|All files|*.*'; \
+         if ($dialog.ShowDialog() -eq 'OK') {{ $dialog.FileName }} else {{ exit 1 }}",
+        TITLE
+    );
+    let mut powershell = Command::new("powershell");
+    powershell.args(["-NoProfile", "-Command", &script]);
+    vec![powershell]
+}
+
+#[cfg(target_os = "macos")]
+fn dialogs() -> Vec<Command> {
+    let mut osascript = Command::new("osascript");
+    osascript.args([
+        "-e",
+        &format!("POSIX path of (choose file with prompt \"{}\")", TITLE),
+    ]);
+    vec![osascript]
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn dialogs() -> Vec<Command> {
+    let mut zenity = Command::new("zenity");
+    zenity.args([
+        "--file-selection",
+        &format!("--title={}", TITLE),
+        "--file-filter=CHIP-8 ROMs | *.ch8 *.c8 *.sc8 *.xo8",
+        "--file-filter=All files | *",
+    ]);
+    let mut kdialog = Command::new("kdialog");
+    kdialog.args([
+        "--title",
+        TITLE,
+        "--getopenfilename",
+        ".",
+        "*.ch8 *.c8 *.sc8 *.xo8",
+    ]);
+    vec![zenity, kdialog]
+}
+
+// The chosen path, or None if the dialog was cancelled or there is no
+// dialog to show.
+pub fn pick_rom() -> Option<String> {
+    for mut dialog in dialogs() {
+        // A dialog that can't be started isn't installed; try the next one.
+        let output = match dialog.output() {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        if !output.status.success() {
+            return None;
+        }
+        let path = String::from_utf8(output.stdout).ok()?;
+        let path = path.trim_end_matches(&['\r', '\n'][..]);
+        return Some(path.to_string()).filter(|path| !path.is_empty());
+    }
+    None
+}
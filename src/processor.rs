@@ -1,26 +1,179 @@
-use std::fs::File;
-use std::io::Read;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
+#[cfg(feature = "std")]
+use crate::cheat::Cheats;
+use crate::error::Chip8Error;
 use crate::font;
-use rand;
-use rand::Rng;
+use crate::frame::{Frame, Frames, CYCLES_PER_FRAME};
+use crate::framebuffer::sprite_row;
+use crate::frontend::Keypad;
+use crate::megachip::{Blend, MegaChip, MEGA_MEMORY_SIZE};
+#[cfg(feature = "std")]
+use crate::profile::Profiler;
+use crate::quirks::Quirks;
+#[cfg(feature = "lua")]
+use crate::script::Script;
+#[cfg(feature = "std")]
+use crate::trace::{Registers, Tracer};
+use serde::{Deserialize, Serialize};
 
+// The framebuffer is sized for SCHIP high resolution; low resolution
+// programs only use the top-left 64x32 corner.
+pub use crate::framebuffer::{Framebuffer, Resolution, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// XO-CHIP programs can address a full 64K; classic programs simply never
+// touch anything past 0xFFF.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+// Everything from 0x200 to the end of memory. Classic ROMs fit below 0x1000
+// but XO-CHIP ones can use the rest.
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - 0x200;
+
+// The same for a MegaChip machine, whose memory grows to fit the ROM.
+pub const MAX_MEGA_ROM_SIZE: usize = MEGA_MEMORY_SIZE - 0x200;
+
+pub(crate) const BIG_FONT_ADDR: usize = 0x50;
+
+// The registers from X to Y inclusive, counting down if Y < X.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+// What a file that is obviously not a ROM is, from its first bytes: the
+// usual mistakes are images, archives, text like assembler source, and this
+// emulator's own save states and recordings. Real ROMs are full of control
+// bytes and opcodes above 0x7F, so can't be mistaken for text.
+fn foreign_format(data: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 9] = [
+        (b"\x89PNG", "a PNG image"),
+        (b"GIF8", "a GIF image"),
+        (b"\xFF\xD8\xFF", "a JPEG image"),
+        (b"PK\x03\x04", "a zip archive"),
+        (b"\x1F\x8B", "a gzip file"),
+        (b"%PDF", "a PDF document"),
+        (b"\x7FELF", "an executable"),
+        (b"C8SS", "a save state"),
+        (b"C8IN", "an input recording"),
+    ];
+    if let Some((_, kind)) = SIGNATURES.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return Some(kind);
+    }
+    let text = data
+        .iter()
+        .all(|&b| b == b'\n' || b == b'\r' || b == b'\t' || (0x20..0x7F).contains(&b));
+    if text && data.len() >= 16 && data.contains(&b'\n') {
+        return Some("a text file");
+    }
+    None
+}
+
+// Reads a ROM file, checking its size first rather than reading all of a
+// huge file to find out it doesn't fit. This only refuses what no machine
+// could load; `CPU::load_bytes` checks against the one loading it.
+#[cfg(feature = "std")]
+pub fn read_rom<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Chip8Error> {
+    let size = fs::metadata(&path)?.len();
+    if size > MAX_MEGA_ROM_SIZE as u64 {
+        return Err(Chip8Error::RomTooLarge {
+            size: size.min(usize::MAX as u64) as usize,
+            max: MAX_MEGA_ROM_SIZE,
+        });
+    }
+    Ok(fs::read(path)?)
+}
+
+// A random seed where the platform has a source of entropy. no_std builds
+// start from a fixed one until `set_seed` is called.
+#[cfg(feature = "std")]
+fn initial_seed() -> u64 {
+    rand::random()
+}
+
+#[cfg(not(feature = "std"))]
+fn initial_seed() -> u64 {
+    0
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CPU {
     pub opcode: u16,
-    pub memory: [u8; 4096],
+    // MEMORY_SIZE bytes, or more on a MegaChip machine.
+    pub memory: Vec<u8>,
     pub v: [u8; 16],
-    pub i: u8,
+    // 16 bits wide, or 24 on a MegaChip machine.
+    pub i: u32,
     pub pc: usize,
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub stack: [usize; 16],
     pub sp: usize,
-    pub key: [bool; 16],
-    pub gfx: [[u8; 64]; 32],
+    pub gfx: Framebuffer,
+    pub resolution: Resolution,
+    pub halted: bool,
+    pub plane: u8,
+    pub rpl: [u8; 16],
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
     pub draw_flag: bool,
+    // Set by DXYN under the display-wait quirk; nothing runs until the
+    // frame ends.
+    pub vblank_wait: bool,
     pub keypad: [bool; 16],
+    // The second player's keypad, which only EXF2/EXF5 read. Frontends set
+    // it directly, since most programs never look at it.
+    pub keypad2: [bool; 16],
     pub keypad_waiting: bool,
     pub keypad_register: usize,
+    pub cycles_per_frame: usize,
+    pub quirks: Quirks,
+    // Where ROMs are loaded and execution begins: 0x200 except on a few
+    // machines such as the ETI 660 (0x600).
+    pub start: usize,
+    // How much of `memory` the program can address, e.g. 0x1000 for the
+    // original 4K machines; accesses past it fail like ones past the end.
+    pub memory_size: usize,
+    // The colour screen and its settings on a MegaChip machine; see
+    // `megachip`.
+    pub mega: Option<Box<MegaChip>>,
+    // State of the CXNN generator; see `set_seed`.
+    pub rng: u64,
+    // Where executed instructions are logged, if anywhere. Not part of save
+    // states; see `trace::Tracer`.
+    #[cfg(feature = "std")]
+    #[serde(skip)]
+    pub tracer: Option<Tracer>,
+    // Counts executed instructions if set; see `profile::Profiler`.
+    #[cfg(feature = "std")]
+    #[serde(skip)]
+    pub profiler: Option<Profiler>,
+    // Memory frozen by cheats; see `cheat::Cheats`. Like the tracer, not
+    // part of save states.
+    #[cfg(feature = "std")]
+    #[serde(skip)]
+    pub cheats: Cheats,
+    // Lua hooks run alongside the program; see `script::Script`.
+    #[cfg(feature = "lua")]
+    #[serde(skip)]
+    pub script: Option<Script>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
 }
 
 impl CPU {
@@ -35,80 +188,314 @@ impl CPU {
             sound_timer: 0,
             stack: [0; 16],
             sp: 0,
-            key: [false; 16],
-            gfx: [[0; 64]; 32],
+            gfx: Framebuffer::default(),
+            resolution: Resolution::Low,
+            halted: false,
+            plane: 1,
+            rpl: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: 64,
             draw_flag: false,
+            vblank_wait: false,
             keypad: [false; 16],
+            keypad2: [false; 16],
             keypad_waiting: false,
             keypad_register: 0,
+            cycles_per_frame: CYCLES_PER_FRAME,
+            quirks: Quirks::default(),
+            start: 0x200,
+            memory_size: MEMORY_SIZE,
+            mega: None,
             opcode: 0,
+            rng: initial_seed(),
+            #[cfg(feature = "std")]
+            tracer: None,
+            #[cfg(feature = "std")]
+            profiler: None,
+            #[cfg(feature = "std")]
+            cheats: Cheats::default(),
+            #[cfg(feature = "lua")]
+            script: None,
         }
     }
 
-    pub fn load(&mut self, filename: &str) {
-        let mut f = File::open(filename).unwrap();
-        let mut buffer = [0u8; 3584];
+    // With the zip feature, a .zip loads the first ROM inside it.
+    #[cfg(feature = "std")]
+    pub fn load(&mut self, filename: &str) -> Result<(), Chip8Error> {
+        #[cfg(feature = "zip")]
+        let rom = crate::archive::read_rom(Path::new(filename), None)?;
+        #[cfg(not(feature = "zip"))]
+        let rom = read_rom(filename)?;
+        self.load_bytes(&rom)
+    }
 
-        f.read(&mut buffer).unwrap();
+    // Writes `len` bytes of memory from `addr` to a file, e.g. to pull out
+    // sprite data.
+    #[cfg(feature = "std")]
+    pub fn dump_memory<P: AsRef<Path>>(
+        &self,
+        addr: usize,
+        len: usize,
+        path: P,
+    ) -> Result<(), Chip8Error> {
+        let start = self.mem_range(addr, len)?;
+        fs::write(path, &self.memory[start..start + len])?;
+        Ok(())
+    }
 
-        for (i, &byte) in buffer.iter().enumerate() {
-            let addr = 0x200 + i;
-            if addr < 4096 {
-                self.memory[addr] = byte;
-            } else {
-                break;
-            }
+    // Copies a file into memory at `addr`, the reverse of `dump_memory`.
+    // Returns the number of bytes copied.
+    #[cfg(feature = "std")]
+    pub fn load_memory<P: AsRef<Path>>(
+        &mut self,
+        addr: usize,
+        path: P,
+    ) -> Result<usize, Chip8Error> {
+        let data = fs::read(path)?;
+        let start = self.mem_range(addr, data.len())?;
+        self.memory[start..start + data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    // Makes this a MegaChip machine, before its ROM is loaded. Programs can
+    // then switch on the colour screen and address up to 16MB.
+    pub fn set_megachip(&mut self) {
+        self.mega = Some(Box::new(MegaChip::new()));
+    }
+
+    // Copies a ROM into memory at `start` (0x200 by default), refusing empty
+    // files and ones that are plainly something else. A MegaChip machine's
+    // memory grows to fit, in 64K steps.
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        if rom.is_empty() {
+            return Err(Chip8Error::EmptyRom);
+        }
+        let size = match self.mega {
+            Some(_) => MEGA_MEMORY_SIZE,
+            None => self.memory_size,
+        };
+        let max = size.saturating_sub(self.start);
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        if let Some(kind) = foreign_format(rom) {
+            return Err(Chip8Error::NotARom(kind));
         }
+        if self.mega.is_some() {
+            let end = (self.start + rom.len()).max(self.memory_size);
+            self.memory_size = (end + 0xFFFF) & !0xFFFF;
+            self.memory.resize(self.memory_size, 0);
+        }
+        self.memory[self.start..self.start + rom.len()].copy_from_slice(rom);
+        Ok(())
+    }
+
+    pub fn get_opcode(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.mem_range(self.pc, 2)?;
+        self.opcode = (self.memory[pc] as u16) << 8 | (self.memory[pc + 1] as u16);
+        Ok(())
     }
 
-    pub fn get_opcode(&mut self) {
-        self.opcode = (self.memory[self.pc] as u16) << 8 | (self.memory[self.pc + 1] as u16);
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.resolution.size()
     }
-    pub fn cycle(&mut self, keypad: [bool; 16]) {
+
+    pub fn cycle(&mut self, keypad: [bool; 16]) -> Result<(), Chip8Error> {
+        self.keypad = keypad;
+        #[cfg(feature = "lua")]
+        if let Some(script) = &self.script {
+            for (key, held) in self.keypad.iter_mut().zip(script.held()) {
+                *key |= held;
+            }
+        }
+        if self.halted || self.vblank_wait {
+            return Ok(());
+        }
+
         if self.keypad_waiting {
-            for i in 0..keypad.len() {
-                if keypad[i] {
+            for (i, &pressed) in self.keypad.iter().enumerate() {
+                if pressed {
                     self.keypad_waiting = false;
                     self.v[self.keypad_register] = i as u8;
                     break;
                 }
             }
         } else {
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
+            #[cfg(feature = "lua")]
+            if let Some(script) = self.script.clone() {
+                script.before_instruction(self)?;
             }
+            self.get_opcode()?;
+            self.dispatch()?;
+        }
+        #[cfg(feature = "std")]
+        self.cheats.apply(&mut self.memory);
+        Ok(())
+    }
 
-            if self.sound_timer > 0 {
-                println!("Beep!");
-                self.sound_timer -= 1;
+    // Runs an opcode fetched ahead of time, as `cycle` would if it were at
+    // PC. The caller sets the keypad and makes sure the CPU isn't halted or
+    // waiting for a key; see `blocks::BlockCache`.
+    #[cfg(feature = "std")]
+    pub(crate) fn execute(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        self.opcode = opcode;
+        self.dispatch()?;
+        self.cheats.apply(&mut self.memory);
+        Ok(())
+    }
+
+    // Runs the fetched opcode, telling the tracer and profiler if set.
+    #[cfg(feature = "std")]
+    fn dispatch(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.pc;
+        let start = self.profiler.as_ref().map(|_| Instant::now());
+        match self.tracer.clone() {
+            Some(tracer) => {
+                let before = Registers::of(self);
+                let result = self.run_opcode();
+                tracer.record(pc, &before, self, result.is_err());
+                result?;
             }
-            self.get_opcode();
-            self.run_opcode();
+            None => self.run_opcode()?,
+        }
+        if let (Some(profiler), Some(start)) = (&self.profiler, start) {
+            profiler.record(pc, self.opcode, start.elapsed());
         }
+        Ok(())
     }
 
-    fn run_opcode(&mut self) {
-        println!("{:x} {:x}", self.opcode, self.pc);
-        
+    #[cfg(not(feature = "std"))]
+    fn dispatch(&mut self) -> Result<(), Chip8Error> {
+        self.run_opcode()
+    }
+
+    pub fn run_frame(&mut self, keypad: [bool; 16]) -> Result<Frame, Chip8Error> {
+        self.draw_flag = false;
+        for _ in 0..self.cycles_per_frame {
+            self.cycle(keypad)?;
+        }
+        self.end_frame()?;
+        Ok(self.frame())
+    }
+
+    // Finishes a frame that ran: counts the timers down and calls the
+    // script's frame hook, if there is one.
+    pub fn end_frame(&mut self) -> Result<(), Chip8Error> {
+        self.vblank_wait = false;
+        self.tick_timers();
+        #[cfg(feature = "lua")]
+        if let Some(script) = self.script.clone() {
+            script.after_frame(self)?;
+        }
+        Ok(())
+    }
+
+    // Counts both timers down by one. They run at 60 Hz no matter how many
+    // instructions execute per frame, so this belongs once per frame rather
+    // than in `cycle`.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // A snapshot of what the display and buzzer should show right now.
+    pub fn frame(&self) -> Frame {
+        Frame {
+            gfx: self.gfx,
+            resolution: self.resolution,
+            mega: self.megachip().map(|mega| mega.screen.clone()),
+            draw: self.draw_flag,
+            sound: self.sound_timer > 0,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    pub fn frames<I: Keypad>(&mut self, input: I) -> Frames<'_, I> {
+        Frames::new(self, input)
+    }
+
+    fn run_opcode(&mut self) -> Result<(), Chip8Error> {
         match self.opcode & 0xF000 {
-            0x0000 => match self.opcode & 0x000F {
-                //00E0  Display disp_clear()    Clears the screen.
-                0x0000 => {
-                    for i in 0..self.gfx.len() {
-                        for j in 0..self.gfx[i].len() {
-                            self.gfx[i][j] = 0;
-                        }
+            0x0000 => match self.opcode {
+                //00BN  Display scroll_up(N)    Scrolls the display up by N pixels. (MegaChip)
+                op if op & 0xFFF0 == 0x00B0 && self.mega.is_some() => {
+                    self.scroll(0, -((op & 0x000F) as isize));
+                    self.pc += 2;
+                }
+                //00CN  Display scroll_down(N)  Scrolls the display down by N pixels. (SCHIP)
+                op if op & 0xFFF0 == 0x00C0 => {
+                    self.scroll(0, (op & 0x000F) as isize);
+                    self.pc += 2;
+                }
+                //00DN  Display scroll_up(N)    Scrolls the display up by N pixels. (XO-CHIP)
+                op if op & 0xFFF0 == 0x00D0 => {
+                    self.scroll(0, -((op & 0x000F) as isize));
+                    self.pc += 2;
+                }
+                //00E0  Display disp_clear()    Clears the screen (only the selected planes on XO-CHIP).
+                //On the MegaChip screen, shows what has been drawn and starts the next picture.
+                0x00E0 => {
+                    match self.megachip_mut() {
+                        Some(mega) => mega.present(),
+                        None => self.gfx.clear(self.plane),
                     }
                     self.draw_flag = true;
                     self.pc += 2;
                 }
                 //00EE  Flow    return; Returns from a subroutine.
-                0x000E => {
+                0x00EE => {
+                    if self.sp == 0 {
+                        return Err(Chip8Error::StackUnderflow { pc: self.pc });
+                    }
                     self.sp -= 1;
-                    self.pc += self.stack[self.sp];
+                    self.pc = self.stack[self.sp];
+                }
+                //00FB  Display scroll_right()  Scrolls the display right by 4 pixels. (SCHIP)
+                0x00FB => {
+                    self.scroll(4, 0);
+                    self.pc += 2;
+                }
+                //00FC  Display scroll_left()   Scrolls the display left by 4 pixels. (SCHIP)
+                0x00FC => {
+                    self.scroll(-4, 0);
+                    self.pc += 2;
+                }
+                //00FD  Flow    exit()  Exits the interpreter. (SCHIP)
+                0x00FD => {
+                    self.halted = true;
+                }
+                //00FE  Display lores() Switches to 64x32 low resolution mode. (SCHIP)
+                0x00FE => {
+                    self.resolution = Resolution::Low;
+                    self.draw_flag = true;
+                    self.pc += 2;
+                }
+                //00FF  Display hires() Switches to 128x64 high resolution mode. (SCHIP)
+                0x00FF => {
+                    self.resolution = Resolution::High;
+                    self.draw_flag = true;
+                    self.pc += 2;
+                }
+                //0230  Display disp_clear()    Clears the 64x64 screen. (HiRes CHIP-8)
+                0x0230 if self.resolution == Resolution::Tall => {
+                    self.gfx.clear(self.plane);
+                    self.draw_flag = true;
+                    self.pc += 2;
                 }
-                _ => panic!("Unknown opcode {}!", self.opcode),
+                _ if self.mega.is_some() => self.run_megachip()?,
+                _ => return Err(self.unknown_opcode()),
             },
+            //1260  Display hires() As the first instruction, switches to 64x64 and starts the program
+            //at 2C0, past where the HiRes CHIP-8 interpreter's own routines were loaded. (HiRes CHIP-8)
+            0x1000 if self.quirks.hires && self.opcode == 0x1260 && self.pc == 0x200 => {
+                self.resolution = Resolution::Tall;
+                self.draw_flag = true;
+                self.pc = 0x2C0;
+            }
             0x1000 => {
                 //1NNN  Flow    goto NNN;   Jumps to address NNN.
                 self.pc = (self.opcode & 0x0FFF) as usize;
@@ -116,6 +503,9 @@ impl CPU {
             //2NNN  Flow    *(0xNNN)()  Calls subroutine at NNN.
             0x2000 => {
                 let nnn: usize = (self.opcode & 0x0FFF) as usize;
+                if self.sp == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { pc: self.pc });
+                }
                 self.stack[self.sp] = self.pc + 2;
                 self.sp += 1;
                 self.pc = nnn;
@@ -123,20 +513,49 @@ impl CPU {
             0x3000 => {
                 //3XNN  Cond    if(Vx==NN)  Skips the next instruction if VX equals NN.
                 // (Usually the next instruction is a jump to skip a code block)
-                if self.v[self.op_x()] == self.opcode as u8 & 0x00FF {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
+                self.skip_if(self.v[self.op_x()] == (self.opcode & 0x00FF) as u8);
+            }
+            0x4000 => {
+                //4XNN  Cond    if(Vx!=NN)  Skips the next instruction if VX doesn't equal NN.
+                // (Usually the next instruction is a jump to skip a code block)
+                self.skip_if(self.v[self.op_x()] != (self.opcode & 0x00FF) as u8);
+            }
+            0x5000 => {
+                let x = self.op_x();
+                let y = self.op_y();
+                match self.opcode & 0x000F {
+                    //5XY0  Cond    if(Vx==Vy)  Skips the next instruction if VX equals VY.
+                    // (Usually the next instruction is a jump to skip a code block)
+                    0x0000 => self.skip_if(self.v[x] == self.v[y]),
+                    //5XY2  MEM save(Vx-Vy) Stores VX to VY (in either order) in memory starting at I.
+                    //(XO-CHIP)
+                    0x0002 => {
+                        let i = self.mem_range(self.i as usize, x.max(y) - x.min(y) + 1)?;
+                        for (n, r) in register_range(x, y).enumerate() {
+                            self.memory[i + n] = self.v[r];
+                        }
+                        self.pc += 2;
+                    }
+                    //5XY3  MEM load(Vx-Vy) Fills VX to VY (in either order) from memory starting at I.
+                    //(XO-CHIP)
+                    0x0003 => {
+                        let i = self.mem_range(self.i as usize, x.max(y) - x.min(y) + 1)?;
+                        for (n, r) in register_range(x, y).enumerate() {
+                            self.v[r] = self.memory[i + n];
+                        }
+                        self.pc += 2;
+                    }
+                    _ => return Err(self.unknown_opcode()),
                 }
             }
             0x6000 => {
                 //6XNN  Const   Vx = NN Sets VX to NN.
-                self.v[self.op_x()] = self.opcode as u8 & 0x00FF;
+                self.v[self.op_x()] = (self.opcode & 0x00FF) as u8;
                 self.pc += 2;
             }
             0x7000 => {
                 //7XNN  Const   Vx += NN    Adds NN to VX. (Carry flag is not changed)
-                let nn = (self.opcode & 0x00FF) as u16;
+                let nn = self.opcode & 0x00FF;
                 let x = self.op_x();
                 let vx = self.v[x] as u16;
                 let result = vx + nn;
@@ -155,169 +574,1284 @@ impl CPU {
                     }
                     0x0001 => {
                         //BitOp OR
-                        self.v[x] = self.v[x] | self.v[y];
+                        self.v[x] |= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x0f] = 0;
+                        }
                         self.pc += 2;
                     }
                     0x0002 => {
                         //BitOp AND
-                        self.v[x] = self.v[x] & self.v[y];
+                        self.v[x] &= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x0f] = 0;
+                        }
                         self.pc += 2;
                     }
                     0x0003 => {
                         //BitOp XOR
-                        self.v[x] = self.v[x] ^ self.v[y];
+                        self.v[x] ^= self.v[y];
+                        if self.quirks.vf_reset {
+                            self.v[0x0f] = 0;
+                        }
                         self.pc += 2;
                     }
                     0x0004 => {
-                        // Addition Example 2 on multigesture...
-
-                        self.v[0xF] = if self.v[x >> 4] > (0xFF - self.v[x >> 8]) {
-                            1
-                        } else {
-                            0
-                        };
-
-                        self.v[x >> 8] += self.v[y >> 4];
+                        //8XY4  Math    Vx += Vy    Adds VY to VX. VF is set to 1 when there's a carry, and to 0
+                        //when there isn't.
+                        let (sum, carry) = self.v[x].overflowing_add(self.v[y]);
+                        self.v[x] = sum;
+                        self.v[0x0f] = carry as u8;
                         self.pc += 2;
                     }
                     0x0005 => {
                         //8XY5  Math    Vx -= Vy    VY is subtracted from VX. VF is set to 0 when there's a borrow,
                         // and 1 when there isn't.
-                        self.v[0x0f] = if self.v[x] > self.v[y] { 1 } else { 0 };
-                        self.v[x] = self.v[x].wrapping_sub(self.v[y]);
+                        // VF is written last so it wins when X is F.
+                        let (difference, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                        self.v[x] = difference;
+                        self.v[0x0f] = !borrow as u8;
                         self.pc += 2;
                     }
                     0x0006 => {
                         //8XY6[a]   BitOp   Vx>>=1  Stores the least significant bit of VX in VF and then shifts
                         //VX to the right by 1.[b]
-                        self.v[0x0f] = self.v[x] & 1;
-                        self.v[x] >>= 1;
+                        let value = if self.quirks.shift_uses_vy {
+                            self.v[y]
+                        } else {
+                            self.v[x]
+                        };
+                        self.v[x] = value >> 1;
+                        self.v[0x0f] = value & 1;
                         self.pc += 2;
                     }
                     0x0007 => {
                         //8XY7[a]   Math    Vx=Vy-Vx    Sets VX to VY minus VX. VF is set to 0 when there's a borrow,
                         //and 1 when there isn't.
-                        self.v[0x0f] = if self.v[y] > self.v[x] { 1 } else { 0 };
-                        self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+                        let (difference, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                        self.v[x] = difference;
+                        self.v[0x0f] = !borrow as u8;
                         self.pc += 2;
                     }
                     0x000E => {
                         //8XYE[a]   BitOp   Vx<<=1  Stores the most significant bit of VX in VF and then shifts VX to the left by 1.[b]
-                        self.v[0x0f] = self.v[x] & 0b10000000;
-                        self.v[x] <<= 1;
+                        let value = if self.quirks.shift_uses_vy {
+                            self.v[y]
+                        } else {
+                            self.v[x]
+                        };
+                        self.v[x] = value << 1;
+                        self.v[0x0f] = value >> 7;
                         self.pc += 2;
                     }
-                    _ => panic!("Unknown opcode {}", self.opcode),
+                    _ => return Err(self.unknown_opcode()),
                 }
             }
             0x9000 => {
                 //9XY0  Cond    if(Vx!=Vy)  Skips the next instruction if VX doesn't equal VY.
                 //(Usually the next instruction is a jump to skip a code block)
-                self.pc += if self.v[self.op_x()] != self.v[self.op_y()] {
-                    4
-                } else {
-                    2
-                };
+                self.skip_if(self.v[self.op_x()] != self.v[self.op_y()]);
             }
             0xA000 => {
                 //ANNN  MEM I = NNN Sets I to the address NNN.
-                self.i = (self.opcode & 0x0FFF) as u8;
+                self.i = (self.opcode & 0x0FFF) as u32;
                 self.pc += 2;
             }
             0xB000 => {
-                //BNNN  Flow    PC=V0+NNN   Jumps to the address NNN plus V0.
-                self.pc = self.v[0] as usize + (self.opcode & 0x0FFF) as usize;
+                //BNNN  Flow    PC=V0+NNN   Jumps to the address NNN plus V0. With the jump quirk this
+                //is BXNN, jumping to XNN plus VX. (SCHIP)
+                let offset = if self.quirks.jump_uses_vx {
+                    self.v[self.op_x()]
+                } else {
+                    self.v[0]
+                };
+                self.pc = offset as usize + (self.opcode & 0x0FFF) as usize;
             }
             0xC000 => {
                 //CXNN  Rand    Vx=rand()&NN    Sets VX to the result of a bitwise and operation on a random number
                 //(Typically: 0 to 255) and NN.
                 let x = self.op_x();
-                let nn = (self.opcode & 0x00FF) as usize;
-                let mut rng = rand::thread_rng();
-                let r = rng.gen_range(0, 255);
-                self.v[x] = (r & nn) as u8;
+                let nn = (self.opcode & 0x00FF) as u8;
+                self.v[x] = self.next_random() & nn;
                 self.pc += 2;
             }
+            0xD000 if self.megachip().is_some() => self.draw_megachip()?,
             0xD000 => {
-                self.draw_flag = true;
-                let x = self.op_x();
-                let y = (self.opcode & 0x00F0 >> 4) as usize;
-                let n = (self.opcode & 0x000F) as usize;
-                for byte in 0..n {
-                    let y = (self.v[y] as usize + byte) % 32;
-                    for bit in 0..8 {
-                        let x = (self.v[x] as usize + byte) % 64;
-                        let color = (self.memory[self.i as usize + byte] >> (7 - bit)) & 1;
-                        self.v[0x0f] |= color & self.gfx[y][x];
-                        self.gfx[y][x] ^= color;
+                //DXYN  Display draw(Vx,Vy,N)   Draws a sprite at coordinate (VX, VY) that has a width of 8
+                //pixels and a height of N pixels. VF is set to 1 if any screen pixels are flipped from set
+                //to unset. DXY0 draws a 16x16 sprite in high resolution and 8x16 in low resolution. (SCHIP)
+                //The starting position always wraps; with the clipping quirk the rest of the sprite is cut
+                //off at the edges instead of wrapping too.
+                let (width, height) = self.screen_size();
+                let x = self.v[self.op_x()] as usize % width;
+                let y = self.v[self.op_y()] as usize % height;
+                let clip = self.quirks.clip_sprites;
+                let (cols, rows) = match (self.opcode & 0x000F) as usize {
+                    0 if self.resolution == Resolution::High => (16, 16),
+                    0 => (8, 16),
+                    n => (8, n),
+                };
+                let bytes_per_row = cols / 8;
+
+                // XO-CHIP draws one sprite per selected plane, stored back to back.
+                let plane = self.plane;
+                let sprite_len = rows * bytes_per_row * plane.count_ones() as usize;
+                let mut addr = self.mem_range(self.i as usize, sprite_len)?;
+                self.v[0x0f] = 0;
+                for &mask in [1u8, 2].iter().filter(|&&m| plane & m != 0) {
+                    for row in 0..rows {
+                        if clip && y + row >= height {
+                            break;
+                        }
+                        let py = (y + row) % height;
+                        let start = addr + row * bytes_per_row;
+                        let sprite = if cols == 16 {
+                            u16::from_be_bytes([self.memory[start], self.memory[start + 1]])
+                        } else {
+                            u16::from(self.memory[start]) << 8
+                        };
+                        let bits = sprite_row(sprite, cols, x, width, clip);
+                        if self.gfx.xor_row(mask, py, bits) {
+                            self.v[0x0f] = 1;
+                        }
                     }
+                    addr += rows * bytes_per_row;
                 }
+                self.draw_flag = true;
+                self.vblank_wait = self.quirks.display_wait;
                 self.pc += 2;
             }
+            0xE000 => {
+                let key = (self.v[self.op_x()] & 0x0F) as usize;
+                let (pressed, pressed2) = (self.keypad[key], self.keypad2[key]);
+                match self.opcode & 0x00FF {
+                    //EX9E  KeyOp   if(key()==Vx)   Skips the next instruction if the key stored in VX is pressed.
+                    0x009E => self.skip_if(pressed),
+                    //EXA1  KeyOp   if(key()!=Vx)   Skips the next instruction if the key stored in VX isn't pressed.
+                    0x00A1 => self.skip_if(!pressed),
+                    //EXF2  KeyOp   if(key2()==Vx)  The same on the second player's keypad. (CHIP-8X)
+                    0x00F2 => self.skip_if(pressed2),
+                    //EXF5  KeyOp   if(key2()!=Vx)
+                    0x00F5 => self.skip_if(!pressed2),
+                    _ => return Err(self.unknown_opcode()),
+                }
+            }
             0xF000 => {
                 let x = self.op_x();
                 match self.opcode & 0x00FF {
+                    0x0000 if x == 0 => {
+                        //F000 NNNN MEM I = NNNN    Sets I to the 16-bit address in the following word.
+                        //(XO-CHIP)
+                        let addr = self.mem_range(self.pc + 2, 2)?;
+                        self.i = (self.memory[addr] as u32) << 8 | self.memory[addr + 1] as u32;
+                        self.pc += 4;
+                    }
+                    0x0001 => {
+                        //FN01  Display plane(N)    Selects the drawing planes given by the bit mask N.
+                        //(XO-CHIP)
+                        self.plane = x as u8 & 0x3;
+                        self.pc += 2;
+                    }
+                    0x0002 if x == 0 => {
+                        //F002  Sound   audio() Loads the 16-byte audio pattern buffer from I. (XO-CHIP)
+                        let i = self.mem_range(self.i as usize, 16)?;
+                        self.audio_pattern.copy_from_slice(&self.memory[i..i + 16]);
+                        self.pc += 2;
+                    }
                     0x0007 => {
+                        //FX07  Timer   Vx = get_delay()    Sets VX to the value of the delay timer.
                         self.v[x] = self.delay_timer;
                         self.pc += 2;
                     }
                     0x000A => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX0A  KeyOp   Vx = get_key()  A key press is awaited, and then stored in VX.
+                        //(Blocking Operation. All instruction halted until next key event)
+                        self.keypad_waiting = true;
+                        self.keypad_register = x;
                         self.pc += 2;
                     }
                     0x0015 => {
+                        //FX15  Timer   delay_timer(Vx) Sets the delay timer to VX.
                         self.delay_timer = self.v[x];
                         self.pc += 2;
                     }
                     0x0018 => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX18  Sound   sound_timer(Vx) Sets the sound timer to VX.
+                        self.sound_timer = self.v[x];
                         self.pc += 2;
                     }
                     0x001E => {
+                        //FX1E  MEM I +=Vx  Adds VX to I. VF is not affected.
+                        let mask = if self.mega.is_some() {
+                            0xFF_FFFF
+                        } else {
+                            0xFFFF
+                        };
+                        self.i = (self.i + self.v[x] as u32) & mask;
                         self.pc += 2;
                     }
                     0x0029 => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX29  MEM I=sprite_addr[Vx]   Sets I to the location of the sprite for the character
+                        //in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
+                        self.i = (self.v[x] & 0x0F) as u32 * 5;
+                        self.pc += 2;
+                    }
+                    0x0030 => {
+                        //FX30  MEM I=bigsprite_addr[Vx]    Sets I to the 8x10 font sprite for the digit in VX.
+                        //(SCHIP)
+                        self.i = (BIG_FONT_ADDR + (self.v[x] & 0x0F) as usize * 10) as u32;
+                        self.pc += 2;
+                    }
+                    0x003A => {
+                        //FX3A  Sound   pitch(Vx)   Sets the audio pattern playback pitch to VX. (XO-CHIP)
+                        self.pitch = self.v[x];
                         self.pc += 2;
                     }
                     0x0033 => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX33  BCD set_BCD(Vx) Stores the binary-coded decimal representation of VX, with the
+                        //hundreds digit at I, the tens digit at I+1, and the ones digit at I+2.
+                        let i = self.mem_range(self.i as usize, 3)?;
+                        let vx = self.v[x];
+                        self.memory[i] = vx / 100;
+                        self.memory[i + 1] = (vx / 10) % 10;
+                        self.memory[i + 2] = vx % 10;
                         self.pc += 2;
                     }
                     0x0055 => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX55  MEM reg_dump(Vx,&I) Stores V0 to VX (including VX) in memory starting at
+                        //address I. I itself is left unmodified unless a load/store quirk is set.
+                        let i = self.mem_range(self.i as usize, x + 1)?;
+                        self.memory[i..=i + x].copy_from_slice(&self.v[..=x]);
+                        self.i += self.load_store_increment(x);
                         self.pc += 2;
                     }
                     0x0065 => {
-                        unimplemented!("Not implemented: {:x}", self.opcode);
+                        //FX65  MEM reg_load(Vx,&I) Fills V0 to VX (including VX) with values from memory
+                        //starting at address I. I itself is left unmodified unless a load/store quirk is set.
+                        let i = self.mem_range(self.i as usize, x + 1)?;
+                        self.v[..=x].copy_from_slice(&self.memory[i..=i + x]);
+                        self.i += self.load_store_increment(x);
+                        self.pc += 2;
+                    }
+                    0x0075 => {
+                        //FX75  MEM rpl_dump(Vx)    Stores V0 to VX in the RPL user flags. SCHIP has 8 of
+                        //them, XO-CHIP (saveflags) all 16.
+                        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+                        self.pc += 2;
+                    }
+                    0x0085 => {
+                        //FX85  MEM rpl_load(Vx)    Fills V0 to VX from the RPL user flags. (SCHIP, XO-CHIP
+                        //loadflags)
+                        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
                         self.pc += 2;
                     }
-                    _ => unimplemented!("Unknown self.opcode {}", self.opcode),
+                    _ => return Err(self.unknown_opcode()),
+                }
+            }
+            _ => return Err(self.unknown_opcode()),
+        }
+        Ok(())
+    }
+
+    fn unknown_opcode(&self) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            opcode: self.opcode,
+            pc: self.pc,
+        }
+    }
+
+    // The part of memory the program can address; see `memory_size`.
+    pub fn addressable(&self) -> &[u8] {
+        &self.memory[..self.memory_size.min(self.memory.len())]
+    }
+
+    // Checks that `len` bytes starting at `start` are inside RAM and
+    // returns `start` for indexing. Both can come from outside, e.g. the
+    // remote `dump` command, so they may be anything up to usize::MAX.
+    fn mem_range(&self, start: usize, len: usize) -> Result<usize, Chip8Error> {
+        match start.checked_add(len) {
+            Some(end) if end <= self.addressable().len() => Ok(start),
+            _ => Err(Chip8Error::MemoryOutOfBounds {
+                addr: start.saturating_add(len).saturating_sub(1),
+                pc: self.pc,
+            }),
+        }
+    }
+
+    // How far FX55/FX65 move I after transferring V0 to VX.
+    fn load_store_increment(&self, x: usize) -> u32 {
+        if self.quirks.load_store_increments_by_x {
+            x as u32
+        } else if self.quirks.load_store_increments_i {
+            x as u32 + 1
+        } else {
+            0
+        }
+    }
+
+    // Skips the next instruction when `condition` holds. XO-CHIP's
+    // `F000 NNNN` is four bytes long, so it has to be skipped as a whole.
+    fn skip_if(&mut self, condition: bool) {
+        self.pc += 2;
+        if condition {
+            let long = self.memory.get(self.pc) == Some(&0xF0)
+                && self.memory.get(self.pc + 1) == Some(&0x00);
+            self.pc += if long { 4 } else { 2 };
+        }
+    }
+
+    // Moves the selected planes of the visible screen by (dx, dy) pixels,
+    // clearing whatever scrolls in from the edges. On the MegaChip screen
+    // this moves the picture being drawn.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let size = self.screen_size();
+        match self.megachip_mut() {
+            Some(mega) => mega.scroll(dx, dy),
+            None => self.gfx.scroll(self.plane, size, dx, dy),
+        }
+        self.draw_flag = true;
+    }
+
+    // The MegaChip state while its screen is on.
+    pub fn megachip(&self) -> Option<&MegaChip> {
+        self.mega.as_deref().filter(|mega| mega.enabled)
+    }
+
+    fn megachip_mut(&mut self) -> Option<&mut MegaChip> {
+        self.mega.as_deref_mut().filter(|mega| mega.enabled)
+    }
+
+    // The 0NNN instructions MegaChip adds; see `megachip`. Only called on
+    // a MegaChip machine.
+    fn run_megachip(&mut self) -> Result<(), Chip8Error> {
+        let nn = (self.opcode & 0x00FF) as u8;
+        let mega = match self.mega.as_deref_mut() {
+            Some(mega) => mega,
+            None => return Err(self.unknown_opcode()),
+        };
+        match self.opcode & 0xFF00 {
+            0x0000 if nn == 0x10 => {
+                mega.enabled = false;
+                self.resolution = Resolution::Low;
+                self.draw_flag = true;
+            }
+            0x0000 if nn == 0x11 => {
+                mega.enabled = true;
+                mega.clear();
+                self.resolution = Resolution::Mega;
+                self.draw_flag = true;
+            }
+            0x0100 => {
+                let addr = self.mem_range(self.pc + 2, 2)?;
+                self.i = (nn as u32) << 16
+                    | (self.memory[addr] as u32) << 8
+                    | self.memory[addr + 1] as u32;
+                self.pc += 4;
+                return Ok(());
+            }
+            0x0200 => {
+                let len = nn as usize * 4;
+                let start = self.mem_range(self.i as usize, len)?;
+                if let Some(mega) = self.mega.as_deref_mut() {
+                    mega.load_palette(&self.memory[start..start + len]);
                 }
             }
-            _ => unimplemented!(
-                "{} at {}",
-                format!("Yikes! self.opcode: {:x}", self.opcode),
-                self.pc
-            ),
+            0x0300 => mega.set_sprite_size(Some(nn), None),
+            0x0400 => mega.set_sprite_size(None, Some(nn)),
+            0x0500 => mega.alpha = nn,
+            // Sampled sound isn't played.
+            0x0600 if nn <= 1 => {}
+            0x0700 if nn == 0 => {}
+            0x0800 => match Blend::from_mode(nn) {
+                Some(blend) => mega.blend = blend,
+                None => return Err(self.unknown_opcode()),
+            },
+            0x0900 => mega.collision_color = nn,
+            _ => return Err(self.unknown_opcode()),
         }
+        self.pc += 2;
+        Ok(())
+    }
+
+    // DXYN on the MegaChip screen: a colour sprite from I, or an ordinary
+    // glyph when I points into the fonts.
+    fn draw_megachip(&mut self) -> Result<(), Chip8Error> {
+        let x = self.v[self.op_x()] as usize;
+        let y = self.v[self.op_y()] as usize;
+        let glyph = self.i < 0x100;
+        let len = match self.megachip() {
+            Some(mega) if !glyph => mega.sprite_len(),
+            _ => (self.opcode & 0x000F) as usize,
+        };
+        let start = self.mem_range(self.i as usize, len)?;
+        let sprite = &self.memory[start..start + len];
+        if let Some(mega) = self.mega.as_deref_mut() {
+            let collision = if glyph {
+                mega.draw_glyph(sprite, x, y)
+            } else {
+                mega.draw_sprite(sprite, x, y)
+            };
+            self.v[0x0f] = collision as u8;
+        }
+        self.pc += 2;
+        Ok(())
+    }
+
+    // Reseeds the CXNN generator. Two CPUs with the same seed, program and
+    // input produce the same random numbers.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    // SplitMix64: tiny, any seed is fine, and the state is a plain integer
+    // that save states can carry.
+    fn next_random(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) as u8
     }
 
     fn op_x(&self) -> usize {
-        (self.opcode & 0x0F00 >> 8) as usize
+        ((self.opcode & 0x0F00) >> 8) as usize
     }
 
     fn op_y(&self) -> usize {
-        (self.opcode & 0x00F0 >> 4) as usize
+        ((self.opcode & 0x00F0) >> 4) as usize
     }
 
-    fn init_ram() -> [u8; 4096] {
-        let mut ram = [0u8; 4096];
+    fn init_ram() -> Vec<u8> {
+        let mut ram = alloc::vec![0u8; MEMORY_SIZE];
 
-        for i in 0..font::FONT_SET.len() {
-            ram[i] = font::FONT_SET[i];
-        }
+        ram[..font::FONT_SET.len()].copy_from_slice(&font::FONT_SET);
+        ram[BIG_FONT_ADDR..BIG_FONT_ADDR + font::BIG_FONT_SET.len()]
+            .copy_from_slice(&font::BIG_FONT_SET);
 
         ram
     }
 }
+
+impl fmt::Display for CPU {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "PC: {:03X}  OP: {:04X}  I: {:03X}  SP: {:X}  DT: {:02X}  ST: {:02X}",
+            self.pc, self.opcode, self.i, self.sp, self.delay_timer, self.sound_timer
+        )?;
+        for (n, v) in self.v.iter().enumerate() {
+            if n > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "V{:X}: {:02X}", n, v)?;
+        }
+        writeln!(f)?;
+        write!(f, "Stack:")?;
+        for addr in &self.stack[..self.sp] {
+            write!(f, " {:03X}", addr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+
+    fn execute(cpu: &mut CPU, opcode: u16) {
+        execute_with_keys(cpu, opcode, [false; 16]);
+    }
+
+    fn execute_with_keys(cpu: &mut CPU, opcode: u16, keys: [bool; 16]) {
+        cpu.memory[cpu.pc] = (opcode >> 8) as u8;
+        cpu.memory[cpu.pc + 1] = opcode as u8;
+        cpu.cycle(keys).unwrap();
+    }
+
+    #[test]
+    fn fx07_reads_delay_timer() {
+        let mut cpu = CPU::new();
+        cpu.delay_timer = 0x21;
+        execute(&mut cpu, 0xF307);
+        assert_eq!(cpu.v[3], 0x21);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn cxnn_is_reproducible_with_a_seed() {
+        let rolls = |seed| {
+            let mut cpu = CPU::new();
+            cpu.set_seed(seed);
+            (0..8)
+                .map(|_| {
+                    cpu.pc = 0x200;
+                    execute(&mut cpu, 0xC0FF);
+                    cpu.v[0]
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(42), rolls(42));
+        assert_ne!(rolls(42), rolls(43));
+    }
+
+    #[test]
+    fn cxnn_masks_with_nn() {
+        let mut cpu = CPU::new();
+        for _ in 0..32 {
+            cpu.pc = 0x200;
+            execute(&mut cpu, 0xC10F);
+            assert_eq!(cpu.v[1] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame() {
+        let mut cpu = CPU::new();
+        // 1200: spin in place
+        cpu.memory[0x200] = 0x12;
+        cpu.memory[0x201] = 0x00;
+        cpu.delay_timer = 10;
+        cpu.sound_timer = 1;
+
+        cpu.cycles_per_frame = 100;
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!(cpu.delay_timer, 9);
+        assert_eq!(cpu.sound_timer, 0);
+
+        cpu.cycles_per_frame = 1;
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!(cpu.delay_timer, 8);
+        assert_eq!(cpu.sound_timer, 0);
+    }
+
+    #[test]
+    fn fx0a_waits_for_a_key_press() {
+        let mut cpu = CPU::new();
+        execute(&mut cpu, 0xF50A);
+        assert!(cpu.keypad_waiting);
+        assert_eq!(cpu.pc, 0x202);
+
+        cpu.cycle([false; 16]).unwrap();
+        assert!(cpu.keypad_waiting);
+        assert_eq!(cpu.pc, 0x202);
+
+        let mut keys = [false; 16];
+        keys[0xB] = true;
+        cpu.cycle(keys).unwrap();
+        assert!(!cpu.keypad_waiting);
+        assert_eq!(cpu.v[5], 0xB);
+    }
+
+    #[test]
+    fn fx15_sets_delay_timer() {
+        let mut cpu = CPU::new();
+        cpu.v[2] = 0x40;
+        execute(&mut cpu, 0xF215);
+        assert_eq!(cpu.delay_timer, 0x40);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx18_sets_sound_timer() {
+        let mut cpu = CPU::new();
+        cpu.v[0xA] = 0x10;
+        execute(&mut cpu, 0xFA18);
+        assert_eq!(cpu.sound_timer, 0x10);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx1e_adds_vx_to_i() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.v[1] = 0x25;
+        cpu.v[0xF] = 0x7;
+        execute(&mut cpu, 0xF11E);
+        assert_eq!(cpu.i, 0x325);
+        assert_eq!(cpu.v[0xF], 0x7);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx29_points_i_at_font_glyph() {
+        let mut cpu = CPU::new();
+        cpu.v[4] = 0xA;
+        execute(&mut cpu, 0xF429);
+        assert_eq!(cpu.i, 50);
+        assert_eq!(&cpu.memory[50..55], &font::FONT_SET[50..55]);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx33_stores_bcd() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x400;
+        cpu.v[6] = 254;
+        execute(&mut cpu, 0xF633);
+        assert_eq!(&cpu.memory[0x400..0x403], &[2, 5, 4]);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx55_stores_registers_through_vx() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x500;
+        for n in 0..16 {
+            cpu.v[n] = n as u8 + 1;
+        }
+        execute(&mut cpu, 0xF355);
+        assert_eq!(&cpu.memory[0x500..0x505], &[1, 2, 3, 4, 0]);
+        assert_eq!(cpu.i, 0x500);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn fx65_loads_registers_through_vx() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x500;
+        cpu.memory[0x500..0x504].copy_from_slice(&[9, 8, 7, 6]);
+        execute(&mut cpu, 0xF265);
+        assert_eq!(&cpu.v[..4], &[9, 8, 7, 0]);
+        assert_eq!(cpu.i, 0x500);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn op_4xnn_skips_when_not_equal() {
+        let mut cpu = CPU::new();
+        cpu.v[1] = 0x12;
+        execute(&mut cpu, 0x4134);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn op_4xnn_does_not_skip_when_equal() {
+        let mut cpu = CPU::new();
+        cpu.v[1] = 0x34;
+        execute(&mut cpu, 0x4134);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn op_5xy0_skips_when_equal() {
+        let mut cpu = CPU::new();
+        cpu.v[2] = 0x55;
+        cpu.v[7] = 0x55;
+        execute(&mut cpu, 0x5270);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn op_5xy0_does_not_skip_when_not_equal() {
+        let mut cpu = CPU::new();
+        cpu.v[2] = 0x55;
+        cpu.v[7] = 0x56;
+        execute(&mut cpu, 0x5270);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn ex9e_skips_when_key_pressed() {
+        let mut cpu = CPU::new();
+        cpu.v[3] = 0xC;
+        let mut keys = [false; 16];
+        keys[0xC] = true;
+        execute_with_keys(&mut cpu, 0xE39E, keys);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn ex9e_does_not_skip_when_key_released() {
+        let mut cpu = CPU::new();
+        cpu.v[3] = 0xC;
+        let mut keys = [false; 16];
+        keys[0xD] = true;
+        execute_with_keys(&mut cpu, 0xE39E, keys);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn exa1_skips_when_key_released() {
+        let mut cpu = CPU::new();
+        cpu.v[3] = 0xC;
+        execute(&mut cpu, 0xE3A1);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn exa1_does_not_skip_when_key_pressed() {
+        let mut cpu = CPU::new();
+        cpu.v[3] = 0xC;
+        let mut keys = [false; 16];
+        keys[0xC] = true;
+        execute_with_keys(&mut cpu, 0xE3A1, keys);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn op_00ee_returns_to_caller() {
+        let mut cpu = CPU::new();
+        execute(&mut cpu, 0x2400);
+        assert_eq!(cpu.pc, 0x400);
+        execute(&mut cpu, 0x00EE);
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.sp, 0);
+    }
+
+    #[test]
+    fn op_00ff_and_00fe_switch_resolution() {
+        let mut cpu = CPU::new();
+        execute(&mut cpu, 0x00FF);
+        assert_eq!(cpu.resolution, Resolution::High);
+        assert_eq!(cpu.screen_size(), (128, 64));
+        execute(&mut cpu, 0x00FE);
+        assert_eq!(cpu.resolution, Resolution::Low);
+        assert_eq!(cpu.screen_size(), (64, 32));
+    }
+
+    #[test]
+    fn op_1260_at_the_start_is_a_jump_on_ordinary_machines() {
+        let mut cpu = CPU::new();
+        cpu.load_bytes(&[0x12, 0x60]).unwrap();
+        cpu.cycle([false; 16]).unwrap();
+        assert_eq!((cpu.pc, cpu.resolution), (0x260, Resolution::Low));
+    }
+
+    #[test]
+    fn op_1260_at_the_start_switches_to_64x64() {
+        let mut cpu = CPU::new();
+        cpu.quirks.hires = true;
+        // 0230 is only a clear screen once the program is in 64x64.
+        cpu.memory[0x2C0..0x2C2].copy_from_slice(&[0x02, 0x30]);
+        let mut lores = cpu.clone();
+        lores.pc = 0x2C0;
+        assert!(lores.cycle([false; 16]).is_err());
+
+        // 1260 anywhere else is an ordinary jump.
+        cpu.pc = 0x400;
+        execute(&mut cpu, 0x1260);
+        assert_eq!((cpu.pc, cpu.resolution), (0x260, Resolution::Low));
+
+        cpu.pc = 0x200;
+        execute(&mut cpu, 0x1260);
+        assert_eq!((cpu.pc, cpu.resolution), (0x2C0, Resolution::Tall));
+        assert_eq!(cpu.screen_size(), (64, 64));
+
+        cpu.gfx.set(10, 50, 1);
+        cpu.cycle([false; 16]).unwrap();
+        assert!(cpu.gfx.is_blank());
+    }
+
+    #[test]
+    fn exf2_and_exf5_read_the_second_keypad() {
+        let mut cpu = CPU::new();
+        cpu.v[3] = 0x7;
+        cpu.keypad2[0x7] = true;
+        execute(&mut cpu, 0xE3F2);
+        assert_eq!(cpu.pc, 0x204);
+        execute(&mut cpu, 0xE3F5);
+        assert_eq!(cpu.pc, 0x206);
+        // The first player's keypad is separate.
+        execute_with_keys(&mut cpu, 0xE39E, [false; 16]);
+        assert_eq!(cpu.pc, 0x208);
+    }
+
+    #[test]
+    fn megachip_memory_grows_to_fit_the_rom() {
+        let mut rom = vec![0; 0x12000];
+        // LDHI I, #010010 then I += V0.
+        rom[..6].copy_from_slice(&[0x01, 0x01, 0x00, 0x10, 0xF0, 0x1E]);
+        let mut cpu = CPU::new();
+        assert!(cpu.load_bytes(&rom).is_err());
+        cpu.set_megachip();
+        cpu.load_bytes(&rom).unwrap();
+        assert_eq!(cpu.memory.len(), 0x20000);
+
+        cpu.v[0] = 0xFF;
+        cpu.cycle([false; 16]).unwrap();
+        cpu.cycle([false; 16]).unwrap();
+        assert_eq!((cpu.i, cpu.pc), (0x01010F, 0x206));
+    }
+
+    #[test]
+    fn megachip_draws_colour_sprites_off_screen() {
+        let mut cpu = CPU::new();
+        // 0011 is only an instruction on a MegaChip machine.
+        cpu.memory[0x200..0x202].copy_from_slice(&[0x00, 0x11]);
+        assert!(cpu.cycle([false; 16]).is_err());
+        cpu.set_megachip();
+        execute(&mut cpu, 0x0011);
+        assert_eq!(cpu.screen_size(), (256, 192));
+
+        // Palette entry 1, then a 2x1 sprite of it at (4, 5).
+        cpu.memory[0x300..0x304].copy_from_slice(&[0xFF, 1, 2, 3]);
+        cpu.memory[0x310..0x312].copy_from_slice(&[1, 1]);
+        cpu.i = 0x300;
+        for opcode in [0x0201, 0x0302, 0x0401, 0xA310, 0x6004, 0x6105, 0xD010] {
+            execute(&mut cpu, opcode);
+        }
+        let frame = cpu.frame();
+        assert_eq!(frame.mega.as_ref().unwrap().color(5, 5), [0; 3]);
+
+        execute(&mut cpu, 0x00E0);
+        let frame = cpu.frame();
+        assert_eq!(frame.color(&Palette::default(), 5, 5), [1, 2, 3]);
+        assert_eq!(frame.color(&Palette::default(), 6, 5), [0; 3]);
+
+        execute(&mut cpu, 0x0010);
+        assert_eq!(cpu.frame().mega, None);
+    }
+
+    #[test]
+    fn op_00cn_scrolls_down() {
+        let mut cpu = CPU::new();
+        cpu.gfx.set(5, 0, 1);
+        execute(&mut cpu, 0x00C3);
+        assert_eq!(cpu.gfx.get(5, 0), 0);
+        assert_eq!(cpu.gfx.get(5, 3), 1);
+    }
+
+    #[test]
+    fn op_00fb_and_00fc_scroll_sideways() {
+        let mut cpu = CPU::new();
+        cpu.gfx.set(10, 2, 1);
+        execute(&mut cpu, 0x00FB);
+        assert_eq!(cpu.gfx.get(14, 2), 1);
+        assert_eq!(cpu.gfx.get(10, 2), 0);
+        execute(&mut cpu, 0x00FC);
+        execute(&mut cpu, 0x00FC);
+        assert_eq!(cpu.gfx.get(6, 2), 1);
+        assert_eq!(cpu.gfx.get(14, 2), 0);
+    }
+
+    #[test]
+    fn op_00fd_halts() {
+        let mut cpu = CPU::new();
+        execute(&mut cpu, 0x00FD);
+        let pc = cpu.pc;
+        cpu.cycle([false; 16]).unwrap();
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, pc);
+    }
+
+    #[test]
+    fn dxyn_draws_and_reports_collision() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory[0x300] = 0b1000_0001;
+        cpu.v[0] = 2;
+        cpu.v[1] = 3;
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(2, 3), 1);
+        assert_eq!(cpu.gfx.get(9, 3), 1);
+        assert_eq!(cpu.v[0xF], 0);
+
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(2, 3), 0);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn dxy0_draws_16x16_sprite_in_hires() {
+        let mut cpu = CPU::new();
+        cpu.resolution = Resolution::High;
+        cpu.i = 0x300;
+        for byte in cpu.memory[0x300..0x320].iter_mut() {
+            *byte = 0xFF;
+        }
+        cpu.v[0] = 100;
+        cpu.v[1] = 40;
+        execute(&mut cpu, 0xD010);
+        assert_eq!(cpu.gfx.get(100, 40), 1);
+        assert_eq!(cpu.gfx.get(115, 55), 1);
+        assert_eq!(cpu.gfx.get(100, 56), 0);
+        assert_eq!(cpu.gfx.get(116, 40), 0);
+    }
+
+    #[test]
+    fn fx30_points_i_at_big_font_glyph() {
+        let mut cpu = CPU::new();
+        cpu.v[2] = 3;
+        execute(&mut cpu, 0xF230);
+        let i = cpu.i as usize;
+        assert_eq!(&cpu.memory[i..i + 10], &font::BIG_FONT_SET[30..40]);
+    }
+
+    #[test]
+    fn fx75_and_fx85_round_trip_rpl_flags() {
+        let mut cpu = CPU::new();
+        cpu.v[..3].copy_from_slice(&[7, 8, 9]);
+        execute(&mut cpu, 0xF275);
+        assert_eq!(&cpu.rpl[..3], &[7, 8, 9]);
+
+        cpu.v = [0; 16];
+        execute(&mut cpu, 0xF285);
+        assert_eq!(&cpu.v[..3], &[7, 8, 9]);
+    }
+
+    #[test]
+    fn f000_loads_long_address() {
+        let mut cpu = CPU::new();
+        cpu.memory[0x202] = 0xAB;
+        cpu.memory[0x203] = 0xCD;
+        execute(&mut cpu, 0xF000);
+        assert_eq!(cpu.i, 0xABCD);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn skips_step_over_long_load() {
+        let mut cpu = CPU::new();
+        cpu.memory[0x202] = 0xF0;
+        cpu.memory[0x203] = 0x00;
+        execute(&mut cpu, 0x3000);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn op_5xy2_and_5xy3_save_and_load_register_ranges() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x600;
+        cpu.v[2..5].copy_from_slice(&[1, 2, 3]);
+        execute(&mut cpu, 0x5422);
+        assert_eq!(&cpu.memory[0x600..0x603], &[3, 2, 1]);
+
+        execute(&mut cpu, 0x5673);
+        assert_eq!(&cpu.v[6..8], &[3, 2]);
+        assert_eq!(cpu.i, 0x600);
+    }
+
+    #[test]
+    fn op_00dn_scrolls_up() {
+        let mut cpu = CPU::new();
+        cpu.gfx.set(1, 5, 1);
+        execute(&mut cpu, 0x00D2);
+        assert_eq!(cpu.gfx.get(1, 3), 1);
+        assert_eq!(cpu.gfx.get(1, 5), 0);
+    }
+
+    #[test]
+    fn drawing_targets_selected_planes() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory[0x300] = 0x80;
+        cpu.memory[0x301] = 0x80;
+        execute(&mut cpu, 0xF201);
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(0, 0), 2);
+
+        execute(&mut cpu, 0xF301);
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(0, 0), 1);
+        assert_eq!(cpu.v[0xF], 1);
+
+        execute(&mut cpu, 0xF101);
+        execute(&mut cpu, 0x00E0);
+        assert_eq!(cpu.gfx.get(0, 0), 0);
+    }
+
+    #[test]
+    fn f002_and_fx3a_set_audio_pattern_and_pitch() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x700;
+        for (n, byte) in cpu.memory[0x700..0x710].iter_mut().enumerate() {
+            *byte = n as u8;
+        }
+        execute(&mut cpu, 0xF002);
+        assert_eq!(cpu.audio_pattern[15], 15);
+
+        cpu.v[1] = 120;
+        execute(&mut cpu, 0xF13A);
+        assert_eq!(cpu.pitch, 120);
+    }
+
+    #[test]
+    fn op_8xy4_sets_carry() {
+        let mut cpu = CPU::new();
+        cpu.v[1] = 0xF0;
+        cpu.v[2] = 0x20;
+        execute(&mut cpu, 0x8124);
+        assert_eq!(cpu.v[1], 0x10);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn op_8xy5_and_8xy7_set_vf_unless_they_borrow() {
+        let mut cpu = CPU::new();
+        cpu.v[1] = 0x20;
+        cpu.v[2] = 0x20;
+        execute(&mut cpu, 0x8125);
+        assert_eq!(cpu.v[1], 0);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.v[2] = 0x01;
+        execute(&mut cpu, 0x8127);
+        assert_eq!(cpu.v[1], 0x01);
+        assert_eq!(cpu.v[0xF], 1);
+
+        // The flag wins over the result when VF is the destination.
+        cpu.v[0xF] = 0x10;
+        cpu.v[3] = 0x20;
+        execute(&mut cpu, 0x8F35);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn shift_quirk_shifts_vy_into_vx() {
+        let mut cpu = CPU::new();
+        cpu.v[1] = 0x01;
+        cpu.v[2] = 0x81;
+        execute(&mut cpu, 0x812E);
+        assert_eq!(cpu.v[1], 0x02);
+        assert_eq!(cpu.v[0xF], 0);
+
+        cpu.quirks.shift_uses_vy = true;
+        execute(&mut cpu, 0x812E);
+        assert_eq!(cpu.v[1], 0x02);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn load_store_quirk_increments_i() {
+        let mut cpu = CPU::new();
+        cpu.quirks.load_store_increments_i = true;
+        cpu.i = 0x500;
+        execute(&mut cpu, 0xF255);
+        assert_eq!(cpu.i, 0x503);
+        execute(&mut cpu, 0xF165);
+        assert_eq!(cpu.i, 0x505);
+
+        cpu.quirks.load_store_increments_by_x = true;
+        execute(&mut cpu, 0xF255);
+        assert_eq!(cpu.i, 0x507);
+        execute(&mut cpu, 0xF065);
+        assert_eq!(cpu.i, 0x507);
+    }
+
+    #[test]
+    fn jump_quirk_uses_vx() {
+        let mut cpu = CPU::new();
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+        execute(&mut cpu, 0xB300);
+        assert_eq!(cpu.pc, 0x310);
+
+        let mut cpu = CPU::new();
+        cpu.quirks.jump_uses_vx = true;
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+        execute(&mut cpu, 0xB300);
+        assert_eq!(cpu.pc, 0x320);
+    }
+
+    #[test]
+    fn clip_quirk_stops_sprites_at_the_edge() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        cpu.memory[0x300] = 0xFF;
+        cpu.v[0] = 60;
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(2, 0), 1);
+
+        let mut cpu = CPU::new();
+        cpu.quirks.clip_sprites = true;
+        cpu.i = 0x300;
+        cpu.memory[0x300] = 0xFF;
+        cpu.v[0] = 60;
+        execute(&mut cpu, 0xD011);
+        assert_eq!(cpu.gfx.get(63, 0), 1);
+        assert_eq!(cpu.gfx.get(2, 0), 0);
+    }
+
+    #[test]
+    fn vf_reset_quirk_clears_vf_on_logic_ops() {
+        let mut cpu = CPU::new();
+        cpu.v[0xF] = 1;
+        execute(&mut cpu, 0x8121);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.quirks.vf_reset = true;
+        execute(&mut cpu, 0x8122);
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn display_wait_quirk_draws_once_per_frame() {
+        // Draw, add 1 to V0, jump back.
+        let program = [0xD0, 0x01, 0x70, 0x01, 0x12, 0x00];
+        let mut cpu = CPU::new();
+        cpu.load_bytes(&program).unwrap();
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!(cpu.v[0], 3);
+
+        let mut cpu = CPU::new();
+        cpu.quirks.display_wait = true;
+        cpu.load_bytes(&program).unwrap();
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!((cpu.v[0], cpu.pc), (0, 0x202));
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!((cpu.v[0], cpu.pc), (1, 0x202));
+    }
+
+    #[test]
+    fn frames_run_the_program_a_frame_at_a_time() {
+        let mut cpu = CPU::new();
+        // V0 = 10, DT = ST = V0, draw the A glyph at (V0, V1), then spin.
+        cpu.memory[0x200..0x20C].copy_from_slice(&[
+            0x60, 0x0A, 0xF0, 0x15, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x15, 0x12, 0x0A,
+        ]);
+        let frames: Vec<Frame> = cpu
+            .frames(|| Some([false; 16]))
+            .take(10)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(frames.len(), 10);
+        assert!(frames[0].draw && frames[1..].iter().all(|frame| !frame.draw));
+        assert_eq!(frames[0].gfx.get(10, 0), 1);
+        assert_eq!(frames[9].gfx, frames[0].gfx);
+        // The timers tick once per frame: ten frames run the sound out.
+        assert!(frames[..9].iter().all(|frame| frame.sound));
+        assert!(!frames[9].sound);
+        assert_eq!(cpu.delay_timer, 0);
+    }
+
+    #[test]
+    fn frames_end_after_an_error() {
+        let mut cpu = CPU::new();
+        cpu.cycles_per_frame = 2;
+        // Two adds, then an unknown opcode.
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x70, 0x01, 0x70, 0x01, 0xE0, 0x00]);
+        let mut frames = cpu.frames(|| Some([false; 16]));
+
+        assert!(frames.next().unwrap().is_ok());
+        assert!(matches!(
+            frames.next(),
+            Some(Err(Chip8Error::UnknownOpcode { pc: 0x204, .. }))
+        ));
+        assert!(frames.next().is_none());
+        assert_eq!(cpu.v[0], 2);
+    }
+
+    #[test]
+    fn unknown_opcode_is_an_error() {
+        let mut cpu = CPU::new();
+        cpu.memory[0x200] = 0xE0;
+        cpu.memory[0x201] = 0x00;
+        match cpu.cycle([false; 16]) {
+            Err(Chip8Error::UnknownOpcode { opcode, pc }) => {
+                assert_eq!(opcode, 0xE000);
+                assert_eq!(pc, 0x200);
+            }
+            other => panic!("expected UnknownOpcode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_depth_is_limited_by_the_stack() {
+        let mut cpu = CPU::new();
+        for _ in 0..16 {
+            execute(&mut cpu, 0x2200);
+        }
+        cpu.memory[0x200] = 0x22;
+        assert!(matches!(
+            cpu.cycle([false; 16]),
+            Err(Chip8Error::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn return_without_call_is_an_error() {
+        let mut cpu = CPU::new();
+        cpu.memory[0x200] = 0x00;
+        cpu.memory[0x201] = 0xEE;
+        assert!(matches!(
+            cpu.cycle([false; 16]),
+            Err(Chip8Error::StackUnderflow { .. })
+        ));
+    }
+
+    #[test]
+    fn memory_access_past_the_end_is_an_error() {
+        let mut cpu = CPU::new();
+        cpu.i = 0xFFFF;
+        cpu.memory[0x200] = 0xF3;
+        cpu.memory[0x201] = 0x55;
+        assert!(matches!(
+            cpu.cycle([false; 16]),
+            Err(Chip8Error::MemoryOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn loads_roms_from_bytes() {
+        let mut cpu = CPU::new();
+        cpu.load_bytes(&[0x12, 0x34]).unwrap();
+        assert_eq!(cpu.memory[0x200..0x202], [0x12, 0x34]);
+
+        cpu.start = 0x600;
+        cpu.memory_size = 0x1000;
+        cpu.load_bytes(&[0x56, 0x78]).unwrap();
+        assert_eq!(cpu.memory[0x600..0x602], [0x56, 0x78]);
+        assert!(matches!(
+            cpu.load_bytes(&[0; 0xA01]),
+            Err(Chip8Error::RomTooLarge { max: 0xA00, .. })
+        ));
+        cpu.start = 0x200;
+        cpu.memory_size = MEMORY_SIZE;
+
+        let rom = vec![0; MEMORY_SIZE - 0x1FF];
+        match cpu.load_bytes(&rom) {
+            Err(Chip8Error::RomTooLarge { size, max }) => {
+                assert_eq!((size, max), (MEMORY_SIZE - 0x1FF, MEMORY_SIZE - 0x200))
+            }
+            _ => panic!("expected RomTooLarge"),
+        }
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_roms() {
+        let mut cpu = CPU::new();
+        assert!(matches!(cpu.load_bytes(&[]), Err(Chip8Error::EmptyRom)));
+        assert!(matches!(
+            cpu.load_bytes(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"),
+            Err(Chip8Error::NotARom("a PNG image"))
+        ));
+        assert!(matches!(
+            cpu.load_bytes(b"; Pong\n\tCLS\n\tLD I, paddle\n"),
+            Err(Chip8Error::NotARom("a text file"))
+        ));
+        // Short programs can happen to be all printable bytes.
+        cpu.load_bytes(b"a1b2").unwrap();
+        assert_eq!(cpu.memory[0x200..0x204], *b"a1b2");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn dumps_and_restores_memory() {
+        let path = std::env::temp_dir().join(format!("chip8-dump-{}.bin", std::process::id()));
+        let mut cpu = CPU::new();
+        cpu.memory[0x300..0x303].copy_from_slice(&[1, 2, 3]);
+        cpu.dump_memory(0x300, 3, &path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3]);
+
+        assert_eq!(cpu.load_memory(0x400, &path).unwrap(), 3);
+        assert_eq!(cpu.memory[0x400..0x403], [1, 2, 3]);
+        assert!(cpu.load_memory(MEMORY_SIZE - 2, &path).is_err());
+        assert!(cpu.dump_memory(MEMORY_SIZE - 2, 3, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn huge_ranges_are_out_of_bounds() {
+        let cpu = CPU::new();
+        let err = cpu.mem_range(usize::MAX, 2).unwrap_err();
+        assert!(matches!(
+            err,
+            Chip8Error::MemoryOutOfBounds { addr, .. } if addr == usize::MAX - 1
+        ));
+        assert!(cpu.mem_range(2, usize::MAX).is_err());
+        assert!(cpu.mem_range(MEMORY_SIZE, 0).is_ok());
+    }
+}
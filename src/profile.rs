@@ -0,0 +1,200 @@
+// Opt-in profiler: counts executions and time spent per opcode and per
+// address. Like the tracer it lives on the CPU, so it sees every
+// instruction however the CPU is driven.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How many of the hottest addresses the report lists.
+const TOP_ADDRESSES: usize = 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl Stats {
+    fn add(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+// Cheap to clone; clones add to the same counts.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    // A copy of everything counted so far.
+    pub fn profile(&self) -> Profile {
+        self.profile
+            .lock()
+            .map(|profile| profile.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record(&self, pc: usize, opcode: u16, time: Duration) {
+        if let Ok(mut profile) = self.profile.lock() {
+            let key = opcode & mask(template(opcode));
+            profile.opcodes.entry(key).or_default().add(time);
+            profile.addresses.entry(pc).or_default().add(time);
+        }
+    }
+}
+
+// Opcodes are grouped by form, e.g. every 8XY4 counts together whatever X
+// and Y are; see `template`.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    opcodes: HashMap<u16, Stats>,
+    addresses: HashMap<usize, Stats>,
+}
+
+impl Profile {
+    // Opcode forms with their stats, most time first.
+    pub fn opcodes(&self) -> Vec<(String, Stats)> {
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .map(|(&key, &stats)| (name(key), stats))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(&b.0)));
+        opcodes
+    }
+
+    // Addresses with their stats, most time first.
+    pub fn addresses(&self) -> Vec<(usize, Stats)> {
+        let mut addresses: Vec<_> = self.addresses.iter().map(|(&a, &s)| (a, s)).collect();
+        addresses.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    fn total(&self) -> Duration {
+        self.opcodes.values().map(|stats| stats.time).sum()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64().max(f64::MIN_POSITIVE);
+        writeln!(f, "Opcode        Count          Time   Share")?;
+        for (name, stats) in self.opcodes() {
+            writeln!(
+                f,
+                "{:<6} {:>12} {:>13} {:>6.1}%",
+                name,
+                stats.count,
+                format!("{:.3?}", stats.time),
+                stats.time.as_secs_f64() * 100.0 / total
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Address       Count          Time   Share")?;
+        for (addr, stats) in self.addresses().into_iter().take(TOP_ADDRESSES) {
+            writeln!(
+                f,
+                "{:03X}    {:>12} {:>13} {:>6.1}%",
+                addr,
+                stats.count,
+                format!("{:.3?}", stats.time),
+                stats.time.as_secs_f64() * 100.0 / total
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The form of an opcode: letters are operands, '.' is a nibble that
+// selects the instruction and is shown as is.
+fn template(opcode: u16) -> &'static str {
+    match opcode >> 12 {
+        0x0 if opcode & 0xFFF0 == 0x00C0 => "00CN",
+        0x0 if opcode & 0xFFF0 == 0x00D0 => "00DN",
+        0x0 => "....",
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XNN",
+        0x4 => "4XNN",
+        0x5 => "5XY.",
+        0x6 => "6XNN",
+        0x7 => "7XNN",
+        0x8 => "8XY.",
+        0x9 => "9XY.",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXNN",
+        0xD => "DXYN",
+        0xE => "EX..",
+        _ if opcode == 0xF000 || opcode == 0xF002 => "....",
+        _ if opcode & 0x00FF == 0x01 => "FN01",
+        _ => "FX..",
+    }
+}
+
+// The bits of an opcode that identify its form.
+fn mask(template: &str) -> u16 {
+    template.chars().fold(0, |mask, c| {
+        let fixed = c == '.' || c.is_ascii_hexdigit();
+        mask << 4 | if fixed { 0xF } else { 0 }
+    })
+}
+
+fn name(key: u16) -> String {
+    template(key)
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            '.' => std::char::from_digit(u32::from(key >> (12 - 4 * i) & 0xF), 16)
+                .unwrap()
+                .to_ascii_uppercase(),
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CPU;
+
+    #[test]
+    fn opcodes_are_grouped_by_form() {
+        assert_eq!(name(0x8124 & mask(template(0x8124))), "8XY4");
+        assert_eq!(name(0x00C3 & mask(template(0x00C3))), "00CN");
+        assert_eq!(name(0x00E0 & mask(template(0x00E0))), "00E0");
+        assert_eq!(name(0xF30A & mask(template(0xF30A))), "FX0A");
+        assert_eq!(name(0xF201 & mask(template(0xF201))), "FN01");
+        assert_eq!(name(0xD125 & mask(template(0xD125))), "DXYN");
+        assert_eq!(name(0xE59E & mask(template(0xE59E))), "EX9E");
+    }
+
+    #[test]
+    fn counts_executions() {
+        let mut cpu = CPU::new();
+        let profiler = Profiler::new();
+        cpu.profiler = Some(profiler.clone());
+        // ADD V0, #01 / ADD V1, #01 / JP #200
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        for _ in 0..9 {
+            cpu.cycle([false; 16]).unwrap();
+        }
+
+        let profile = profiler.profile();
+        let opcodes = profile.opcodes();
+        let count = |name: &str| opcodes.iter().find(|(n, _)| n == name).unwrap().1.count;
+        assert_eq!(count("7XNN"), 6);
+        assert_eq!(count("1NNN"), 3);
+        let addresses = profile.addresses();
+        assert_eq!(addresses.len(), 3);
+        assert!(addresses.iter().all(|(_, stats)| stats.count == 3));
+        assert!(profile.to_string().contains("7XNN"));
+    }
+}
@@ -0,0 +1,196 @@
+// Metadata from the CHIP-8 Archive (github.com/JohnEarnest/chip8Archive),
+// whose programs.json describes every ROM in its roms/ folder by file name:
+//
+//   "br8kout": {
+//     "title": "Br8kout", "authors": ["SharpenedSpoon"], "platform": "chip8",
+//     "options": { "tickrate": 7, "shiftQuirks": false, "clipQuirks": true, ... },
+//     "keys": { "left": "7", "right": "9" }
+//   }
+//
+// The platform picks a quirk preset, which the Octo quirk options then
+// adjust, and the tickrate is instructions per frame. Other fields are
+// ignored.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::quirks::Quirks;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: String,
+    pub quirks: Quirks,
+    pub tickrate: Option<usize>,
+    // What the keypad keys do, e.g. ("left", "7"), as the archive gives them.
+    pub keys: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Options {
+    tickrate: Option<usize>,
+    shift_quirks: Option<bool>,
+    load_store_quirks: Option<bool>,
+    jump_quirks: Option<bool>,
+    clip_quirks: Option<bool>,
+    logic_quirks: Option<bool>,
+    v_blank_quirks: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    platform: String,
+    options: Option<Options>,
+    #[serde(default)]
+    keys: BTreeMap<String, Value>,
+}
+
+impl From<Record> for Program {
+    fn from(record: Record) -> Self {
+        let mut quirks = Quirks::preset(&record.platform).unwrap_or_default();
+        let mut tickrate = None;
+        if let Some(options) = record.options {
+            let overrides = [
+                (options.shift_quirks, &mut quirks.shift_uses_vy),
+                (
+                    options.load_store_quirks,
+                    &mut quirks.load_store_increments_i,
+                ),
+                (options.jump_quirks, &mut quirks.jump_uses_vx),
+                (options.clip_quirks, &mut quirks.clip_sprites),
+                (options.logic_quirks, &mut quirks.vf_reset),
+                (options.v_blank_quirks, &mut quirks.display_wait),
+            ];
+            for (value, quirk) in overrides {
+                if let Some(value) = value {
+                    *quirk = value;
+                }
+            }
+            tickrate = options.tickrate.filter(|&n| n > 0);
+        }
+        let keys = record
+            .keys
+            .into_iter()
+            .map(|(name, key)| match key {
+                Value::String(key) => (name, key),
+                key => (name, key.to_string()),
+            })
+            .collect();
+        Program {
+            title: record.title,
+            authors: record.authors,
+            platform: record.platform,
+            quirks,
+            tickrate,
+            keys,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Programs {
+    programs: BTreeMap<String, Program>,
+}
+
+impl Programs {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let records: BTreeMap<String, Record> =
+            serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let programs = records
+            .into_iter()
+            .map(|(name, record)| (name, record.into()))
+            .collect();
+        Ok(Programs { programs })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Programs::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The entry for a ROM file, by its name without the extension.
+    pub fn get(&self, rom: &Path) -> Option<&Program> {
+        let name = rom.file_stem()?.to_str()?;
+        self.programs.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+}
+
+// Where the archive's programs.json would be for a ROM: next to it, or one
+// folder up as in the archive's own layout.
+pub fn metadata_path(rom: &Path) -> Option<PathBuf> {
+    rom.ancestors()
+        .skip(1)
+        .take(2)
+        .map(|dir| dir.join("programs.json"))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS: &str = r##"{
+        "br8kout": {
+            "title": "Br8kout",
+            "authors": ["SharpenedSpoon"],
+            "platform": "chip8",
+            "desc": "Breakout.",
+            "options": { "tickrate": 7, "clipQuirks": false, "fillColor": "#FFCC00" },
+            "keys": { "left": "7", "right": 9 }
+        },
+        "superneatboy": {
+            "title": "Super Neat Boy",
+            "platform": "xochip"
+        }
+    }"##;
+
+    #[test]
+    fn reads_titles_quirks_and_keys() {
+        let programs = Programs::parse(PROGRAMS).unwrap();
+        assert_eq!(programs.len(), 2);
+
+        let br8kout = programs.get(Path::new("roms/br8kout.ch8")).unwrap();
+        assert_eq!(br8kout.title, "Br8kout");
+        assert_eq!(br8kout.authors, ["SharpenedSpoon"]);
+        assert_eq!(
+            br8kout.quirks,
+            Quirks {
+                clip_sprites: false,
+                ..Quirks::vip()
+            }
+        );
+        assert_eq!(br8kout.tickrate, Some(7));
+        assert_eq!(
+            br8kout.keys,
+            [
+                ("left".to_string(), "7".to_string()),
+                ("right".to_string(), "9".to_string())
+            ]
+        );
+
+        let neat = programs.get(Path::new("superneatboy.ch8")).unwrap();
+        assert_eq!((neat.quirks, neat.tickrate), (Quirks::xochip(), None));
+        assert!(programs.get(Path::new("pong.ch8")).is_none());
+
+        assert!(Programs::parse("[]").is_err());
+        assert!(Programs::parse(r#"{ "x": { "authors": [] } }"#).is_err());
+    }
+}
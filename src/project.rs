@@ -0,0 +1,192 @@
+// A debugging session kept between runs: the breakpoints, watchpoints,
+// memory view position and comments for one ROM, in <ROM>.debug next to
+// it. The window loads it when the ROM starts and writes it back on
+// quitting if anything changed, so a long reverse-engineering session
+// picks up where it stopped. One entry per line:
+//
+//   break 2A4
+//   break-op DXYN
+//   watch 3A0:w
+//   memory 300
+//   comment 2A4 Draws the paddle
+//
+// with addresses in hex and the forms `--break`, `--break-op` and `--watch`
+// take. Blank lines and lines starting with # are skipped, so the file can
+// be edited by hand.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::debugger::{Debugger, OpcodePattern};
+use crate::watch::Watchpoint;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugProject {
+    pub breakpoints: BTreeSet<usize>,
+    pub opcode_breakpoints: Vec<OpcodePattern>,
+    pub watchpoints: Vec<Watchpoint>,
+    // The first address the memory view shows; None while it follows PC.
+    pub memory_view: Option<usize>,
+    pub comments: BTreeMap<usize, String>,
+}
+
+impl DebugProject {
+    // `<rom>.debug`, next to the ROM.
+    pub fn path_for<P: AsRef<Path>>(rom: P) -> PathBuf {
+        rom.as_ref().with_extension("debug")
+    }
+
+    // What `debugger` is set up with, and where the memory view is.
+    pub fn from_debugger(debugger: &Debugger, memory_view: Option<usize>) -> Self {
+        DebugProject {
+            breakpoints: debugger.breakpoints().collect(),
+            opcode_breakpoints: debugger.opcode_breakpoints().cloned().collect(),
+            watchpoints: debugger.watchpoints().copied().collect(),
+            memory_view,
+            comments: debugger
+                .comments()
+                .map(|(addr, text)| (addr, text.to_string()))
+                .collect(),
+        }
+    }
+
+    // Adds the project's breakpoints, watchpoints and comments to
+    // `debugger`, keeping any it already has.
+    pub fn apply(&self, debugger: &mut Debugger) {
+        for &addr in &self.breakpoints {
+            debugger.add_breakpoint(addr);
+        }
+        for pattern in &self.opcode_breakpoints {
+            debugger.add_opcode_breakpoint(pattern.clone());
+        }
+        for &watch in &self.watchpoints {
+            debugger.add_watchpoint(watch);
+        }
+        for (&addr, text) in &self.comments {
+            debugger.set_comment(addr, text);
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut project = DebugProject::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {}", n + 1, what);
+            let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+            match kind {
+                "break" => {
+                    project
+                        .breakpoints
+                        .insert(address(rest).ok_or_else(|| bad("expected a hex address"))?);
+                }
+                "break-op" => project.opcode_breakpoints.push(
+                    OpcodePattern::parse(rest)
+                        .ok_or_else(|| bad("expected an opcode like DXYN"))?,
+                ),
+                "watch" => project.watchpoints.push(
+                    Watchpoint::parse(rest)
+                        .ok_or_else(|| bad("expected an address or register like 3A0 or VF:w"))?,
+                ),
+                "memory" => {
+                    project.memory_view =
+                        Some(address(rest).ok_or_else(|| bad("expected a hex address"))?);
+                }
+                "comment" => {
+                    let (addr, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let addr = address(addr).ok_or_else(|| bad("expected a hex address"))?;
+                    if !text.trim().is_empty() {
+                        project.comments.insert(addr, text.trim().to_string());
+                    }
+                }
+                _ => return Err(bad(&format!("unknown entry '{}'", kind))),
+            }
+        }
+        Ok(project)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        DebugProject::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+fn address(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).ok()
+}
+
+impl fmt::Display for DebugProject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for addr in &self.breakpoints {
+            writeln!(f, "break {:03X}", addr)?;
+        }
+        for pattern in &self.opcode_breakpoints {
+            writeln!(f, "break-op {}", pattern)?;
+        }
+        for watch in &self.watchpoints {
+            writeln!(f, "watch {}", watch)?;
+        }
+        if let Some(addr) = self.memory_view {
+            writeln!(f, "memory {:03X}", addr)?;
+        }
+        for (addr, text) in &self.comments {
+            writeln!(f, "comment {:03X} {}", addr, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x2A4);
+        debugger.add_opcode_breakpoint(OpcodePattern::parse("DXYN").unwrap());
+        debugger.add_watchpoint(Watchpoint::parse("3A0:w").unwrap());
+        debugger.add_watchpoint(Watchpoint::parse("VF").unwrap());
+        debugger.set_comment(0x2A4, "Draws the paddle");
+        let project = DebugProject::from_debugger(&debugger, Some(0x300));
+
+        let path = std::env::temp_dir().join(format!("chip8-{}.debug", std::process::id()));
+        project.save(&path).unwrap();
+        let loaded = DebugProject::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, project);
+
+        let mut restored = Debugger::new();
+        loaded.apply(&mut restored);
+        assert_eq!(DebugProject::from_debugger(&restored, Some(0x300)), project);
+        assert_eq!(restored.comment(0x2A4), Some("Draws the paddle"));
+    }
+
+    #[test]
+    fn reads_hand_written_files() {
+        let project = DebugProject::parse(
+            "# Pong\n\nbreak 0x2a4\nwatch v3:r\ncomment 200 Start\ncomment 204\n",
+        )
+        .unwrap();
+        assert!(project.breakpoints.contains(&0x2A4));
+        assert_eq!(project.watchpoints[0].to_string(), "V3:r");
+        assert_eq!(project.comments[&0x200], "Start");
+        assert!(!project.comments.contains_key(&0x204));
+        assert_eq!(project.memory_view, None);
+
+        let err = DebugProject::parse("break here").unwrap_err();
+        assert_eq!(err, "line 1: expected a hex address");
+        assert!(DebugProject::parse("label 200 start").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// Behaviors that differ between CHIP-8 interpreters. The default matches
+// what this emulator has always done; `vip` and `schip` reproduce the
+// original COSMAC VIP interpreter and Super-CHIP 1.1 respectively.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    // FX55/FX65 leave I pointing just past the last register transferred.
+    pub load_store_increments_i: bool,
+    // FX55/FX65 leave I on the last register transferred, X past where it
+    // was, as CHIP-48 and SCHIP 1.0 did. Wins over the above.
+    pub load_store_increments_by_x: bool,
+    // BNNN is read as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    // Sprites are cut off at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    // DXYN waits for the vertical blank, as on the VIP, so at most one
+    // sprite is drawn per frame.
+    pub display_wait: bool,
+    // 1260 as the program's first instruction switches to HiRes CHIP-8's
+    // 64x64 screen and starts at 2C0, instead of jumping to 260.
+    pub hires: bool,
+}
+
+impl Quirks {
+    pub fn vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            load_store_increments_by_x: false,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            vf_reset: true,
+            display_wait: true,
+            hires: false,
+        }
+    }
+
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            load_store_increments_by_x: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+            display_wait: false,
+            hires: false,
+        }
+    }
+
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            load_store_increments_by_x: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+            display_wait: false,
+            hires: false,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "vip" | "chip8" => Some(Quirks::vip()),
+            "schip" => Some(Quirks::schip()),
+            "xochip" | "xo-chip" => Some(Quirks::xochip()),
+            "hires" => Some(Quirks {
+                hires: true,
+                ..Quirks::vip()
+            }),
+            _ => None,
+        }
+    }
+
+    // Turns on one quirk by the name `Display` uses for it.
+    pub fn enable(&mut self, name: &str) -> bool {
+        let quirk = match name {
+            "shift" => &mut self.shift_uses_vy,
+            "load-store" => &mut self.load_store_increments_i,
+            "load-store-x" => &mut self.load_store_increments_by_x,
+            "jump" => &mut self.jump_uses_vx,
+            "clip" => &mut self.clip_sprites,
+            "vf-reset" => &mut self.vf_reset,
+            "display-wait" => &mut self.display_wait,
+            "hires" => &mut self.hires,
+            _ => return false,
+        };
+        *quirk = true;
+        true
+    }
+}
+
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (self.shift_uses_vy, "shift"),
+            (self.load_store_increments_i, "load-store"),
+            (self.load_store_increments_by_x, "load-store-x"),
+            (self.jump_uses_vx, "jump"),
+            (self.clip_sprites, "clip"),
+            (self.vf_reset, "vf-reset"),
+            (self.display_wait, "display-wait"),
+            (self.hires, "hires"),
+        ];
+        let enabled: Vec<&str> = names.iter().filter(|q| q.0).map(|q| q.1).collect();
+        if enabled.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(", "))
+        }
+    }
+}
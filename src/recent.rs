@@ -0,0 +1,112 @@
+// The ROMs loaded most recently, newest first, so they can be started again
+// without typing their paths. Kept in `recent.txt` in the config directory,
+// one absolute path per line.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+pub const MAX_RECENT: usize = 10;
+
+#[derive(Clone, Debug, Default)]
+pub struct RecentRoms {
+    roms: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    pub fn path() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("recent.txt"))
+    }
+
+    // The saved list; empty if there is none yet.
+    pub fn load() -> Self {
+        let text = RecentRoms::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        RecentRoms::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Self {
+        RecentRoms {
+            roms: text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(PathBuf::from)
+                .take(MAX_RECENT)
+                .collect(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = RecentRoms::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    // Moves `rom` to the front, dropping the oldest entry if the list is
+    // full. Relative paths are made absolute so they still work from
+    // another directory.
+    pub fn add<P: AsRef<Path>>(&mut self, rom: P) {
+        let rom = fs::canonicalize(&rom).unwrap_or_else(|_| rom.as_ref().to_path_buf());
+        self.roms.retain(|r| *r != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT);
+    }
+
+    // The `n`th most recent ROM, counting from 1.
+    pub fn get(&self, n: usize) -> Option<&Path> {
+        self.roms.get(n.checked_sub(1)?).map(PathBuf::as_path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.roms.iter().map(PathBuf::as_path)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+impl fmt::Display for RecentRoms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rom in &self.roms {
+            writeln!(f, "{}", rom.display())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_first_without_duplicates() {
+        let mut recent = RecentRoms::parse("/roms/a.ch8\n/roms/b.ch8\n");
+        recent.add("/roms/b.ch8");
+        recent.add("/roms/c.ch8");
+        assert_eq!(
+            recent.to_string(),
+            "/roms/c.ch8\n/roms/b.ch8\n/roms/a.ch8\n"
+        );
+        assert_eq!(recent.get(1), Some(Path::new("/roms/c.ch8")));
+        assert_eq!(recent.get(0), None);
+        assert_eq!(recent.get(4), None);
+    }
+
+    #[test]
+    fn keeps_at_most_max_recent() {
+        let mut recent = RecentRoms::default();
+        for i in 0..=MAX_RECENT {
+            recent.add(format!("/roms/{}.ch8", i));
+        }
+        assert_eq!(recent.iter().count(), MAX_RECENT);
+        let oldest = format!("/roms/{}.ch8", 1);
+        assert_eq!(recent.get(MAX_RECENT), Some(Path::new(&oldest)));
+    }
+}
@@ -0,0 +1,376 @@
+// A WebSocket debugging API, for a browser-based inspector or other tools
+// to drive the same `Debugger` as the hotkeys while the window keeps
+// drawing. The frontend calls `Server::poll` once a frame, before running
+// it. Any number of clients can connect.
+//
+// Requests are JSON text messages with a "cmd", and each gets one reply:
+//
+//     {"cmd": "state"}                        the state, as below
+//     {"cmd": "pause"} / {"cmd": "resume"}
+//     {"cmd": "step", "count": 3}             count defaults to 1
+//     {"cmd": "break", "addr": 516}           also "unbreak"
+//     {"cmd": "watch", "at": "VF:w"}          also "unwatch"; see --watch
+//     {"cmd": "read", "addr": 512, "len": 16} {"memory": [...]}
+//     {"cmd": "write", "addr": 512, "data": [...]}
+//     {"cmd": "dump", "addr": 512, "len": 16, "path": "sprites.bin"}
+//     {"cmd": "load", "addr": 512, "path": "sprites.bin"}
+//
+// Commands without a result reply {"ok": true}, and bad requests
+// {"error": "..."}. Whenever the program pauses or resumes, every client
+// is also sent {"event": "paused"} or {"event": "resumed"} with the state.
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tungstenite::{Message, WebSocket};
+
+use crate::debugger::Debugger;
+use crate::processor::{CPU, MEMORY_SIZE};
+use crate::watch::Watchpoint;
+
+// How long a connecting client gets to finish the handshake, during which
+// the frontend waits.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<WebSocket<TcpStream>>,
+    paused: bool,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            clients: Vec::new(),
+            paused: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Accepts new clients, answers their requests and tells them all if
+    // the program paused or resumed since the last poll.
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut CPU) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if let Some(socket) = handshake(stream) {
+                self.clients.push(socket);
+            }
+        }
+        self.clients
+            .retain_mut(|socket| serve(socket, debugger, cpu));
+
+        if debugger.is_paused() != self.paused {
+            self.paused = debugger.is_paused();
+            let event = if self.paused { "paused" } else { "resumed" };
+            let text = json!({ "event": event, "state": State::new(debugger, cpu) }).to_string();
+            self.clients.retain_mut(|socket| send(socket, text.clone()));
+        }
+    }
+}
+
+fn handshake(stream: TcpStream) -> Option<WebSocket<TcpStream>> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+    let socket = tungstenite::accept(stream).ok()?;
+    socket.get_ref().set_nonblocking(true).ok()?;
+    let _ = socket.get_ref().set_nodelay(true);
+    Some(socket)
+}
+
+// Answers every request waiting on `socket`. Returns false once it has
+// closed.
+fn serve(socket: &mut WebSocket<TcpStream>, debugger: &mut Debugger, cpu: &mut CPU) -> bool {
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if !send(socket, handle(&text, debugger, cpu)) {
+                    return false;
+                }
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+// Sends `text`, leaving it queued if the client isn't keeping up. Returns
+// false if the socket has closed.
+fn send(socket: &mut WebSocket<TcpStream>, text: String) -> bool {
+    match socket.send(Message::Text(text)) {
+        Ok(()) => true,
+        Err(tungstenite::Error::Io(e)) => e.kind() == io::ErrorKind::WouldBlock,
+        Err(_) => false,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+enum Request {
+    State,
+    Pause,
+    Resume,
+    Step {
+        #[serde(default = "one")]
+        count: usize,
+    },
+    Break {
+        addr: usize,
+    },
+    Unbreak {
+        addr: usize,
+    },
+    Watch {
+        at: String,
+    },
+    Unwatch {
+        at: String,
+    },
+    Read {
+        addr: usize,
+        len: usize,
+    },
+    Write {
+        addr: usize,
+        data: Vec<u8>,
+    },
+    Dump {
+        addr: usize,
+        len: usize,
+        path: String,
+    },
+    Load {
+        addr: usize,
+        path: String,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Serialize)]
+struct State {
+    paused: bool,
+    pc: usize,
+    i: u32,
+    v: [u8; 16],
+    sp: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    opcode: u16,
+    screen: (usize, usize),
+    // Innermost call first; `entry` is null where the debugger didn't see
+    // the call.
+    call_stack: Vec<Call>,
+    breakpoints: Vec<usize>,
+    opcode_breakpoints: Vec<String>,
+    watchpoints: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Call {
+    entry: Option<usize>,
+    return_to: usize,
+}
+
+impl State {
+    fn new(debugger: &Debugger, cpu: &CPU) -> Self {
+        let next = cpu.memory.get(cpu.pc..cpu.pc + 2).unwrap_or_default();
+        State {
+            paused: debugger.is_paused(),
+            pc: cpu.pc,
+            i: cpu.i,
+            v: cpu.v,
+            sp: cpu.sp,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+            opcode: next.iter().fold(0, |opcode, &b| opcode << 8 | b as u16),
+            screen: cpu.screen_size(),
+            call_stack: debugger
+                .call_stack(cpu)
+                .into_iter()
+                .map(|frame| Call {
+                    entry: frame.entry,
+                    return_to: frame.return_to,
+                })
+                .collect(),
+            breakpoints: debugger.breakpoints().collect(),
+            opcode_breakpoints: debugger
+                .opcode_breakpoints()
+                .map(ToString::to_string)
+                .collect(),
+            watchpoints: debugger.watchpoints().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+// The reply to one request.
+fn handle(text: &str, debugger: &mut Debugger, cpu: &mut CPU) -> String {
+    let request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return json!({ "error": e.to_string() }).to_string(),
+    };
+    let ok = json!({ "ok": true });
+    let reply = match request {
+        Request::State => json!(State::new(debugger, cpu)),
+        Request::Pause => {
+            debugger.pause();
+            ok
+        }
+        Request::Resume => {
+            debugger.resume();
+            ok
+        }
+        Request::Step { count } if debugger.is_paused() => {
+            for _ in 0..count {
+                debugger.step();
+            }
+            ok
+        }
+        Request::Step { .. } => json!({ "error": "not paused" }),
+        Request::Break { addr } => {
+            debugger.add_breakpoint(addr);
+            ok
+        }
+        Request::Unbreak { addr } => json!({ "ok": debugger.remove_breakpoint(addr) }),
+        Request::Watch { at } | Request::Unwatch { at } if Watchpoint::parse(&at).is_none() => {
+            json!({ "error": format!("bad watchpoint {:?}", at) })
+        }
+        Request::Watch { at } => {
+            debugger.add_watchpoint(Watchpoint::parse(&at).unwrap());
+            ok
+        }
+        Request::Unwatch { at } => {
+            json!({ "ok": debugger.remove_watchpoint(&Watchpoint::parse(&at).unwrap()) })
+        }
+        Request::Read { addr, len } if in_memory(addr, len) => {
+            json!({ "memory": &cpu.memory[addr..addr + len] })
+        }
+        Request::Write { addr, data } if in_memory(addr, data.len()) => {
+            cpu.memory[addr..addr + data.len()].copy_from_slice(&data);
+            ok
+        }
+        Request::Read { .. } | Request::Write { .. } => json!({ "error": "out of memory range" }),
+        Request::Dump { addr, len, path } => match cpu.dump_memory(addr, len, path) {
+            Ok(()) => ok,
+            Err(e) => json!({ "error": e.to_string() }),
+        },
+        Request::Load { addr, path } => match cpu.load_memory(addr, path) {
+            Ok(len) => json!({ "ok": true, "len": len }),
+            Err(e) => json!({ "error": e.to_string() }),
+        },
+    };
+    reply.to_string()
+}
+
+// Whether `len` bytes from `addr` are in memory, for addresses straight
+// off the network that may be anything up to usize::MAX.
+fn in_memory(addr: usize, len: usize) -> bool {
+    addr.checked_add(len).is_some_and(|end| end <= MEMORY_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn request(debugger: &mut Debugger, cpu: &mut CPU, text: &str) -> Value {
+        serde_json::from_str(&handle(text, debugger, cpu)).unwrap()
+    }
+
+    #[test]
+    fn reports_state_and_memory() {
+        let (mut debugger, mut cpu) = (Debugger::new(), CPU::new());
+        cpu.memory[0x200..0x202].copy_from_slice(&[0x60, 0x07]);
+        cpu.v[3] = 9;
+
+        let state = request(&mut debugger, &mut cpu, r#"{"cmd": "state"}"#);
+        assert_eq!(state["pc"], 0x200);
+        assert_eq!(state["v"][3], 9);
+        assert_eq!(state["opcode"], 0x6007);
+        assert_eq!(state["paused"], false);
+
+        let reply = request(
+            &mut debugger,
+            &mut cpu,
+            r#"{"cmd": "write", "addr": 768, "data": [190, 239]}"#,
+        );
+        assert_eq!(reply["ok"], true);
+        let reply = request(
+            &mut debugger,
+            &mut cpu,
+            r#"{"cmd": "read", "addr": 767, "len": 3}"#,
+        );
+        assert_eq!(reply["memory"], json!([0, 0xBE, 0xEF]));
+        let reply = request(
+            &mut debugger,
+            &mut cpu,
+            r#"{"cmd": "read", "addr": 65535, "len": 2}"#,
+        );
+        assert!(reply["error"].is_string());
+        // Wraps around to a small end address if added unchecked.
+        let huge = r#"{"cmd": "read", "addr": 18446744073709551615, "len": 1}"#;
+        assert!(request(&mut debugger, &mut cpu, huge)["error"].is_string());
+        let huge = r#"{"cmd": "write", "addr": 18446744073709551615, "data": [1, 2]}"#;
+        assert!(request(&mut debugger, &mut cpu, huge)["error"].is_string());
+        assert!(request(&mut debugger, &mut cpu, r#"{"cmd": "fly"}"#)["error"].is_string());
+    }
+
+    #[test]
+    fn steps_and_breaks() {
+        let (mut debugger, mut cpu) = (Debugger::new(), CPU::new());
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x60, 0x07, 0x70, 0x01, 0x12, 0x02]);
+
+        assert!(request(&mut debugger, &mut cpu, r#"{"cmd": "step"}"#)["error"].is_string());
+        request(&mut debugger, &mut cpu, r#"{"cmd": "pause"}"#);
+        request(&mut debugger, &mut cpu, r#"{"cmd": "step", "count": 2}"#);
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert_eq!((cpu.pc, cpu.v[0]), (0x204, 8));
+
+        request(&mut debugger, &mut cpu, r#"{"cmd": "break", "addr": 514}"#);
+        request(&mut debugger, &mut cpu, r#"{"cmd": "watch", "at": "V0:r"}"#);
+        let state = request(&mut debugger, &mut cpu, r#"{"cmd": "state"}"#);
+        assert_eq!(state["breakpoints"], json!([514]));
+        assert_eq!(state["watchpoints"], json!(["V0:r"]));
+
+        request(&mut debugger, &mut cpu, r#"{"cmd": "resume"}"#);
+        debugger.run_frame(&mut cpu, [false; 16]).unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn serves_over_websockets() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let (mut debugger, mut cpu) = (Debugger::new(), CPU::new());
+
+        let client = std::thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let url = format!("ws://{}/", addr);
+            let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+            socket
+                .send(Message::Text(r#"{"cmd": "pause"}"#.to_string()))
+                .unwrap();
+            let reply = socket.read().unwrap().into_text().unwrap();
+            let event = socket.read().unwrap().into_text().unwrap();
+            (reply, event)
+        });
+        while !client.is_finished() {
+            server.poll(&mut debugger, &mut cpu);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let (reply, event) = client.join().unwrap();
+        assert_eq!(reply, r#"{"ok":true}"#);
+        assert!(event.starts_with(r#"{"event":"paused""#));
+        assert!(debugger.is_paused());
+    }
+}
@@ -0,0 +1,154 @@
+// Input recordings: the keypad state for every frame plus the RNG state at
+// the start, which together make a run repeat exactly. Files use the same
+// magic-and-version header as save states.
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Chip8Error;
+use crate::frontend::Keypad;
+
+const MAGIC: &[u8; 4] = b"C8IN";
+pub const RECORDING_VERSION: u16 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u64,
+    // One bit per key, key 0 in the lowest bit.
+    pub frames: Vec<u16>,
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Self {
+        Recording {
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, keys: [bool; 16]) {
+        let mask = keys
+            .iter()
+            .enumerate()
+            .filter(|(_, &pressed)| pressed)
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        self.frames.push(mask);
+    }
+
+    // Feeds the recorded frames back as a keypad, ending when they run out.
+    pub fn playback(&self) -> Playback<'_> {
+        Playback {
+            frames: self.frames.iter(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Chip8Error> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&RECORDING_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, self)
+            .map_err(|e| Chip8Error::InvalidRecording(e.to_string()))?;
+        Ok(data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Chip8Error> {
+        if data.len() < 6 || &data[..4] != MAGIC {
+            return Err(Chip8Error::InvalidRecording(
+                "not an input recording".to_string(),
+            ));
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != RECORDING_VERSION {
+            return Err(Chip8Error::InvalidRecording(format!(
+                "version {} is not supported (expected {})",
+                version, RECORDING_VERSION
+            )));
+        }
+        bincode::deserialize(&data[6..]).map_err(|e| Chip8Error::InvalidRecording(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Chip8Error> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Chip8Error> {
+        Recording::from_bytes(&fs::read(path)?)
+    }
+}
+
+pub struct Playback<'a> {
+    frames: std::slice::Iter<'a, u16>,
+}
+
+impl Keypad for Playback<'_> {
+    fn keys(&mut self) -> Option<[bool; 16]> {
+        let mask = self.frames.next()?;
+        let mut keys = [false; 16];
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = mask & (1 << i) != 0;
+        }
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CPU;
+
+    fn keys(pressed: &[usize]) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for &i in pressed {
+            keys[i] = true;
+        }
+        keys
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let mut recording = Recording::new(7);
+        recording.push(keys(&[0, 0xF]));
+        recording.push(keys(&[]));
+
+        let bytes = recording.to_bytes().unwrap();
+        assert_eq!(Recording::from_bytes(&bytes).unwrap(), recording);
+        assert!(Recording::from_bytes(b"C8SS\x01\x00").is_err());
+    }
+
+    #[test]
+    fn playback_returns_the_recorded_keys() {
+        let mut recording = Recording::new(0);
+        recording.push(keys(&[5]));
+        recording.push(keys(&[1, 2]));
+
+        let mut playback = recording.playback();
+        assert_eq!(playback.keys(), Some(keys(&[5])));
+        assert_eq!(playback.keys(), Some(keys(&[1, 2])));
+        assert_eq!(playback.keys(), None);
+    }
+
+    #[test]
+    fn replays_are_identical() {
+        // Wait for a key, roll CXNN into V1, repeat.
+        let program = [0xF0, 0x0A, 0xC1, 0xFF, 0x12, 0x00];
+        let mut recording = Recording::new(1234);
+        for frame in 0..20 {
+            recording.push(keys(if frame % 3 == 0 { &[4] } else { &[] }));
+        }
+
+        let run = || {
+            let mut cpu = CPU::new();
+            cpu.memory[0x200..0x206].copy_from_slice(&program);
+            cpu.set_seed(recording.seed);
+            let mut playback = recording.playback();
+            let mut trace = Vec::new();
+            while let Some(keys) = playback.keys() {
+                cpu.run_frame(keys).unwrap();
+                trace.push((cpu.pc, cpu.v));
+            }
+            trace
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+use crate::megachip::{MEGA_HEIGHT, MEGA_WIDTH};
+use crate::processor::CPU;
+
+// A snapshot every tenth of a second and half a minute of history. With 64K
+// of RAM a snapshot is about 72K, so the full buffer stays around 20 MB.
+pub const DEFAULT_INTERVAL: usize = 6;
+pub const DEFAULT_CAPACITY: usize = 300;
+
+// MegaChip machines can have megabytes of RAM, so they keep as many
+// snapshots as fit in this instead.
+const MAX_BYTES: usize = 24 << 20;
+
+// Ring buffer of periodic CPU snapshots. Frontends call `record` after every
+// frame and `rewind` once per frame while the player holds the rewind key,
+// which plays history back at `interval` times normal speed.
+pub struct Rewind {
+    snapshots: VecDeque<CPU>,
+    capacity: usize,
+    interval: usize,
+    frames: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(DEFAULT_INTERVAL, DEFAULT_CAPACITY)
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+        }
+    }
+
+    pub fn record(&mut self, cpu: &CPU) {
+        self.frames += 1;
+        if self.frames < self.interval || self.capacity == 0 {
+            return;
+        }
+        self.frames = 0;
+        let limit = self.capacity.min(MAX_BYTES / snapshot_size(cpu)).max(1);
+        while self.snapshots.len() >= limit {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(cpu.clone());
+    }
+
+    // Restores the most recent snapshot and drops it from the history.
+    // Returns false once there is nothing left to rewind to.
+    pub fn rewind(&mut self, cpu: &mut CPU) -> bool {
+        match self.snapshots.pop_back() {
+            Some(snapshot) => {
+                // Cheats toggled since stay as they are.
+                let cheats = std::mem::take(&mut cpu.cheats);
+                *cpu = snapshot;
+                cpu.cheats = cheats;
+                cpu.draw_flag = true;
+                self.frames = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+// Roughly what a snapshot of `cpu` takes: its RAM, plus both MegaChip
+// screens on a MegaChip machine.
+fn snapshot_size(cpu: &CPU) -> usize {
+    let screens = match cpu.mega {
+        Some(_) => 2 * MEGA_WIDTH * MEGA_HEIGHT * 4,
+        None => 0,
+    };
+    cpu.memory.len() + screens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu_at(pc: usize) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.pc = pc;
+        cpu
+    }
+
+    #[test]
+    fn snapshots_every_interval() {
+        let mut rewind = Rewind::new(3, 10);
+        for frame in 0..9 {
+            rewind.record(&cpu_at(0x200 + frame * 2));
+        }
+        assert_eq!(rewind.len(), 3);
+
+        let mut cpu = CPU::new();
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(cpu.pc, 0x200 + 8 * 2);
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(cpu.pc, 0x200 + 5 * 2);
+    }
+
+    #[test]
+    fn drops_the_oldest_snapshot_when_full() {
+        let mut rewind = Rewind::new(1, 2);
+        for frame in 0..5 {
+            rewind.record(&cpu_at(0x200 + frame * 2));
+        }
+        let mut cpu = CPU::new();
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(cpu.pc, 0x208);
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(cpu.pc, 0x206);
+        assert!(!rewind.rewind(&mut cpu));
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn keeps_fewer_snapshots_of_large_machines() {
+        let mut rewind = Rewind::new(1, 300);
+        let mut cpu = CPU::new();
+        cpu.set_megachip();
+        cpu.memory.resize(8 << 20, 0);
+        for _ in 0..5 {
+            rewind.record(&cpu);
+        }
+        assert_eq!(rewind.len(), 2);
+    }
+}
@@ -0,0 +1,147 @@
+// Known ROMs by SHA-1, with the quirks they need to run correctly, so users
+// don't have to know which interpreter a game was written for. Entries use
+// the same hashes as the community chip-8-database, one per line:
+//
+//   <sha1>  <preset>[+quirk...]  <title>
+//
+// e.g. `0123...cdef  schip  Blinky` or `0123...cdef  vip+jump  Some Game`,
+// where the preset is any `Quirks::preset` name and the extra quirks use
+// the names `Quirks` displays. Blank lines and lines starting with # are
+// skipped.
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::quirks::Quirks;
+
+const BUILTIN: &str = include_str!("romdb.txt");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    // The quirks column as written, e.g. "schip" or "vip+jump".
+    pub platform: String,
+    pub quirks: Quirks,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RomDb {
+    entries: HashMap<String, Entry>,
+}
+
+impl RomDb {
+    pub fn builtin() -> Self {
+        RomDb::parse(BUILTIN).expect("the built-in ROM database is malformed")
+    }
+
+    pub fn parse<'a>(text: &'a str) -> Result<Self, String> {
+        let mut db = RomDb::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let field = |line: &'a str| {
+                let line = line.trim_start();
+                let end = line.find(char::is_whitespace).unwrap_or(line.len());
+                (&line[..end], line[end..].trim())
+            };
+            let (hash, rest) = field(line);
+            let (platform, title) = field(rest);
+            if platform.is_empty() || title.is_empty() {
+                return Err(format!("line {}: expected SHA1 QUIRKS TITLE", n + 1));
+            }
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("line {}: '{}' is not a SHA-1 hash", n + 1, hash));
+            }
+
+            let mut names = platform.split('+');
+            let preset = names.next().unwrap_or_default();
+            let mut quirks = Quirks::preset(preset)
+                .ok_or_else(|| format!("line {}: unknown quirk preset '{}'", n + 1, preset))?;
+            for name in names {
+                if !quirks.enable(name) {
+                    return Err(format!("line {}: unknown quirk '{}'", n + 1, name));
+                }
+            }
+
+            db.entries.insert(
+                hash.to_ascii_lowercase(),
+                Entry {
+                    title: title.to_string(),
+                    platform: platform.to_string(),
+                    quirks,
+                },
+            );
+        }
+        Ok(db)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        RomDb::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Adds `other`'s entries, replacing any for the same ROM.
+    pub fn extend(&mut self, other: RomDb) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&Entry> {
+        self.entries.get(&sha1(rom))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Lowercase hex, as the database stores it.
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_database_parses() {
+        RomDb::builtin();
+    }
+
+    #[test]
+    fn looks_up_roms_by_hash() {
+        let rom = [0x12, 0x00];
+        let text = format!(
+            "# comment\n\n{}  schip+vf-reset  Spin Forever\n",
+            sha1(&rom)
+        );
+        let db = RomDb::parse(&text).unwrap();
+
+        let entry = db.lookup(&rom).unwrap();
+        assert_eq!(entry.title, "Spin Forever");
+        assert_eq!(
+            entry.quirks,
+            Quirks {
+                vf_reset: true,
+                ..Quirks::schip()
+            }
+        );
+        assert!(db.lookup(&[0x00, 0xE0]).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let hash = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+        assert!(RomDb::parse("abc schip Title").is_err());
+        assert!(RomDb::parse(&format!("{} amiga Title", hash)).is_err());
+        assert!(RomDb::parse(&format!("{} vip+turbo Title", hash)).is_err());
+        assert!(RomDb::parse(hash).is_err());
+    }
+}
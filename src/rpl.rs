@@ -0,0 +1,84 @@
+// SCHIP's RPL user flags (FX75/FX85) kept in a file between runs, the way
+// the HP-48 kept them between programs, so games that store high scores
+// there keep them. The file is the 16 flags as raw bytes; it is only
+// written once a game actually changes them.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::processor::CPU;
+
+pub struct RplFile {
+    path: PathBuf,
+    // The flags as last read or written, to skip writes that change nothing.
+    saved: [u8; 16],
+}
+
+impl RplFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        RplFile {
+            path: path.into(),
+            saved: [0; 16],
+        }
+    }
+
+    // `<rom>.rpl`, next to the ROM.
+    pub fn for_rom<P: AsRef<Path>>(rom: P) -> Self {
+        RplFile::new(rom.as_ref().with_extension("rpl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Copies the stored flags into the CPU. A missing file means a game
+    // that has never saved anything, so the flags stay zero.
+    pub fn load(&mut self, cpu: &mut CPU) -> io::Result<()> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let len = data.len().min(cpu.rpl.len());
+        cpu.rpl = [0; 16];
+        cpu.rpl[..len].copy_from_slice(&data[..len]);
+        self.saved = cpu.rpl;
+        Ok(())
+    }
+
+    // Writes the CPU's flags if they changed; returns whether it wrote.
+    pub fn save(&mut self, cpu: &CPU) -> io::Result<bool> {
+        if cpu.rpl == self.saved {
+            return Ok(false);
+        }
+        fs::write(&self.path, cpu.rpl)?;
+        self.saved = cpu.rpl;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("chip8-rpl-{}.rpl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut cpu = CPU::new();
+        let mut file = RplFile::new(&path);
+        file.load(&mut cpu).unwrap();
+        assert_eq!(cpu.rpl, [0; 16]);
+        assert!(!file.save(&cpu).unwrap(), "unchanged flags were written");
+        assert!(!path.exists());
+
+        cpu.rpl[..3].copy_from_slice(&[7, 8, 9]);
+        assert!(file.save(&cpu).unwrap());
+
+        let mut cpu = CPU::new();
+        RplFile::new(&path).load(&mut cpu).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&cpu.rpl[..4], &[7, 8, 9, 0]);
+    }
+}
@@ -0,0 +1,168 @@
+// Save states are the whole CPU serialized with bincode behind a small
+// header, so a state written by an incompatible build is rejected instead
+// of being misread.
+use std::fs;
+use std::path::Path;
+
+use crate::error::Chip8Error;
+use crate::processor::{Resolution, CPU, MEMORY_SIZE};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+// Bump whenever a field is added to, removed from or reordered in `CPU`.
+pub const STATE_VERSION: u16 = 10;
+
+impl CPU {
+    pub fn save_state(&self) -> Result<Vec<u8>, Chip8Error> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, self)
+            .map_err(|e| Chip8Error::InvalidState(e.to_string()))?;
+        Ok(data)
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        if data.len() < 6 || &data[..4] != MAGIC {
+            return Err(Chip8Error::InvalidState("not a save state".to_string()));
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != STATE_VERSION {
+            return Err(Chip8Error::InvalidState(format!(
+                "version {} is not supported (expected {})",
+                version, STATE_VERSION
+            )));
+        }
+        let mut loaded: CPU = bincode::deserialize(&data[6..])
+            .map_err(|e| Chip8Error::InvalidState(e.to_string()))?;
+        loaded.check_state().map_err(Chip8Error::InvalidState)?;
+        // The tracer, profiler, cheats and script belong to the session,
+        // not the state.
+        loaded.tracer = self.tracer.take();
+        loaded.profiler = self.profiler.take();
+        loaded.cheats = std::mem::take(&mut self.cheats);
+        #[cfg(feature = "lua")]
+        {
+            loaded.script = self.script.take();
+        }
+        *self = loaded;
+        Ok(())
+    }
+
+    // What the interpreter takes for granted and bincode can't promise: a
+    // damaged or hand-made state would otherwise load and then panic on the
+    // next call, return or key wait.
+    fn check_state(&self) -> Result<(), String> {
+        let i_limit = if self.mega.is_some() {
+            1 << 24
+        } else {
+            1 << 16
+        };
+        if self.sp > self.stack.len() {
+            Err(format!("stack pointer {} is past the stack", self.sp))
+        } else if self.keypad_register >= self.v.len() {
+            Err(format!(
+                "key wait register {} is not a register",
+                self.keypad_register
+            ))
+        } else if self.memory.len() < MEMORY_SIZE {
+            Err(format!("only {} bytes of memory", self.memory.len()))
+        } else if self.pc > self.addressable().len() {
+            Err(format!("PC {:X} is past the end of memory", self.pc))
+        } else if self.i >= i_limit {
+            Err(format!("I {:X} is wider than the machine's", self.i))
+        } else if self.plane > 3 {
+            Err(format!(
+                "plane mask {:X} selects planes that don't exist",
+                self.plane
+            ))
+        } else if self.resolution == Resolution::Mega && self.mega.is_none() {
+            Err("MegaChip screen without a MegaChip machine".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn save_state_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Chip8Error> {
+        fs::write(path, self.save_state()?)?;
+        Ok(())
+    }
+
+    pub fn load_state_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Chip8Error> {
+        let data = fs::read(path)?;
+        self.load_state(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = CPU::default();
+        cpu.v[3] = 0x42;
+        cpu.i = 0x345;
+        cpu.pc = 0x246;
+        cpu.stack[0] = 0x202;
+        cpu.sp = 1;
+        cpu.delay_timer = 9;
+        cpu.gfx.set(7, 5, 1);
+        cpu.memory[0x300] = 0xAB;
+
+        let data = cpu.save_state().unwrap();
+        let mut restored = CPU::default();
+        restored.load_state(&data).unwrap();
+
+        assert_eq!(restored.v[3], 0x42);
+        assert_eq!(restored.i, 0x345);
+        assert_eq!(restored.pc, 0x246);
+        assert_eq!(restored.stack[0], 0x202);
+        assert_eq!(restored.sp, 1);
+        assert_eq!(restored.delay_timer, 9);
+        assert_eq!(restored.gfx.get(7, 5), 1);
+        assert_eq!(restored.memory[0x300], 0xAB);
+    }
+
+    #[test]
+    fn rejects_foreign_data() {
+        let mut cpu = CPU::default();
+        assert!(cpu.load_state(b"not a state").is_err());
+    }
+
+    // A state saved from a CPU changed by `change`.
+    fn state_with(change: impl FnOnce(&mut CPU)) -> Vec<u8> {
+        let mut cpu = CPU::new();
+        change(&mut cpu);
+        cpu.save_state().unwrap()
+    }
+
+    #[test]
+    fn rejects_states_that_break_the_interpreter() {
+        let data = state_with(|cpu| cpu.sp = 17);
+        let mut restored = CPU::default();
+        restored
+            .load_state(&state_with(|cpu| cpu.v[0] = 7))
+            .unwrap();
+        let err = restored.load_state(&data).unwrap_err();
+        assert!(matches!(err, Chip8Error::InvalidState(_)));
+        assert_eq!((restored.sp, restored.v[0]), (0, 7));
+
+        for data in [
+            state_with(|cpu| cpu.keypad_register = 16),
+            state_with(|cpu| cpu.memory.truncate(0x1000)),
+            state_with(|cpu| cpu.plane = 4),
+            state_with(|cpu| cpu.pc = MEMORY_SIZE + 2),
+        ]
+        .iter()
+        {
+            assert!(CPU::default().load_state(data).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let cpu = CPU::default();
+        let mut data = cpu.save_state().unwrap();
+        data[4] = data[4].wrapping_add(1);
+        assert!(CPU::default().load_state(&data).is_err());
+    }
+}
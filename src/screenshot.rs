@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{ImageResult, Rgba, RgbaImage};
+
+use crate::frame::Frame;
+use crate::palette::Palette;
+use crate::processor::CPU;
+
+impl CPU {
+    pub fn screenshot(&self, palette: &Palette, scale: u32) -> RgbaImage {
+        self.frame().screenshot(palette, scale)
+    }
+}
+
+impl Frame {
+    // The visible screen with each pixel scaled to a `scale` x `scale`
+    // block.
+    pub fn screenshot(&self, palette: &Palette, scale: u32) -> RgbaImage {
+        let scale = scale.max(1);
+        let (width, height) = self.screen_size();
+        RgbaImage::from_fn(width as u32 * scale, height as u32 * scale, |x, y| {
+            let [r, g, b] = self.color(palette, (x / scale) as usize, (y / scale) as usize);
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    // The image format follows the extension of `path`.
+    pub fn save_screenshot<P: AsRef<Path>>(
+        &self,
+        palette: &Palette,
+        scale: u32,
+        path: P,
+    ) -> ImageResult<()> {
+        self.screenshot(palette, scale).save(path)
+    }
+}
+
+// Names a capture after the ROM and the current UTC time, e.g.
+// `roms/pong-20240131-235959.png` for `roms/pong.ch8`, so repeated captures
+// sort in order and never overwrite each other.
+pub fn timestamped_path<P: AsRef<Path>>(rom: P, extension: &str) -> PathBuf {
+    let rom = rom.as_ref();
+    let stem = rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "chip8".to_string());
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    rom.with_file_name(format!("{}-{}.{}", stem, timestamp(secs), extension))
+}
+
+// Formats seconds since the Unix epoch as `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc_calendar_dates() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        assert_eq!(timestamp(951_782_400), "20000229-000000");
+        assert_eq!(timestamp(1_706_745_599), "20240131-235959");
+    }
+
+    #[test]
+    fn captures_are_named_after_the_rom() {
+        let path = timestamped_path("roms/pong.ch8", "png");
+        assert_eq!(path.parent(), Some(Path::new("roms")));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(
+            name.starts_with("pong-") && name.ends_with(".png"),
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn scales_the_visible_screen() {
+        let mut cpu = CPU::new();
+        cpu.gfx.set(2, 1, 1);
+        let image = cpu.frame().screenshot(&Palette::default(), 3);
+        assert_eq!(image.dimensions(), (64 * 3, 32 * 3));
+        assert_eq!(image.get_pixel(6, 3), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(5, 3), &Rgba([0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,279 @@
+// Lua scripts that run alongside a program, for bots, automated tests and
+// cheats. A script defines any of these global functions, which the
+// interpreter calls with the CPU as their first argument:
+//
+//     on_instruction(cpu, pc, opcode)   before each instruction
+//     on_memory(cpu, addr, access, pc)  before an instruction reads or
+//                                       writes addr; access is "read" or
+//                                       "write"
+//     on_frame(cpu, frame)              after each 60 Hz frame, counted
+//                                       from 1
+//
+// The CPU has fields pc, i, sp, dt, st and opcode (all but sp and opcode
+// writable) and the methods v(x), set_v(x, n), read(addr), write(addr, n)
+// and key(k), whether keypad key k is down. The globals press(k) and
+// release(k) hold keypad keys down on top of the player's until released.
+//
+// Like the tracer, a script belongs to the session rather than the machine
+// state: it survives resets and state loads, and an error in it stops the
+// program like a CPU fault.
+use std::fs;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use mlua::{Function, Lua, UserData, UserDataFields, UserDataMethods};
+
+use crate::disasm::Instruction;
+use crate::error::Chip8Error;
+use crate::processor::CPU;
+use crate::watch;
+
+#[derive(Clone)]
+pub struct Script {
+    lua: Arc<Mutex<Lua>>,
+    held: Arc<Mutex<[bool; 16]>>,
+    frames: Arc<Mutex<u64>>,
+}
+
+fn error(e: mlua::Error) -> Chip8Error {
+    Chip8Error::Script(e.to_string())
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, Chip8Error> {
+        let source = fs::read_to_string(path)?;
+        Script::from_source(&source, path)
+    }
+
+    // Runs `source`, which should define the callbacks. `name` is used in
+    // error messages.
+    pub fn from_source(source: &str, name: &str) -> Result<Self, Chip8Error> {
+        let lua = Lua::new();
+        let held = Arc::new(Mutex::new([false; 16]));
+        for (global, pressed) in [("press", true), ("release", false)] {
+            let held = held.clone();
+            let function = lua
+                .create_function(move |_, key: usize| {
+                    let mut held = held.lock().unwrap();
+                    match held.get_mut(key) {
+                        Some(slot) => *slot = pressed,
+                        None => return Err(mlua::Error::runtime(format!("no key {}", key))),
+                    }
+                    Ok(())
+                })
+                .map_err(error)?;
+            lua.globals().set(global, function).map_err(error)?;
+        }
+        lua.load(source).set_name(name).exec().map_err(error)?;
+        Ok(Script {
+            lua: Arc::new(Mutex::new(lua)),
+            held,
+            frames: Arc::new(Mutex::new(0)),
+        })
+    }
+
+    fn lua(&self) -> MutexGuard<'_, Lua> {
+        self.lua.lock().unwrap()
+    }
+
+    // The keys the script is holding down.
+    pub fn held(&self) -> [bool; 16] {
+        *self.held.lock().unwrap()
+    }
+
+    // Calls on_instruction and on_memory for the instruction at PC.
+    pub fn before_instruction(&self, cpu: &mut CPU) -> Result<(), Chip8Error> {
+        let lua = self.lua();
+        let globals = lua.globals();
+        let on_instruction: Option<Function> = globals.get("on_instruction").map_err(error)?;
+        let on_memory: Option<Function> = globals.get("on_memory").map_err(error)?;
+        if on_instruction.is_none() && on_memory.is_none() {
+            return Ok(());
+        }
+
+        let pc = cpu.pc;
+        let next = cpu.memory.get(pc..).unwrap_or_default();
+        let opcode = match *next {
+            [hi, lo, ..] => (hi as u16) << 8 | lo as u16,
+            _ => 0,
+        };
+        let accesses = match (&on_memory, Instruction::decode(next)) {
+            (Some(_), Some(instruction)) => watch::accesses(instruction, cpu),
+            _ => watch::Accesses::default(),
+        };
+        let memory = accesses
+            .memory_reads
+            .map(|addr| (addr, "read"))
+            .chain(accesses.memory_writes.map(|addr| (addr, "write")));
+
+        lua.scope(|scope| {
+            let cpu = scope.create_userdata_ref_mut(cpu)?;
+            if let Some(on_instruction) = &on_instruction {
+                on_instruction.call::<_, ()>((cpu.clone(), pc, opcode))?;
+            }
+            if let Some(on_memory) = &on_memory {
+                for (addr, access) in memory {
+                    on_memory.call::<_, ()>((cpu.clone(), addr, access, pc))?;
+                }
+            }
+            Ok(())
+        })
+        .map_err(error)
+    }
+
+    // Calls on_frame once a frame has run.
+    pub fn after_frame(&self, cpu: &mut CPU) -> Result<(), Chip8Error> {
+        let frame = {
+            let mut frames = self.frames.lock().unwrap();
+            *frames += 1;
+            *frames
+        };
+        let lua = self.lua();
+        let on_frame: Option<Function> = lua.globals().get("on_frame").map_err(error)?;
+        let on_frame = match on_frame {
+            Some(on_frame) => on_frame,
+            None => return Ok(()),
+        };
+        lua.scope(|scope| {
+            let cpu = scope.create_userdata_ref_mut(cpu)?;
+            on_frame.call::<_, ()>((cpu, frame))
+        })
+        .map_err(error)
+    }
+}
+
+fn out_of_range(what: &str, value: usize) -> mlua::Error {
+    mlua::Error::runtime(format!("{} {:X} is out of range", what, value))
+}
+
+impl UserData for CPU {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("pc", |_, cpu| Ok(cpu.pc));
+        fields.add_field_method_set("pc", |_, cpu, pc: usize| {
+            cpu.pc = pc;
+            Ok(())
+        });
+        fields.add_field_method_get("i", |_, cpu| Ok(cpu.i));
+        fields.add_field_method_set("i", |_, cpu, i: u32| {
+            cpu.i = i;
+            Ok(())
+        });
+        fields.add_field_method_get("dt", |_, cpu| Ok(cpu.delay_timer));
+        fields.add_field_method_set("dt", |_, cpu, dt: u8| {
+            cpu.delay_timer = dt;
+            Ok(())
+        });
+        fields.add_field_method_get("st", |_, cpu| Ok(cpu.sound_timer));
+        fields.add_field_method_set("st", |_, cpu, st: u8| {
+            cpu.sound_timer = st;
+            Ok(())
+        });
+        fields.add_field_method_get("sp", |_, cpu| Ok(cpu.sp));
+        fields.add_field_method_get("opcode", |_, cpu| Ok(cpu.opcode));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("v", |_, cpu, x: usize| {
+            cpu.v
+                .get(x)
+                .copied()
+                .ok_or_else(|| out_of_range("register", x))
+        });
+        methods.add_method_mut("set_v", |_, cpu, (x, value): (usize, u8)| {
+            *cpu.v
+                .get_mut(x)
+                .ok_or_else(|| out_of_range("register", x))? = value;
+            Ok(())
+        });
+        methods.add_method("read", |_, cpu, addr: usize| {
+            cpu.memory
+                .get(addr)
+                .copied()
+                .ok_or_else(|| out_of_range("address", addr))
+        });
+        methods.add_method_mut("write", |_, cpu, (addr, value): (usize, u8)| {
+            *cpu.memory
+                .get_mut(addr)
+                .ok_or_else(|| out_of_range("address", addr))? = value;
+            Ok(())
+        });
+        methods.add_method("key", |_, cpu, key: usize| {
+            cpu.keypad
+                .get(key)
+                .copied()
+                .ok_or_else(|| out_of_range("key", key))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu_with(script: &str, program: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_bytes(program).unwrap();
+        cpu.script = Some(Script::from_source(script, "test").unwrap());
+        cpu
+    }
+
+    #[test]
+    fn hooks_see_and_change_the_cpu() {
+        let script = r#"
+            seen = {}
+            function on_instruction(cpu, pc, opcode)
+                table.insert(seen, string.format("%03X:%04X", pc, opcode))
+            end
+            function on_frame(cpu, frame)
+                cpu:set_v(1, frame)
+                cpu:write(0x300, cpu:v(0))
+            end
+        "#;
+        // V0 = 7, then loop.
+        let mut cpu = cpu_with(script, &[0x60, 0x07, 0x12, 0x02]);
+        cpu.cycles_per_frame = 2;
+        cpu.run_frame([false; 16]).unwrap();
+        assert_eq!((cpu.v[1], cpu.memory[0x300]), (1, 7));
+
+        let script = cpu.script.clone().unwrap();
+        let seen: Vec<String> = script.lua().globals().get("seen").unwrap();
+        assert_eq!(seen, ["200:6007", "202:1202"]);
+    }
+
+    #[test]
+    fn memory_hook_sees_stores() {
+        let script = r#"
+            writes = {}
+            function on_memory(cpu, addr, access, pc)
+                if access == "write" then table.insert(writes, addr) end
+            end
+        "#;
+        // I = 300, store V0-V1.
+        let mut cpu = cpu_with(script, &[0xA3, 0x00, 0xF1, 0x55]);
+        cpu.cycle([false; 16]).unwrap();
+        cpu.cycle([false; 16]).unwrap();
+        let script = cpu.script.clone().unwrap();
+        let writes: Vec<usize> = script.lua().globals().get("writes").unwrap();
+        assert_eq!(writes, [0x300, 0x301]);
+    }
+
+    #[test]
+    fn held_keys_reach_the_program() {
+        let script = "function on_instruction(cpu) press(5) end";
+        // Wait for a key into V0.
+        let mut cpu = cpu_with(script, &[0xF0, 0x0A, 0x12, 0x02]);
+        for _ in 0..3 {
+            cpu.cycle([false; 16]).unwrap();
+        }
+        assert_eq!(cpu.v[0], 5);
+    }
+
+    #[test]
+    fn script_errors_stop_the_program() {
+        let mut cpu = cpu_with("function on_instruction(cpu) cpu:v(16) end", &[0x00, 0xE0]);
+        match cpu.cycle([false; 16]) {
+            Err(Chip8Error::Script(message)) => assert!(message.contains("out of range")),
+            _ => panic!("expected a script error"),
+        }
+        assert!(Script::from_source("this is not lua", "bad").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+// Emulator-wide preferences from settings.txt in the config directory, in
+// the same NAME = VALUE form as tone.txt:
+//
+//   resume = yes   # or no
+//   speed = 12     # instructions per frame
+//   scale = 16     # window size, as with --scale
+//   crt = yes      # or no
+//
+// `resume` saves the state when quitting a game and loads it when the same
+// ROM is started again; restarting the game goes back to the beginning.
+// Saved states are kept in the config directory's `resume` folder, named
+// by the ROM's SHA-1, so a game resumes wherever it is started from.
+//
+// The others are where the command line starts from, and the window
+// writes them back when they are changed while playing; see
+// `tone::save_setting`.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::romdb;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub resume: bool,
+    // None for the defaults.
+    pub speed: Option<usize>,
+    pub scale: Option<u32>,
+    pub crt: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            resume: true,
+            speed: None,
+            scale: None,
+            crt: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn path() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("settings.txt"))
+    }
+
+    // The saved settings, or the defaults if there are none yet.
+    pub fn load() -> Result<Self, String> {
+        let mut settings = Settings::default();
+        if let Some(path) = Settings::path().filter(|path| path.exists()) {
+            settings.apply_file(path)?;
+        }
+        Ok(settings)
+    }
+
+    // Applies the settings in `text`, leaving the others as they are.
+    pub fn apply(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {}", n + 1, what);
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| bad("expected NAME = VALUE"))?;
+            match name {
+                "resume" => {
+                    self.resume = match value {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(bad("resume must be yes or no")),
+                    };
+                }
+                "speed" => {
+                    self.speed = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&speed| speed > 0)
+                            .ok_or_else(|| bad("speed must be a positive number"))?,
+                    );
+                }
+                "scale" => {
+                    self.scale = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&scale| scale > 0)
+                            .ok_or_else(|| bad("scale must be a positive number"))?,
+                    );
+                }
+                "crt" => {
+                    self.crt = match value {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(bad("crt must be yes or no")),
+                    };
+                }
+                _ => return Err(bad(&format!("unknown setting '{}'", name))),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| self.apply(&text))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Where the state saved on quitting `rom` is kept.
+pub fn resume_path(rom: &[u8]) -> Option<PathBuf> {
+    config::config_dir().map(|dir| {
+        dir.join("resume")
+            .join(romdb::sha1(rom))
+            .with_extension("state")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings() {
+        let mut settings = Settings::default();
+        assert!(settings.resume);
+        settings
+            .apply("# comment\n\nresume = no  # start afresh\n")
+            .unwrap();
+        assert!(!settings.resume);
+
+        let err = settings.apply("resume = ask").unwrap_err();
+        assert_eq!(err, "line 1: resume must be yes or no");
+        assert!(settings.apply("resume").is_err());
+        assert!(settings.apply("volume = 10").is_err());
+    }
+
+    #[test]
+    fn leaves_unnamed_settings_alone() {
+        let mut settings = Settings::default();
+        settings.apply("speed = 12\ncrt = yes").unwrap();
+        settings.apply("scale = 16").unwrap();
+        assert_eq!(settings.speed, Some(12));
+        assert_eq!(settings.scale, Some(16));
+        assert!(settings.crt);
+        assert!(settings.apply("speed = 0").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+// A buzzer on the default output device through cpal, for frontends that
+// don't have SDL's audio: the winit window and the terminal. It plays the
+// same `tone::Voice` as the SDL buzzer does.
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, SampleFormat, Stream, SupportedBufferSize};
+
+use crate::frontend::Buzzer;
+use crate::tone::{Tone, Voice};
+
+pub struct Speaker {
+    // Kept for as long as sound should play; dropping it closes the device.
+    _stream: Stream,
+    voice: Arc<Mutex<Voice>>,
+    pattern: ([u8; 16], u8),
+    playing: bool,
+}
+
+impl Speaker {
+    pub fn new(tone: Tone) -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no output device")?;
+        let supported = device.default_output_config().map_err(|e| e.to_string())?;
+        if supported.sample_format() != SampleFormat::F32 {
+            return Err(format!(
+                "the output device wants {} samples",
+                supported.sample_format()
+            ));
+        }
+        let mut config = supported.config();
+        let channels = config.channels as usize;
+        // The asked-for buffer size, within what the device can do.
+        if let (Some(samples), SupportedBufferSize::Range { min, max }) =
+            (tone.buffer, supported.buffer_size())
+        {
+            config.buffer_size = BufferSize::Fixed((samples as u32).clamp(*min, *max));
+        }
+
+        let voice = Arc::new(Mutex::new(Voice::new(tone, config.sample_rate.0)));
+        let stream = {
+            let voice = voice.clone();
+            device
+                .build_output_stream(
+                    &config,
+                    move |out: &mut [f32], _| voice.lock().unwrap().fill(out, channels),
+                    |e| eprintln!("Audio error: {}", e),
+                    None,
+                )
+                .map_err(|e| e.to_string())?
+        };
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(Speaker {
+            _stream: stream,
+            voice,
+            pattern: ([0; 16], 64),
+            playing: false,
+        })
+    }
+}
+
+impl Buzzer for Speaker {
+    fn set_playing(&mut self, on: bool) {
+        if on {
+            self.voice.lock().unwrap().play_frame();
+        } else if self.playing {
+            self.voice.lock().unwrap().stop();
+        }
+        self.playing = on;
+    }
+
+    fn underruns(&self) -> u32 {
+        self.voice.lock().unwrap().underruns()
+    }
+
+    fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        if self.pattern != (pattern, pitch) {
+            self.pattern = (pattern, pitch);
+            self.voice.lock().unwrap().set_pattern(pattern, pitch);
+        }
+    }
+}
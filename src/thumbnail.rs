@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use chip8::frame::Frame;
+use chip8::palette::Palette;
+use chip8::processor::{Framebuffer, Resolution};
+
+// Roughly one second of unchanged output at 60 frames per second.
+const STABLE_FRAMES: u32 = 60;
+const THUMBNAIL_SCALE: u32 = 4;
+
+// Watches the frames after boot and saves the first one that stays unchanged
+// for about a second (typically the title screen) next to the ROM as a PNG.
+// Nothing is captured if the ROM already has a thumbnail.
+pub struct ThumbnailCapture {
+    path: PathBuf,
+    last: Option<Framebuffer>,
+    stable_frames: u32,
+    done: bool,
+}
+
+impl ThumbnailCapture {
+    pub fn new(rom_path: &Path) -> Self {
+        let path = rom_path.with_extension("png");
+        let done = path.exists();
+        ThumbnailCapture {
+            path,
+            last: None,
+            stable_frames: 0,
+            done,
+        }
+    }
+
+    pub fn observe(&mut self, frame: &Frame) {
+        if !self.settled(frame) {
+            return;
+        }
+        let image = frame.screenshot(&Palette::default(), THUMBNAIL_SCALE);
+        if let Err(e) = image.save(&self.path) {
+            eprintln!("Could not save thumbnail {}: {}", self.path.display(), e);
+        }
+    }
+
+    // Whether `frame` is the one to capture: true once, for the first
+    // screen with something on it to stay the same for STABLE_FRAMES.
+    fn settled(&mut self, frame: &Frame) -> bool {
+        if self.done {
+            return false;
+        }
+
+        let blank = frame.gfx.is_blank();
+        if blank || self.last != Some(frame.gfx) {
+            self.last = Some(frame.gfx);
+            self.stable_frames = 0;
+            return false;
+        }
+
+        self.stable_frames += 1;
+        self.done = self.stable_frames >= STABLE_FRAMES;
+        self.done
+    }
+}
+
+// The thumbnail saved for `rom`, turned back into a frame so the browser
+// can show it in the player's palette. None if there isn't one or it isn't
+// a screen this captures, e.g. a MegaChip one.
+pub fn load(rom: &Path) -> Option<Frame> {
+    let image = image::open(rom.with_extension("png")).ok()?.to_rgb8();
+    let size = (
+        (image.width() / THUMBNAIL_SCALE) as usize,
+        (image.height() / THUMBNAIL_SCALE) as usize,
+    );
+    let resolution = [Resolution::Low, Resolution::Tall, Resolution::High]
+        .iter()
+        .copied()
+        .find(|resolution| resolution.size() == size)?;
+    let palette = Palette::default();
+    let mut frame = Frame {
+        resolution,
+        ..Frame::default()
+    };
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let pixel = image.get_pixel(x as u32 * THUMBNAIL_SCALE, y as u32 * THUMBNAIL_SCALE);
+            let value = palette.colors.iter().position(|&color| color == pixel.0)?;
+            frame.gfx.set(x, y, value as u8);
+        }
+    }
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(pixels: &[(usize, usize)]) -> Frame {
+        let mut frame = Frame::default();
+        for &(x, y) in pixels {
+            frame.gfx.set(x, y, 1);
+        }
+        frame
+    }
+
+    fn capture() -> ThumbnailCapture {
+        ThumbnailCapture::new(Path::new("no-such-dir/game.ch8"))
+    }
+
+    #[test]
+    fn captures_a_screen_that_settles() {
+        let mut capture = capture();
+        let title = screen(&[(1, 1), (2, 2)]);
+        // The first sighting starts the count.
+        assert!((0..STABLE_FRAMES).all(|_| !capture.settled(&title)));
+        assert!(capture.settled(&title));
+        assert!(!capture.settled(&title));
+    }
+
+    #[test]
+    fn skips_blank_and_changing_screens() {
+        let mut capture = capture();
+        let blank = Frame::default();
+        assert!((0..STABLE_FRAMES * 2).all(|_| !capture.settled(&blank)));
+
+        let frames = [screen(&[(1, 1)]), screen(&[(2, 2)])];
+        assert!((0..STABLE_FRAMES as usize * 2).all(|i| !capture.settled(&frames[i % 2])));
+    }
+
+    #[test]
+    fn leaves_roms_that_have_a_thumbnail() {
+        let rom = std::env::temp_dir().join(format!("chip8-thumb-{}.ch8", std::process::id()));
+        let title = screen(&[(0, 0), (63, 31)]);
+        title
+            .save_screenshot(
+                &Palette::default(),
+                THUMBNAIL_SCALE,
+                rom.with_extension("png"),
+            )
+            .unwrap();
+
+        let mut capture = ThumbnailCapture::new(&rom);
+        assert!((0..=STABLE_FRAMES).all(|_| !capture.settled(&title)));
+
+        let loaded = load(&rom).unwrap();
+        std::fs::remove_file(rom.with_extension("png")).unwrap();
+        assert_eq!(loaded.resolution, Resolution::Low);
+        assert_eq!(loaded.gfx, title.gfx);
+    }
+}
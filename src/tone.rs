@@ -0,0 +1,410 @@
+// The buzzer's sound. Settings come from tone.txt in the config directory,
+// then <ROM>.tone next to the ROM for a single game, one setting per line:
+//
+//   frequency = 440     # Hz
+//   volume = 25         # percent of full scale
+//   waveform = triangle # square, triangle or sine
+//   muted = yes         # or no
+//   buffer = 512        # samples per audio device buffer
+//
+// Each file only changes the settings it names; `save_setting` changes one
+// in place, e.g. when the mute hotkey is pressed. Blank lines and anything
+// after a # are skipped.
+//
+// A smaller buffer starts and stops the sound sooner; a larger one keeps it
+// from crackling on a busy machine. Either way every frame the sound timer
+// runs plays exactly a frame's worth of samples, so a short beep is never
+// lost between two device callbacks.
+//
+// XO-CHIP programs can replace the tone with a 128-bit pattern of their own
+// (F002), played one bit per sample at 4000 Hz times 2^((pitch - 64) / 48)
+// (FX3A); see `Voice`.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub frequency: f32,
+    // 0 to 1.
+    pub volume: f32,
+    pub waveform: Waveform,
+    // Left to the frontend, which keeps the buzzer quiet while it's set.
+    pub muted: bool,
+    // Samples per device buffer, or None for the device's default.
+    pub buffer: Option<u16>,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            muted: false,
+            buffer: None,
+        }
+    }
+}
+
+impl Tone {
+    // Applies the settings in `text`, leaving the others as they are.
+    pub fn apply(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = |what: &str| format!("line {}: {}", n + 1, what);
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| bad("expected NAME = VALUE"))?;
+            match name {
+                "frequency" => {
+                    self.frequency = value
+                        .parse()
+                        .ok()
+                        .filter(|hz: &f32| (20.0..=20_000.0).contains(hz))
+                        .ok_or_else(|| bad("frequency must be 20 to 20000 Hz"))?;
+                }
+                "volume" => {
+                    let percent: f32 = value
+                        .parse()
+                        .ok()
+                        .filter(|percent| (0.0..=100.0).contains(percent))
+                        .ok_or_else(|| bad("volume must be 0 to 100"))?;
+                    self.volume = percent / 100.0;
+                }
+                "waveform" => {
+                    self.waveform = Waveform::parse(value)
+                        .ok_or_else(|| bad("waveform must be square, triangle or sine"))?;
+                }
+                "muted" => {
+                    self.muted = match value {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(bad("muted must be yes or no")),
+                    };
+                }
+                "buffer" => {
+                    self.buffer = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|samples| (16..=8192).contains(samples))
+                            .ok_or_else(|| bad("buffer must be 16 to 8192 samples"))?,
+                    );
+                }
+                _ => return Err(bad(&format!("unknown setting '{}'", name))),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| self.apply(&text))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The tone at `phase`, a fraction of a cycle from 0 to 1.
+    pub fn sample(&self, phase: f32) -> f32 {
+        let wave = match self.waveform {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+        };
+        wave * self.volume
+    }
+}
+
+// Sets `name` to `value` in the settings file at `path`, replacing the
+// line that sets it if there is one and keeping everything else, comments
+// included. The file is created if it doesn't exist.
+pub fn save_setting<P: AsRef<Path>>(path: P, name: &str, value: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let setting = format!("{} = {}", name, value);
+    let mut found = false;
+    let mut lines: Vec<&str> = text
+        .lines()
+        .map(|line| {
+            let key = line.split(['=', '#']).next().unwrap_or_default().trim();
+            if key == name && line.contains('=') && !found {
+                found = true;
+                setting.as_str()
+            } else {
+                line
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(&setting);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+// Samples of a 4000 Hz pattern at pitch 64, the default, per second.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+// Makes the buzzer's samples at a given output rate: the tone, or the
+// program's pattern once it has loaded one. The frontend calls `play_frame`
+// for each frame the sound timer runs and the device callback `fill`s its
+// buffer, which plays what has been queued and silence after it.
+#[derive(Clone, Debug)]
+pub struct Voice {
+    pub tone: Tone,
+    sample_rate: f32,
+    // Samples of sound still to play, and how many the device asks for at
+    // a time.
+    queued: f32,
+    chunk: f32,
+    // Whether the last frame sounded, and how often the device ran out of
+    // sound in the middle of a beep.
+    sounding: bool,
+    underruns: u32,
+    pattern: [u8; 16],
+    pitch: u8,
+    // Through the tone's cycle, from 0 to 1, or through the pattern's 128
+    // bits.
+    tone_phase: f32,
+    pattern_phase: f32,
+}
+
+impl Voice {
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Voice {
+            tone,
+            sample_rate: sample_rate as f32,
+            queued: 0.0,
+            chunk: 0.0,
+            sounding: false,
+            underruns: 0,
+            pattern: [0; 16],
+            pitch: 64,
+            tone_phase: 0.0,
+            pattern_phase: 0.0,
+        }
+    }
+
+    pub fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        self.pattern = pattern;
+        self.pitch = pitch;
+    }
+
+    // Queues one 60 Hz frame of sound. The queue holds at most one device
+    // buffer on top, so a program that beeps all the time doesn't drift
+    // further and further behind.
+    // A beep that has nothing left queued when its next frame comes has
+    // been broken up by silence: the device buffer is too small for the
+    // frames to keep up with it.
+    pub fn play_frame(&mut self) {
+        if self.sounding && self.queued < 1.0 {
+            self.underruns += 1;
+        }
+        self.sounding = true;
+        let frame = self.sample_rate / 60.0;
+        self.queued = (self.queued + frame).min(self.chunk + frame);
+    }
+
+    // Ends a beep, so the gap before the next one isn't taken for an
+    // underrun.
+    pub fn stop(&mut self) {
+        self.sounding = false;
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    // Fills a device buffer of `channels` interleaved channels.
+    pub fn fill(&mut self, out: &mut [f32], channels: usize) {
+        self.chunk = (out.len() / channels) as f32;
+        for frame in out.chunks_mut(channels) {
+            let sample = if self.queued >= 1.0 {
+                self.queued -= 1.0;
+                self.next_sample()
+            } else {
+                0.0
+            };
+            frame.fill(sample);
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        if self.pattern == [0; 16] {
+            let sample = self.tone.sample(self.tone_phase);
+            self.tone_phase = (self.tone_phase + self.tone.frequency / self.sample_rate) % 1.0;
+            return sample;
+        }
+        let bit = self.pattern_phase as usize;
+        let on = self.pattern[bit / 8] & 0x80 >> (bit % 8) != 0;
+        self.pattern_phase =
+            (self.pattern_phase + pattern_rate(self.pitch) / self.sample_rate) % 128.0;
+        if on {
+            self.tone.volume
+        } else {
+            -self.tone.volume
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_settings_override_earlier_ones() {
+        let mut tone = Tone::default();
+        tone.apply("# quieter\nvolume = 10\nwaveform = sine\n")
+            .unwrap();
+        tone.apply("frequency = 220 # an octave down\n").unwrap();
+        assert_eq!(
+            tone,
+            Tone {
+                frequency: 220.0,
+                volume: 0.1,
+                waveform: Waveform::Sine,
+                muted: false,
+                buffer: None,
+            }
+        );
+
+        assert!(tone.apply("volume = 150").is_err());
+        assert!(tone.apply("waveform = noise").is_err());
+        assert!(tone.apply("pitch = 3").is_err());
+        assert!(tone.apply("frequency").is_err());
+        assert!(tone.apply("muted = maybe").is_err());
+        assert!(tone.apply("buffer = 8").is_err());
+        tone.apply("buffer = 256").unwrap();
+        assert_eq!(tone.buffer, Some(256));
+    }
+
+    #[test]
+    fn saves_one_setting_in_place() {
+        let path = std::env::temp_dir().join(format!("chip8-tone-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        save_setting(&path, "muted", "yes").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "muted = yes\n");
+
+        fs::write(&path, "# mine\nvolume = 10\nmuted = yes # shh\n").unwrap();
+        save_setting(&path, "muted", "no").unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(text, "# mine\nvolume = 10\nmuted = no\n");
+
+        let mut tone = Tone::default();
+        tone.apply(&text).unwrap();
+        assert_eq!((tone.volume, tone.muted), (0.1, false));
+    }
+
+    #[test]
+    fn samples_each_waveform() {
+        let mut tone = Tone {
+            volume: 0.5,
+            ..Tone::default()
+        };
+        assert_eq!((tone.sample(0.25), tone.sample(0.75)), (0.5, -0.5));
+        tone.waveform = Waveform::Triangle;
+        assert_eq!(
+            (tone.sample(0.0), tone.sample(0.5), tone.sample(0.75)),
+            (-0.5, 0.5, 0.0)
+        );
+        tone.waveform = Waveform::Sine;
+        assert!((tone.sample(0.25) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn plays_patterns_at_the_pitch_rate() {
+        assert_eq!(pattern_rate(64), 4000.0);
+        assert!((pattern_rate(112) - 8000.0).abs() < 0.01);
+
+        let mut voice = Voice::new(Tone::default(), 8000);
+        // Until a pattern is loaded, the 440 Hz tone: ten samples high.
+        let high = (0..20).take_while(|_| voice.next_sample() > 0.0).count();
+        assert_eq!(high, 10);
+
+        // 1010... at half the output rate, so each bit lasts two samples.
+        voice.set_pattern([0xAA; 16], 64);
+        let samples: Vec<f32> = (0..6).map(|_| voice.next_sample()).collect();
+        assert_eq!(samples, [0.25, 0.25, -0.25, -0.25, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn plays_a_frame_of_sound_per_frame_queued() {
+        let mut voice = Voice::new(Tone::default(), 6000);
+        let mut out = [0.0; 1024];
+        voice.fill(&mut out, 2);
+        assert!(out.iter().all(|&s| s == 0.0));
+
+        // A one-frame beep lands in the next buffer whole, however long
+        // after the frame the device gets to it.
+        voice.play_frame();
+        voice.fill(&mut out, 2);
+        let sounding = out.chunks(2).filter(|f| f[0] != 0.0).count();
+        assert_eq!(sounding, 100);
+        assert!(out.chunks(2).all(|f| f[0] == f[1]));
+
+        // Beeping without a break queues no more than a buffer ahead.
+        for _ in 0..100 {
+            voice.play_frame();
+        }
+        assert_eq!(voice.queued, 612.0);
+    }
+
+    #[test]
+    fn counts_underruns_within_a_beep() {
+        let mut voice = Voice::new(Tone::default(), 6000);
+        let mut out = [0.0; 2048];
+        voice.fill(&mut out, 1);
+
+        // Gaps between beeps are silent anyway.
+        voice.play_frame();
+        voice.fill(&mut out, 1);
+        voice.stop();
+        voice.play_frame();
+        assert_eq!(voice.underruns(), 0);
+
+        // A buffer longer than a frame runs dry before the next one.
+        voice.fill(&mut out, 1);
+        voice.play_frame();
+        assert_eq!(voice.underruns(), 1);
+
+        // One that keeps up doesn't.
+        let mut out = [0.0; 64];
+        voice.fill(&mut out, 1);
+        voice.play_frame();
+        assert_eq!(voice.underruns(), 1);
+    }
+}
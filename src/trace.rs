@@ -0,0 +1,148 @@
+// Opt-in execution trace: one line per executed instruction with its
+// disassembly and whatever registers it changed. The tracer lives on the
+// CPU, so every way of running it (frames, the debugger, headless runs) is
+// traced alike.
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::disasm::Instruction;
+use crate::processor::CPU;
+
+// Cheap to clone; clones write to the same output. Lines are written as
+// they are produced, so a trace is complete up to the instruction that
+// faulted even if the process exits right after.
+#[derive(Clone)]
+pub struct Tracer {
+    out: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Tracer {
+            out: Arc::new(Mutex::new(out)),
+        }
+    }
+
+    pub fn stderr() -> Self {
+        Tracer::new(io::stderr())
+    }
+
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Tracer::new(LineWriter::new(File::create(path)?)))
+    }
+
+    pub(crate) fn record(&self, pc: usize, before: &Registers, cpu: &CPU, faulted: bool) {
+        let instruction = cpu
+            .memory
+            .get(pc..)
+            .and_then(Instruction::decode)
+            .map(|instruction| instruction.to_string())
+            .unwrap_or_default();
+        let mut line = format!("{:03X}  {:04X}  {:<20}", pc, cpu.opcode, instruction);
+
+        let after = Registers::of(cpu);
+        for (n, (old, new)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if old != new {
+                line += &format!(" V{:X}={:02X}", n, new);
+            }
+        }
+        if before.i != after.i {
+            line += &format!(" I={:03X}", after.i);
+        }
+        if before.sp != after.sp {
+            line += &format!(" SP={:X}", after.sp);
+        }
+        if before.delay_timer != after.delay_timer {
+            line += &format!(" DT={:02X}", after.delay_timer);
+        }
+        if before.sound_timer != after.sound_timer {
+            line += &format!(" ST={:02X}", after.sound_timer);
+        }
+        if faulted {
+            line += " FAULT";
+        }
+
+        // A trace that can't be written is not worth stopping the program
+        // for.
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+    }
+}
+
+// The registers an instruction can change, captured before it runs.
+pub(crate) struct Registers {
+    v: [u8; 16],
+    i: u32,
+    sp: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+}
+
+impl Registers {
+    pub(crate) fn of(cpu: &CPU) -> Self {
+        Registers {
+            v: cpu.v,
+            i: cpu.i,
+            sp: cpu.sp,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn traces_instructions_and_changes() {
+        let buffer = Buffer::default();
+        let mut cpu = CPU::new();
+        cpu.tracer = Some(Tracer::new(buffer.clone()));
+        // LD V3, #05 / LD I, #234 / JP #204
+        cpu.memory[0x200..0x206].copy_from_slice(&[0x63, 0x05, 0xA2, 0x34, 0x12, 0x04]);
+        for _ in 0..3 {
+            cpu.cycle([false; 16]).unwrap();
+        }
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("200  6305  LD V3, #05"),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].ends_with(" V3=05"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" I=234"), "{}", lines[1]);
+        assert!(lines[2].ends_with("JP #204"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn faults_are_traced() {
+        let buffer = Buffer::default();
+        let mut cpu = CPU::new();
+        cpu.tracer = Some(Tracer::new(buffer.clone()));
+        cpu.memory[0x200..0x202].copy_from_slice(&[0x00, 0xEE]);
+        assert!(cpu.cycle([false; 16]).is_err());
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(text.trim_end().ends_with("FAULT"), "{}", text);
+    }
+}
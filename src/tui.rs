@@ -0,0 +1,191 @@
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+
+use chip8::frame::Frame;
+use chip8::frontend::{Keypad, Screen};
+use chip8::palette::Palette;
+
+// Most terminals only report presses, so a key counts as held for this many
+// frames after its last press or auto-repeat. Terminals that support the
+// kitty keyboard protocol report releases and don't need it.
+const HOLD_FRAMES: u8 = 10;
+
+// Puts the terminal into raw mode on an alternate screen for as long as it
+// lives, and puts it back on drop, even when emulation stops with an error.
+pub struct Terminal {
+    enhanced: bool,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            queue!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        stdout.flush()?;
+        Ok(Terminal { enhanced })
+    }
+
+    pub fn screen(&self, palette: Palette) -> TerminalScreen {
+        TerminalScreen {
+            stdout: io::stdout(),
+            palette,
+        }
+    }
+
+    pub fn keys(&self) -> TerminalKeys {
+        TerminalKeys {
+            held: [0; 16],
+            enhanced: self.enhanced,
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced {
+            let _ = queue!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Draws two framebuffer rows per terminal line using upper half blocks: the
+// foreground colours the top pixel and the background the bottom one.
+pub struct TerminalScreen {
+    stdout: Stdout,
+    palette: Palette,
+}
+
+impl TerminalScreen {
+    fn color(&self, frame: &Frame, x: usize, y: usize) -> Color {
+        let [r, g, b] = frame.color(&self.palette, x, y);
+        Color::Rgb { r, g, b }
+    }
+
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        let (width, height) = frame.screen_size();
+        for (line, y) in (0..height).step_by(2).enumerate() {
+            queue!(self.stdout, cursor::MoveTo(0, line as u16))?;
+            let mut current = None;
+            for x in 0..width {
+                let colors = (self.color(frame, x, y), self.color(frame, x, y + 1));
+                if current != Some(colors) {
+                    queue!(
+                        self.stdout,
+                        SetForegroundColor(colors.0),
+                        SetBackgroundColor(colors.1)
+                    )?;
+                    current = Some(colors);
+                }
+                queue!(self.stdout, Print('▀'))?;
+            }
+        }
+        queue!(self.stdout, ResetColor)?;
+        self.stdout.flush()
+    }
+}
+
+impl Screen for TerminalScreen {
+    fn draw(&mut self, frame: &Frame) {
+        // There is nowhere sensible to report a broken terminal from here;
+        // the next key poll will end the session if stdin is gone too.
+        let _ = self.render(frame);
+    }
+}
+
+pub struct TerminalKeys {
+    held: [u8; 16],
+    enhanced: bool,
+}
+
+impl TerminalKeys {
+    // Returns false once the user asks to quit.
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        let quit = key.code == KeyCode::Esc
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+        if quit {
+            return false;
+        }
+
+        if let KeyCode::Char(c) = key.code {
+            if let Some(i) = keypad_index(c.to_ascii_lowercase()) {
+                self.held[i] = match key.kind {
+                    KeyEventKind::Release => 0,
+                    _ if self.enhanced => u8::MAX,
+                    _ => HOLD_FRAMES,
+                };
+            }
+        }
+        true
+    }
+}
+
+impl Keypad for TerminalKeys {
+    fn keys(&mut self) -> Option<[bool; 16]> {
+        for held in self.held.iter_mut() {
+            if !self.enhanced {
+                *held = held.saturating_sub(1);
+            }
+        }
+
+        while event::poll(Duration::from_secs(0)).ok()? {
+            if let Event::Key(key) = event::read().ok()? {
+                if !self.handle(key) {
+                    return None;
+                }
+            }
+        }
+
+        let mut keys = [false; 16];
+        for (key, &held) in keys.iter_mut().zip(self.held.iter()) {
+            *key = held > 0;
+        }
+        Some(keys)
+    }
+}
+
+// The same 1234/QWER/ASDF/ZXCV block as the SDL frontend's default keymap.
+fn keypad_index(c: char) -> Option<usize> {
+    let index = match c {
+        '1' => 0x1,
+        '2' => 0x2,
+        '3' => 0x3,
+        '4' => 0xC,
+        'q' => 0x4,
+        'w' => 0x5,
+        'e' => 0x6,
+        'r' => 0xD,
+        'a' => 0x7,
+        's' => 0x8,
+        'd' => 0x9,
+        'f' => 0xE,
+        'z' => 0xA,
+        'x' => 0x0,
+        'c' => 0xB,
+        'v' => 0xF,
+        _ => return None,
+    };
+    Some(index)
+}
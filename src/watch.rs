@@ -0,0 +1,239 @@
+// Data watchpoints for the debugger. Rather than instrumenting every memory
+// and register access in the interpreter, the debugger works out from the
+// decoded instruction and the CPU state what the next instruction is about
+// to read and write, and pauses before it if that touches a watched
+// location. Instruction fetches don't count as reads; use a breakpoint to
+// stop on code.
+use std::fmt;
+use std::ops::Range;
+
+use crate::disasm::Instruction;
+use crate::processor::{Resolution, CPU};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Memory(usize),
+    Register(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub location: Location,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+impl Watchpoint {
+    // Parses `<ADDR>` (hex, optionally 0x-prefixed) or `V<X>`, followed by
+    // `:r` or `:w` to watch only reads or writes, e.g. `0x3A0`, `VF:w`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (location, mode) = match text.split_once(':') {
+            Some((location, mode)) => (location, mode),
+            None => (text, "rw"),
+        };
+        let (on_read, on_write) = match mode.to_ascii_lowercase().as_str() {
+            "r" => (true, false),
+            "w" => (false, true),
+            "rw" => (true, true),
+            _ => return None,
+        };
+        let location = match location.strip_prefix(&['V', 'v'][..]) {
+            Some(x) if x.len() == 1 => Location::Register(u8::from_str_radix(x, 16).ok()?),
+            _ => {
+                let digits = location.trim_start_matches("0x").trim_start_matches("0X");
+                Location::Memory(usize::from_str_radix(digits, 16).ok()?)
+            }
+        };
+        Some(Watchpoint {
+            location,
+            on_read,
+            on_write,
+        })
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Location::Memory(addr) => write!(f, "{:03X}", addr)?,
+            Location::Register(x) => write!(f, "V{:X}", x)?,
+        }
+        match (self.on_read, self.on_write) {
+            (true, false) => write!(f, ":r"),
+            (false, true) => write!(f, ":w"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// What an instruction reads and writes, besides PC, I and the stack.
+// Registers are bit masks with bit n for Vn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accesses {
+    pub reads: u16,
+    pub writes: u16,
+    pub memory_reads: Range<usize>,
+    pub memory_writes: Range<usize>,
+}
+
+impl Accesses {
+    // Whether this touches `watch` in a way it watches. Returns "read" or
+    // "write" for the report, preferring write for read-modify-writes.
+    pub fn hits(&self, watch: &Watchpoint) -> Option<&'static str> {
+        let (read, write) = match watch.location {
+            Location::Register(x) => (self.reads & 1 << x != 0, self.writes & 1 << x != 0),
+            Location::Memory(addr) => (
+                self.memory_reads.contains(&addr),
+                self.memory_writes.contains(&addr),
+            ),
+        };
+        if write && watch.on_write {
+            Some("write")
+        } else if read && watch.on_read {
+            Some("read")
+        } else {
+            None
+        }
+    }
+}
+
+// Bit mask of V registers `from` to `to` inclusive, in either order.
+fn registers(from: u8, to: u8) -> u16 {
+    let (low, high) = (from.min(to), from.max(to));
+    (low..=high).fold(0, |mask, r| mask | 1 << r)
+}
+
+fn reg(x: u8) -> u16 {
+    1 << x
+}
+
+const VF: u16 = 1 << 0xF;
+
+// What `instruction` will access when `cpu` executes it.
+pub fn accesses(instruction: Instruction, cpu: &CPU) -> Accesses {
+    use Instruction::*;
+
+    let i = cpu.i as usize;
+    let quirks = cpu.quirks;
+    let mut a = Accesses::default();
+    match instruction {
+        SkipEqByte { x, .. } | SkipNeByte { x, .. } => a.reads = reg(x),
+        SkipEqReg { x, y } | SkipNeReg { x, y } => a.reads = reg(x) | reg(y),
+        SaveRange { x, y } => {
+            a.reads = registers(x, y);
+            a.memory_writes = i..i + x.abs_diff(y) as usize + 1;
+        }
+        LoadRange { x, y } => {
+            a.writes = registers(x, y);
+            a.memory_reads = i..i + x.abs_diff(y) as usize + 1;
+        }
+        LoadByte { x, .. } | Random { x, .. } | GetDelay(x) | WaitKey(x) => a.writes = reg(x),
+        AddByte { x, .. } => {
+            a.reads = reg(x);
+            a.writes = reg(x);
+        }
+        Move { x, y } => {
+            a.reads = reg(y);
+            a.writes = reg(x);
+        }
+        Or { x, y } | And { x, y } | Xor { x, y } => {
+            a.reads = reg(x) | reg(y);
+            a.writes = reg(x) | if quirks.vf_reset { VF } else { 0 };
+        }
+        Add { x, y } | Sub { x, y } | SubN { x, y } => {
+            a.reads = reg(x) | reg(y);
+            a.writes = reg(x) | VF;
+        }
+        Shr { x, y } | Shl { x, y } => {
+            a.reads = reg(if quirks.shift_uses_vy { y } else { x });
+            a.writes = reg(x) | VF;
+        }
+        JumpOffset(nnn) => {
+            let x = (nnn >> 8) as u8;
+            a.reads = reg(if quirks.jump_uses_vx { x } else { 0 });
+        }
+        Draw { x, y, n } => {
+            // MegaChip draws a SPRW x SPRH colour sprite, or a glyph from
+            // the fonts, with no planes.
+            let bytes = match (cpu.megachip(), n) {
+                (Some(mega), _) if i >= 0x100 => mega.sprite_len(),
+                (Some(_), n) => n as usize,
+                (None, 0) if cpu.resolution == Resolution::High => {
+                    32 * cpu.plane.count_ones() as usize
+                }
+                (None, 0) => 16 * cpu.plane.count_ones() as usize,
+                (None, n) => n as usize * cpu.plane.count_ones() as usize,
+            };
+            a.reads = reg(x) | reg(y);
+            a.writes = VF;
+            a.memory_reads = i..i + bytes;
+        }
+        LoadPalette(nn) => a.memory_reads = i..i + nn as usize * 4,
+        SkipKey(x) | SkipNotKey(x) | SkipKey2(x) | SkipNotKey2(x) | SetDelay(x) | SetSound(x)
+        | AddI(x) | Font(x) | BigFont(x) | Pitch(x) => a.reads = reg(x),
+        Audio => a.memory_reads = i..i + 16,
+        Bcd(x) => {
+            a.reads = reg(x);
+            a.memory_writes = i..i + 3;
+        }
+        Store(x) => {
+            a.reads = registers(0, x);
+            a.memory_writes = i..i + x as usize + 1;
+        }
+        Restore(x) => {
+            a.writes = registers(0, x);
+            a.memory_reads = i..i + x as usize + 1;
+        }
+        SaveFlags(x) => a.reads = registers(0, x),
+        LoadFlags(x) => a.writes = registers(0, x),
+        ScrollDown(_) | ScrollUp(_) | Cls | Ret | ScrollRight | ScrollLeft | Exit | Lores
+        | Hires | Jump(_) | Call(_) | LoadI(_) | LoadILong(_) | Plane(_) | Data(_) | Byte(_)
+        | MegaOff | MegaOn | LoadIHigh(_) | SpriteWidth(_) | SpriteHeight(_) | Alpha(_)
+        | DigiSound(_) | StopSound | BlendMode(_) | CollisionColor(_) | MegaScrollUp(_) => {}
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_locations_and_modes() {
+        let watch = Watchpoint::parse("0x3a0").unwrap();
+        assert_eq!(watch.location, Location::Memory(0x3A0));
+        assert!(watch.on_read && watch.on_write);
+        let watch = Watchpoint::parse("vf:w").unwrap();
+        assert_eq!(watch.location, Location::Register(0xF));
+        assert!(!watch.on_read && watch.on_write);
+        assert_eq!(watch.to_string(), "VF:w");
+        assert!(Watchpoint::parse("V10").is_none());
+        assert!(Watchpoint::parse("300:x").is_none());
+    }
+
+    #[test]
+    fn store_writes_memory_from_i() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x300;
+        let a = accesses(Instruction::Store(2), &cpu);
+        assert_eq!(a.reads, 0b111);
+        assert_eq!(a.memory_writes, 0x300..0x303);
+
+        let watch = Watchpoint::parse("302").unwrap();
+        assert_eq!(a.hits(&watch), Some("write"));
+        let watch = Watchpoint::parse("303").unwrap();
+        assert_eq!(a.hits(&watch), None);
+        let watch = Watchpoint::parse("V1:w").unwrap();
+        assert_eq!(a.hits(&watch), None);
+    }
+
+    #[test]
+    fn draw_reads_the_sprite_and_writes_vf() {
+        let mut cpu = CPU::new();
+        cpu.i = 0x400;
+        let a = accesses(Instruction::Draw { x: 1, y: 2, n: 5 }, &cpu);
+        assert_eq!(a.memory_reads, 0x400..0x405);
+        assert_eq!(a.reads, 0b110);
+        assert_eq!(a.hits(&Watchpoint::parse("VF").unwrap()), Some("write"));
+    }
+}
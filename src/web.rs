@@ -0,0 +1,139 @@
+// Browser frontend, built with
+//
+//     cargo rustc --lib --crate-type cdylib --release \
+//         --target wasm32-unknown-unknown --no-default-features --features wasm
+//     wasm-bindgen --target web --out-dir pkg \
+//         target/wasm32-unknown-unknown/release/chip8.wasm
+//
+// The crate is not a cdylib by default, since that would stop no_std users
+// from depending on it.
+//
+// The page owns the animation loop: it calls `frame()` from
+// requestAnimationFrame and forwards keyboard events, as web/index.html does.
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+
+use crate::emulator::Chip8;
+use crate::error::Chip8Error;
+use crate::palette::Palette;
+
+#[wasm_bindgen]
+pub struct WebChip8 {
+    chip8: Chip8,
+    palette: Palette,
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement) -> Result<WebChip8, JsValue> {
+        let context = canvas
+            .get_context("2d")?
+            .ok_or("canvas has no 2d context")?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        Ok(WebChip8 {
+            chip8: Chip8::new(),
+            palette: Palette::default(),
+            canvas,
+            context,
+        })
+    }
+
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.chip8.load_bytes(rom).map_err(error)
+    }
+
+    pub fn set_speed(&mut self, cycles_per_frame: usize) {
+        self.chip8.set_speed(cycles_per_frame);
+    }
+
+    // Takes a preset name as for `--palette`; returns false if unknown.
+    pub fn set_palette(&mut self, name: &str) -> bool {
+        match Palette::preset(name) {
+            Some(palette) => {
+                self.palette = palette;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Takes a KeyboardEvent.code. Returns false for keys that are not part
+    // of the keypad so the page can let the browser handle them.
+    pub fn key_down(&mut self, code: &str) -> bool {
+        self.set_key(code, true)
+    }
+
+    pub fn key_up(&mut self, code: &str) -> bool {
+        self.set_key(code, false)
+    }
+
+    // Runs one 60 Hz frame and redraws the canvas if needed. Returns whether
+    // the buzzer should be sounding.
+    pub fn frame(&mut self) -> Result<bool, JsValue> {
+        let keys = self.chip8.keys();
+        let frame = self.chip8.run_frame(keys).map_err(error)?;
+        if frame.draw {
+            let (width, height) = frame.screen_size();
+            let mut pixels = Vec::with_capacity(width * height * 4);
+            for y in 0..height {
+                for x in 0..width {
+                    pixels.extend_from_slice(&frame.color(&self.palette, x, y));
+                    pixels.push(0xFF);
+                }
+            }
+
+            // The canvas is sized in CHIP-8 pixels and scaled up by CSS.
+            self.canvas.set_width(width as u32);
+            self.canvas.set_height(height as u32);
+            let image = ImageData::new_with_u8_clamped_array_and_sh(
+                Clamped(&pixels),
+                width as u32,
+                height as u32,
+            )?;
+            self.context.put_image_data(&image, 0.0, 0.0)?;
+        }
+        Ok(frame.sound)
+    }
+
+    fn set_key(&mut self, code: &str, pressed: bool) -> bool {
+        match keypad_index(code) {
+            Some(i) => {
+                self.chip8.set_key(i, pressed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Same physical layout as the SDL frontend's default keymap.
+fn keypad_index(code: &str) -> Option<usize> {
+    let index = match code {
+        "Digit1" => 0x1,
+        "Digit2" => 0x2,
+        "Digit3" => 0x3,
+        "Digit4" => 0xC,
+        "KeyQ" => 0x4,
+        "KeyW" => 0x5,
+        "KeyE" => 0x6,
+        "KeyR" => 0xD,
+        "KeyA" => 0x7,
+        "KeyS" => 0x8,
+        "KeyD" => 0x9,
+        "KeyF" => 0xE,
+        "KeyZ" => 0xA,
+        "KeyX" => 0x0,
+        "KeyC" => 0xB,
+        "KeyV" => 0xF,
+        _ => return None,
+    };
+    Some(index)
+}
+
+fn error(e: Chip8Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
@@ -0,0 +1,165 @@
+use std::process;
+use std::time::Instant;
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::WindowBuilder;
+
+use chip8::frame::{Frame, FRAME_DURATION};
+use chip8::palette::Palette;
+use chip8::phosphor::Phosphor;
+use chip8::processor::Resolution;
+use chip8::{Buzzer, Chip8, Chip8Error};
+
+// Pure-Rust windowed frontend for builds without SDL2. The pixel buffer is
+// one pixel per screen pixel, resized to the frame's screen whenever the
+// program switches resolution, and scaled up to fill the window. Sound
+// needs the cpal feature; without it the buzzer is `()`.
+//
+// Returns when the window is closed, like the SDL frontend, so whatever
+// main does on exit still happens.
+pub fn run<B: Buzzer>(
+    chip8: &mut Chip8,
+    scale: u32,
+    palette: Palette,
+    phosphor: Option<u32>,
+    mut buzzer: B,
+) -> Result<(), Chip8Error> {
+    let mut event_loop = EventLoop::new();
+    let size = LogicalSize::new(64 * scale, 32 * scale);
+    let window = WindowBuilder::new()
+        .with_title("chip8")
+        .with_inner_size(size)
+        .with_min_inner_size(LogicalSize::new(64, 32))
+        .build(&event_loop)
+        .unwrap_or_else(|e| fail("Could not open a window", e));
+
+    let surface_size = window.inner_size();
+    let surface = SurfaceTexture::new(surface_size.width, surface_size.height, &window);
+    let mut buffer_size = Resolution::Low.size();
+    let (width, height) = buffer_size;
+    let mut pixels = Pixels::new(width as u32, height as u32, surface)
+        .unwrap_or_else(|e| fail("Could not create a rendering surface", e));
+
+    let mut phosphor = phosphor.map(Phosphor::new);
+    let mut frame: Option<Frame> = None;
+    let mut next_frame = Instant::now();
+    let mut result = Ok(());
+
+    event_loop.run_return(|event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) => {
+                if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                    fail("Could not resize the surface", e);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                if key == VirtualKeyCode::Escape {
+                    *control_flow = ControlFlow::Exit;
+                } else if key == VirtualKeyCode::Back && state == ElementState::Pressed {
+                    chip8.reset();
+                } else if let Some(i) = keypad_index(key) {
+                    chip8.set_key(i, state == ElementState::Pressed);
+                }
+            }
+            _ => {}
+        },
+        Event::MainEventsCleared => {
+            let now = Instant::now();
+            if now >= next_frame {
+                next_frame = now + FRAME_DURATION;
+                match chip8.run_frame(chip8.keys()).cloned() {
+                    Ok(f) => {
+                        buzzer.set_pattern(f.audio_pattern, f.pitch);
+                        buzzer.set_playing(f.sound);
+                        let fading = phosphor.as_mut().is_some_and(|p| p.update(&f));
+                        if f.draw || fading {
+                            frame = Some(f);
+                            window.request_redraw();
+                        }
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+            }
+            *control_flow = ControlFlow::WaitUntil(next_frame);
+        }
+        Event::RedrawRequested(_) => {
+            if let Some(frame) = &frame {
+                // The buffer is the size of the screen being shown, so a
+                // resolution switch resizes it.
+                let (width, height) = frame.screen_size();
+                if (width, height) != buffer_size {
+                    if let Err(e) = pixels.resize_buffer(width as u32, height as u32) {
+                        fail("Could not resize the screen", e);
+                    }
+                    buffer_size = (width, height);
+                }
+                draw(frame, &palette, phosphor.as_ref(), pixels.frame_mut());
+            }
+            if let Err(e) = pixels.render() {
+                fail("Could not render", e);
+            }
+        }
+        _ => {}
+    });
+    buzzer.set_playing(false);
+    result
+}
+
+// Fills `buffer`, which is the size of the frame's screen.
+fn draw(frame: &Frame, palette: &Palette, phosphor: Option<&Phosphor>, buffer: &mut [u8]) {
+    let (width, _) = frame.screen_size();
+    for (i, rgba) in buffer.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let [r, g, b] = match phosphor {
+            Some(phosphor) if frame.mega.is_none() => phosphor.color(palette, x, y),
+            _ => frame.color(palette, x, y),
+        };
+        rgba.copy_from_slice(&[r, g, b, 0xFF]);
+    }
+}
+
+fn fail(context: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", context, e);
+    process::exit(1);
+}
+
+// The same 1234/QWER/ASDF/ZXCV block as the other frontends.
+fn keypad_index(key: VirtualKeyCode) -> Option<usize> {
+    let index = match key {
+        VirtualKeyCode::Key1 => 0x1,
+        VirtualKeyCode::Key2 => 0x2,
+        VirtualKeyCode::Key3 => 0x3,
+        VirtualKeyCode::Key4 => 0xC,
+        VirtualKeyCode::Q => 0x4,
+        VirtualKeyCode::W => 0x5,
+        VirtualKeyCode::E => 0x6,
+        VirtualKeyCode::R => 0xD,
+        VirtualKeyCode::A => 0x7,
+        VirtualKeyCode::S => 0x8,
+        VirtualKeyCode::D => 0x9,
+        VirtualKeyCode::F => 0xE,
+        VirtualKeyCode::Z => 0xA,
+        VirtualKeyCode::X => 0x0,
+        VirtualKeyCode::C => 0xB,
+        VirtualKeyCode::V => 0xF,
+        _ => return None,
+    };
+    Some(index)
+}
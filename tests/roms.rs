@@ -0,0 +1,92 @@
+// Runs the ROMs listed in tests/roms/golden.txt headlessly and compares the
+// final screen against the stored hash. `.asm` entries are assembled first;
+// ROMs that aren't checked in (such as the Timendus and corax89 suites) are
+// skipped when missing, so they can be dropped into tests/roms locally.
+//
+// After an intended change in output, rerun with CHIP8_BLESS=1 to print
+// the new golden lines.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8::{asm, Quirks, CPU};
+
+struct Case {
+    file: String,
+    quirks: String,
+    cycles: usize,
+    hash: String,
+}
+
+fn cases(dir: &Path) -> Vec<Case> {
+    let golden = fs::read_to_string(dir.join("golden.txt")).unwrap();
+    golden
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 4, "bad golden line: {}", line);
+            Case {
+                file: fields[0].to_string(),
+                quirks: fields[1].to_string(),
+                cycles: fields[2].parse().unwrap(),
+                hash: fields[3].to_string(),
+            }
+        })
+        .collect()
+}
+
+fn rom(path: &Path) -> Vec<u8> {
+    if path.extension().is_some_and(|ext| ext == "asm") {
+        let source = fs::read_to_string(path).unwrap();
+        asm::assemble(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    } else {
+        fs::read(path).unwrap()
+    }
+}
+
+// FNV-1a, which unlike the std hashers is guaranteed never to change.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn test_roms_match_golden_screens() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for case in cases(&dir) {
+        let path = dir.join(&case.file);
+        if !path.exists() {
+            eprintln!("skipping {}: not present", case.file);
+            continue;
+        }
+
+        let rom = rom(&path);
+        let mut cpu = CPU::new();
+        cpu.set_seed(0);
+        cpu.quirks = Quirks::preset(&case.quirks).expect("unknown quirk preset");
+        cpu.load_bytes(&rom).unwrap();
+        if let Err(e) = cpu.run_headless(case.cycles, [false; 16]) {
+            failures.push(format!("{}: {}", case.file, e));
+            continue;
+        }
+
+        let screen = cpu.frame().to_string();
+        let hash = format!("{:016x}", fnv1a(screen.as_bytes()));
+        if bless {
+            println!("{} {} {} {}", case.file, case.quirks, case.cycles, hash);
+        } else if hash != case.hash {
+            failures.push(format!(
+                "{}: screen hash {} != {}\n{}",
+                case.file, hash, case.hash, screen
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,36 @@
+; DXYN: sprites crossing the right and bottom edges, and the collision
+; flag, shown as a digit for a fresh draw and for drawing over it.
+; Expected: a block at the top left, one across the right edge and one
+; across the bottom, either wrapped around or clipped depending on the
+; quirks, then the digits 0 1.
+        LD I, block
+        LD V0, 2
+        LD V1, 2
+        DRW V0, V1, 4       ; plain draw
+        LD V0, 62
+        LD V1, 10
+        DRW V0, V1, 4       ; straddles the right edge
+        LD V0, 20
+        LD V1, 30
+        DRW V0, V1, 4       ; runs off the bottom
+
+        LD V0, 40
+        LD V1, 2
+        DRW V0, V1, 4       ; no collision
+        LD V3, VF
+        DRW V0, V1, 4       ; erases itself: collision
+        LD V4, VF
+
+        LD V0, 40
+        LD V1, 10
+        LD F, V3
+        DRW V0, V1, 5
+        LD V0, 45
+        LD F, V4
+        DRW V0, V1, 5
+
+done:
+        JP done
+
+block:
+        DB #F0, #F0, #F0, #F0
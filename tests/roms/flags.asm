@@ -0,0 +1,53 @@
+; Leaves VF from each arithmetic instruction on screen as a digit.
+; Expected: 1 0 1 0 1 0 1 0 1 0
+        LD VA, 0
+        LD VB, 0
+
+        LD V0, #FF          ; 8XY4 carries
+        LD V1, 1
+        ADD V0, V1
+        CALL show
+        LD V0, 1            ; 8XY4 does not
+        ADD V0, V1
+        CALL show
+
+        LD V0, 5            ; 8XY5 with equal operands does not borrow
+        LD V1, 5
+        SUB V0, V1
+        CALL show
+        LD V0, 4            ; 8XY5 borrows
+        SUB V0, V1
+        CALL show
+
+        LD V0, 5            ; 8XY7 with equal operands does not borrow
+        SUBN V0, V1
+        CALL show
+        LD V0, 6            ; 8XY7 borrows
+        SUBN V0, V1
+        CALL show
+
+        LD V0, 3            ; 8XY6 shifts out a 1
+        SHR V0
+        CALL show
+        LD V0, #40          ; 8XYE shifts out a 0
+        SHL V0
+        CALL show
+
+        LD VF, #FF          ; the flag overwrites the result in VF
+        LD V1, 1
+        ADD VF, V1
+        CALL show
+        LD VF, 5
+        LD V1, 6
+        SUB VF, V1
+        CALL show
+
+done:
+        JP done
+
+show:
+        LD V2, VF
+        LD F, V2
+        DRW VA, VB, 5
+        ADD VA, 5
+        RET
@@ -0,0 +1,86 @@
+// Snapshot tests for drawing: each program in tests/snapshots is assembled,
+// run headlessly until it settles into its final loop, and the screen is
+// compared row by row with the stored <name>-<quirks>.txt, as printed by
+// `Frame`. Full test ROM runs are checked the same way by hash in roms.rs.
+//
+// To add or update snapshots after an intended change in output, run
+// `CHIP8_BLESS=1 cargo test --test snapshots` and review the new files.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8::{asm, Halt, Quirks, CPU};
+
+// Program and quirk preset.
+const CASES: [(&str, &str); 6] = [
+    ("sprites", "default"),
+    ("edges", "default"),
+    ("edges", "vip"),
+    ("hires", "schip"),
+    ("scroll", "schip"),
+    ("planes", "xochip"),
+];
+
+const MAX_CYCLES: usize = 100_000;
+
+fn render(source: &Path, quirks: &str) -> String {
+    let text = fs::read_to_string(source).unwrap();
+    let rom = asm::assemble(&text).unwrap_or_else(|e| panic!("{}: {}", source.display(), e));
+    let mut cpu = CPU::new();
+    cpu.set_seed(0);
+    cpu.quirks = Quirks::preset(quirks).expect("unknown quirk preset");
+    cpu.load_bytes(&rom).unwrap();
+    match cpu.run_headless(MAX_CYCLES, [false; 16]) {
+        Ok(Halt::Loop { .. }) => {}
+        other => panic!("{} did not finish drawing: {:?}", source.display(), other),
+    }
+    cpu.frame().to_string()
+}
+
+// The rows that differ, for a readable failure.
+fn diff(expected: &str, actual: &str) -> String {
+    let mut rows: Vec<String> = expected
+        .lines()
+        .zip(actual.lines())
+        .enumerate()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(|(y, (expected, actual))| format!("row {:2}: -{}\n        +{}", y, expected, actual))
+        .collect();
+    let (expected, actual) = (expected.lines().count(), actual.lines().count());
+    if expected != actual {
+        rows.push(format!("{} rows, expected {}", actual, expected));
+    }
+    rows.join("\n")
+}
+
+#[test]
+fn screens_match_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for (name, quirks) in CASES {
+        let screen = render(&dir.join(format!("{}.asm", name)), quirks);
+        let snapshot = dir.join(format!("{}-{}.txt", name, quirks));
+        if bless {
+            fs::write(&snapshot, &screen).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == screen => {}
+            Ok(expected) => failures.push(format!(
+                "{} with {} quirks:\n{}",
+                name,
+                quirks,
+                diff(&expected, &screen)
+            )),
+            Err(_) => failures.push(format!(
+                "{} is missing; the screen was:\n{}",
+                snapshot.display(),
+                screen
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
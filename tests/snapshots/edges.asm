@@ -0,0 +1,16 @@
+; Sprites at the edges: one over the bottom right corner, which wraps into
+; all four corners or is clipped depending on the quirks, and one whose
+; position is off the screen entirely, which wraps around either way.
+        LD I, arrow
+        LD V0, 60
+        LD V1, 28
+        DRW V0, V1, 8
+        LD V0, 90           ; 90 - 64 = 26
+        LD V1, 44           ; 44 - 32 = 12
+        DRW V0, V1, 8
+
+done:
+        JP done
+
+arrow:
+        DB #18, #3C, #7E, #FF, #18, #18, #18, #18
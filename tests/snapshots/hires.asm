@@ -0,0 +1,31 @@
+; SCHIP high resolution: the big font digits 0-9, a 16x16 sprite, then
+; the whole screen scrolled down 3 rows and right and left again, which
+; should leave it 3 rows lower.
+        HIGH
+        LD V0, 0            ; digit
+        LD V1, 2            ; x
+        LD V2, 2            ; y
+digits:
+        LD HF, V0
+        DRW V1, V2, 10
+        ADD V0, 1
+        ADD V1, 10
+        SE V0, 10
+        JP digits
+
+        LD I, ring
+        LD V1, 56
+        LD V2, 30
+        DRW V1, V2, 0
+
+        SCD 3
+        SCR
+        SCR
+        SCL
+
+done:
+        JP done
+
+ring:
+        DW #07E0, #1FF8, #3C3C, #700E, #6006, #E007, #C003, #C003
+        DW #C003, #C003, #E007, #6006, #700E, #3C3C, #1FF8, #07E0
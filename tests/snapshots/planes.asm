@@ -0,0 +1,25 @@
+; XO-CHIP bit planes: a block on plane 1, an overlapping one on plane 2,
+; and one drawn on both at once from two sprites' worth of data, showing
+; all four colours.
+        LD I, block
+        LD V0, 4
+        LD V1, 4
+        DRW V0, V1, 8
+        PLANE 2
+        LD V0, 8
+        LD V1, 8
+        DRW V0, V1, 8
+        PLANE 3
+        LD I, pair
+        LD V0, 24
+        LD V1, 4
+        DRW V0, V1, 4
+
+done:
+        JP done
+
+block:
+        DB #FF, #FF, #FF, #FF, #FF, #FF, #FF, #FF
+pair:
+        DB #F0, #F0, #F0, #F0   ; plane 1
+        DB #3C, #3C, #3C, #3C   ; plane 2
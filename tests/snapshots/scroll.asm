@@ -0,0 +1,21 @@
+; SCHIP scrolling in low resolution: digits scrolled down 4 rows, right 4
+; and then left 8 pixels, so the first digit loses its left edge for good
+; rather than wrapping.
+        LD V0, 0
+        LD V1, 2
+        LD V2, 2
+digits:
+        LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 6
+        SE V0, 4
+        JP digits
+
+        SCD 4
+        SCR
+        SCL
+        SCL
+
+done:
+        JP done
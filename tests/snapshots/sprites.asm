@@ -0,0 +1,31 @@
+; DXYN in low resolution: the font digits 0-F in two rows, then two
+; blocks drawn over each other, so their overlap is XORed away.
+        LD V0, 0            ; digit
+        LD V1, 1            ; x
+        LD V2, 1            ; y
+digits:
+        LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 5
+        SE V0, 8
+        JP next
+        LD V1, 1            ; second row from 8 on
+        LD V2, 8
+next:
+        SE V0, 16
+        JP digits
+
+        LD I, block
+        LD V1, 44
+        LD V2, 16
+        DRW V1, V2, 8
+        ADD V1, 4
+        ADD V2, 4
+        DRW V1, V2, 8
+
+done:
+        JP done
+
+block:
+        DB #FF, #81, #BD, #A5, #A5, #BD, #81, #FF